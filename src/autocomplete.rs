@@ -0,0 +1,605 @@
+//! Placeholder autocomplete: matching the token at the caret against the
+//! known placeholders, and cycling through the candidates on repeated Tab
+
+use crate::expand::{braced_token, needs_braces, ARGUMENT_TOKENS};
+
+/// Find the autocomplete suggestion for the token at the caret (`cursor`, a
+/// byte offset into `text`): the first of `find_autocomplete_all`.
+pub fn find_autocomplete(
+    text: &str,
+    cursor: usize,
+    placeholders: &[String],
+    prefix: char,
+    fuzzy: bool,
+) -> (String, bool) {
+    match find_autocomplete_all(text, cursor, placeholders, prefix, fuzzy).first() {
+        Some(best) => {
+            let braced = ActiveToken::at(text, cursor, prefix)
+                .is_some_and(|token| token.typed(text, prefix).starts_with('{'));
+            if braced || needs_braces(best) {
+                (braced_token(best, prefix), true)
+            } else {
+                (format!("{}{}", prefix, best), true)
+            }
+        }
+        None => (String::new(), false),
+    }
+}
+
+/// Every placeholder the token at the caret could complete to; empty if the
+/// caret isn't in a token.
+///
+/// By default these are prefix matches of what was typed before the caret, in
+/// placeholder order. With `fuzzy` they are subsequence matches, best
+/// `fuzzy_score` first and alphabetical among equal scores.
+fn find_autocomplete_all<'a>(
+    text: &str,
+    cursor: usize,
+    placeholders: &'a [String],
+    prefix: char,
+    fuzzy: bool,
+) -> Vec<&'a String> {
+    let Some(token) = ActiveToken::at(text, cursor, prefix) else {
+        return Vec::new();
+    };
+    let whole = token.whole(text, prefix);
+    // `@{na` completes like `@na`; a closed `@{name}` is finished
+    if whole.starts_with('{') && whole.contains('}') {
+        return Vec::new();
+    }
+    let whole = whole.strip_prefix('{').unwrap_or(whole);
+    let typed = token.typed(text, prefix);
+    let after_at = typed.strip_prefix('{').unwrap_or(typed);
+
+    // Don't suggest if the token already exactly matches a placeholder.
+    // Matching ignores case, but this check doesn't: `@camelcase` still
+    // suggests `@CamelCase`, since expansion is case-sensitive.
+    if placeholders.iter().any(|p| p == whole) {
+        return Vec::new();
+    }
+
+    if fuzzy {
+        let mut scored: Vec<(i32, &String)> = placeholders
+            .iter()
+            .filter_map(|p| fuzzy_score(after_at, p).map(|score| (score, p)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        return scored.into_iter().map(|(_, p)| p).collect();
+    }
+
+    // Prefix match; a bare prefix offers every placeholder
+    let partial = after_at.to_lowercase();
+    placeholders
+        .iter()
+        .filter(|p| p.to_lowercase().starts_with(&partial))
+        .collect()
+}
+
+/// fzf-style score of `query` against `candidate`, ignoring case.
+///
+/// `None` unless every query character appears in `candidate` in order.
+/// Consecutive matches and matches at the start of a word (after `-`/`_`)
+/// score higher; skipped characters cost a point each.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+
+    for q in query.to_lowercase().chars() {
+        let found = next + candidate[next..].iter().position(|&c| c == q)?;
+        score += 1;
+        if previous.is_some_and(|p| p + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || matches!(candidate[found - 1], '-' | '_') {
+            score += 3;
+        }
+        score -= (found - next) as i32;
+        previous = Some(found);
+        next = found + 1;
+    }
+    Some(score)
+}
+
+/// The placeholder token the caret is in, as byte offsets into the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ActiveToken {
+    /// Offset of the token's prefix
+    start: usize,
+    /// Where the caret is; what was typed of the name ends here
+    caret: usize,
+    /// End of what a completion replaces: the next whitespace after the caret
+    end: usize,
+}
+
+impl ActiveToken {
+    /// The token the caret (`cursor`, a byte offset) is in or right after.
+    ///
+    /// Only a prefix at a word boundary (start of text or after whitespace)
+    /// counts, so the `@` in `user@host` never starts a token. A caret in
+    /// whitespace that runs to the end of the text counts as standing after
+    /// the last word, and a completion then replaces that whitespace too, so a
+    /// stray space doesn't hide the suggestion.
+    fn at(text: &str, cursor: usize, prefix: char) -> Option<Self> {
+        let cursor = if text.is_char_boundary(cursor) {
+            cursor
+        } else {
+            text.len()
+        };
+        let trailing = text[cursor..].trim().is_empty();
+        let caret = if trailing {
+            text[..cursor].trim_end().len()
+        } else {
+            cursor
+        };
+        let start = text[..caret]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        if !text[start..caret].starts_with(prefix) {
+            return None;
+        }
+        let end = if trailing {
+            text.len()
+        } else {
+            text[caret..]
+                .find(char::is_whitespace)
+                .map_or(text.len(), |i| caret + i)
+        };
+        Some(Self { start, caret, end })
+    }
+
+    /// What was typed of the name, between the prefix and the caret
+    fn typed<'t>(&self, text: &'t str, prefix: char) -> &'t str {
+        &text[self.start + prefix.len_utf8()..self.caret]
+    }
+
+    /// The whole name after the prefix, including what follows the caret
+    fn whole<'t>(&self, text: &'t str, prefix: char) -> &'t str {
+        text[self.start + prefix.len_utf8()..self.end].trim_end()
+    }
+}
+
+/// What follows a completed placeholder: argument-taking built-ins get their `:`
+/// so the argument can be typed right away, everything else a space
+fn completion_suffix(name: &str) -> &'static str {
+    if ARGUMENT_TOKENS
+        .iter()
+        .any(|token| token.strip_suffix(':') == Some(name))
+    {
+        ":"
+    } else {
+        " "
+    }
+}
+
+/// Replace `token` in `text` with `name`, returning the new text and the
+/// byte offset the caret goes to: after the completion and the space that
+/// follows it.
+///
+/// The braced form is kept if it was being typed, and used for names a bare
+/// token can't spell. After an argument-taking built-in's `:` the closing `}`
+/// is left for the user to type after the argument. No space is added when
+/// one already follows the token.
+fn complete_token(text: &str, token: ActiveToken, name: &str, prefix: char) -> (String, usize) {
+    let braced = token.typed(text, prefix).starts_with('{') || needs_braces(name);
+    let suffix = completion_suffix(name);
+    let rest = &text[token.end..];
+    let mut result = text[..token.start].to_string();
+    result.push(prefix);
+    if braced {
+        result.push('{');
+    }
+    result.push_str(name);
+    if braced && suffix != ":" {
+        result.push('}');
+    }
+
+    let caret = match rest.chars().next() {
+        Some(c) if suffix == " " && c.is_whitespace() => result.len() + c.len_utf8(),
+        _ => {
+            result.push_str(suffix);
+            result.len()
+        }
+    };
+    result.push_str(rest);
+    (result, caret)
+}
+
+/// Repeated-Tab state: the candidates for one token and which one is inserted
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutocompleteCycle {
+    /// Input text before the first completion
+    original: String,
+    /// The token being completed in `original`
+    token: ActiveToken,
+    candidates: Vec<String>,
+    /// Index of the inserted candidate
+    index: usize,
+    /// Input text after the latest completion; anything else ends the cycle
+    pub completed: String,
+    /// Caret offset after the latest completion; moving it ends the cycle
+    pub caret: usize,
+}
+
+impl AutocompleteCycle {
+    /// Complete the token at the caret (`cursor`): start a cycle, or move to
+    /// the next candidate if `text` and the caret are still where the previous
+    /// completion left them. `None` if there's nothing to complete.
+    pub fn advance(
+        previous: Option<Self>,
+        text: &str,
+        cursor: usize,
+        placeholders: &[String],
+        prefix: char,
+        fuzzy: bool,
+    ) -> Option<Self> {
+        let mut cycle = match previous {
+            Some(mut cycle) if cycle.completed == text && cycle.caret == cursor => {
+                cycle.index = (cycle.index + 1) % cycle.candidates.len();
+                cycle
+            }
+            _ => {
+                let token = ActiveToken::at(text, cursor, prefix)?;
+                let candidates: Vec<String> =
+                    find_autocomplete_all(text, cursor, placeholders, prefix, fuzzy)
+                        .into_iter()
+                        .cloned()
+                        .collect();
+                if candidates.is_empty() {
+                    return None;
+                }
+                Self {
+                    original: text.to_string(),
+                    token,
+                    candidates,
+                    index: 0,
+                    completed: String::new(),
+                    caret: 0,
+                }
+            }
+        };
+        (cycle.completed, cycle.caret) = complete_token(
+            &cycle.original,
+            cycle.token,
+            &cycle.candidates[cycle.index],
+            prefix,
+        );
+        Some(cycle)
+    }
+
+    /// The candidate the next Tab inserts, if there is more than one
+    pub fn upcoming(&self) -> Option<&str> {
+        (self.candidates.len() > 1)
+            .then(|| self.candidates[(self.index + 1) % self.candidates.len()].as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expand::BUILTIN_PLACEHOLDERS;
+    use crate::highlight::build_highlight_text;
+
+    /// First completion of the token at the end of `text`, as one Tab inserts it
+    fn apply_autocomplete(
+        text: &str,
+        cursor: usize,
+        placeholders: &[String],
+        prefix: char,
+    ) -> String {
+        AutocompleteCycle::advance(None, text, cursor, placeholders, prefix, false)
+            .map_or_else(|| text.to_string(), |cycle| cycle.completed)
+    }
+
+    #[test]
+    fn test_find_autocomplete_partial() {
+        let placeholders = vec!["clipboard".to_string(), "path".to_string()];
+        let (suggestion, visible) = find_autocomplete("hello @cl", 9, &placeholders, '@', false);
+        assert!(visible);
+        assert_eq!(suggestion, "@clipboard");
+    }
+
+    #[test]
+    fn test_find_autocomplete_at_only() {
+        let placeholders = vec!["clipboard".to_string(), "path".to_string()];
+        let (suggestion, visible) = find_autocomplete("hello @", 7, &placeholders, '@', false);
+        assert!(visible);
+        assert_eq!(suggestion, "@clipboard");
+    }
+
+    #[test]
+    fn test_find_autocomplete_exact_match_no_suggest() {
+        let placeholders = vec!["clipboard".to_string()];
+        let (_suggestion, visible) =
+            find_autocomplete("hello @clipboard", 16, &placeholders, '@', false);
+        assert!(!visible);
+    }
+
+    #[test]
+    fn test_autocomplete_braced() {
+        let placeholders = vec![
+            "path".to_string(),
+            "some.file".to_string(),
+            "file".to_string(),
+        ];
+        assert_eq!(
+            find_autocomplete("Fix @{pa", 8, &placeholders, '@', false),
+            ("@{path}".to_string(), true)
+        );
+        assert_eq!(
+            apply_autocomplete("Fix @{pa", 8, &placeholders, '@'),
+            "Fix @{path} "
+        );
+        // Dotted names can only be written braced
+        assert_eq!(
+            apply_autocomplete("Fix @so", 7, &placeholders, '@'),
+            "Fix @{some.file} "
+        );
+        // The closing brace goes after the argument
+        assert_eq!(
+            apply_autocomplete("Read @{fi", 9, &placeholders, '@'),
+            "Read @{file:"
+        );
+        // A closed token is finished
+        assert!(!find_autocomplete("Fix @{pa}", 9, &placeholders, '@', false).1);
+    }
+
+    #[test]
+    fn test_find_autocomplete_no_at() {
+        let placeholders = vec!["clipboard".to_string()];
+        let (_suggestion, visible) =
+            find_autocomplete("hello world", 11, &placeholders, '@', false);
+        assert!(!visible);
+    }
+
+    #[test]
+    fn test_apply_autocomplete() {
+        let placeholders = vec!["clipboard".to_string(), "path".to_string()];
+        let result = apply_autocomplete("Fix @cl", 7, &placeholders, '@');
+        assert_eq!(result, "Fix @clipboard ");
+    }
+
+    #[test]
+    fn test_apply_autocomplete_argument_tokens() {
+        let placeholders: Vec<String> =
+            BUILTIN_PLACEHOLDERS.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            apply_autocomplete("Review @fi", 10, &placeholders, '@'),
+            "Review @file:"
+        );
+        assert_eq!(
+            apply_autocomplete("Key: @en", 8, &placeholders, '@'),
+            "Key: @env:"
+        );
+        assert_eq!(
+            apply_autocomplete("Today is @da", 12, &placeholders, '@'),
+            "Today is @date "
+        );
+        assert_eq!(
+            find_autocomplete("Review @file:", 13, &placeholders, '@', false),
+            (String::new(), false)
+        );
+    }
+
+    #[test]
+    fn test_autocomplete_ignores_mid_word_prefix() {
+        let placeholders = vec!["path".to_string(), "host".to_string()];
+        assert_eq!(
+            find_autocomplete("user@host @pa", 13, &placeholders, '@', false),
+            ("@path".to_string(), true)
+        );
+        assert_eq!(
+            apply_autocomplete("user@host @pa", 13, &placeholders, '@'),
+            "user@host @path "
+        );
+        assert_eq!(
+            find_autocomplete("foo@bar", 7, &placeholders, '@', false),
+            (String::new(), false)
+        );
+        assert_eq!(
+            find_autocomplete("mail me@ho", 10, &placeholders, '@', false),
+            (String::new(), false)
+        );
+        assert_eq!(
+            apply_autocomplete("mail me@ho", 10, &placeholders, '@'),
+            "mail me@ho"
+        );
+        assert_eq!(
+            find_autocomplete("line one\n@ho", 12, &placeholders, '@', false),
+            ("@host".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_autocomplete_preserves_case() {
+        let placeholders = vec!["CamelCase".to_string(), "path".to_string()];
+        assert_eq!(
+            find_autocomplete("Use @cam", 8, &placeholders, '@', false),
+            ("@CamelCase".to_string(), true)
+        );
+        assert_eq!(
+            apply_autocomplete("Use @cam", 8, &placeholders, '@'),
+            "Use @CamelCase "
+        );
+        assert_eq!(
+            find_autocomplete("Use @camelcase", 14, &placeholders, '@', false),
+            ("@CamelCase".to_string(), true)
+        );
+        assert_eq!(
+            find_autocomplete("Use @CamelCase", 14, &placeholders, '@', false),
+            (String::new(), false)
+        );
+        assert_eq!(
+            build_highlight_text("Use @CamelCase or @camelcase", &placeholders, '@'),
+            "    @CamelCase              "
+        );
+    }
+
+    #[test]
+    fn test_autocomplete_cycle() {
+        let placeholders = vec![
+            "clipboard".to_string(),
+            "path".to_string(),
+            "pathname".to_string(),
+        ];
+        assert_eq!(
+            find_autocomplete_all("Fix @pa", 7, &placeholders, '@', false),
+            vec!["path", "pathname"]
+        );
+
+        let first =
+            AutocompleteCycle::advance(None, "Fix @pa", 7, &placeholders, '@', false).unwrap();
+        assert_eq!(first.completed, "Fix @path ");
+        assert_eq!(first.upcoming(), Some("pathname"));
+
+        let second =
+            AutocompleteCycle::advance(Some(first), "Fix @path ", 10, &placeholders, '@', false)
+                .unwrap();
+        assert_eq!(second.completed, "Fix @pathname ");
+        assert_eq!(second.upcoming(), Some("path"));
+
+        let wrapped = AutocompleteCycle::advance(
+            Some(second),
+            "Fix @pathname ",
+            14,
+            &placeholders,
+            '@',
+            false,
+        )
+        .unwrap();
+        assert_eq!(wrapped.completed, "Fix @path ");
+
+        // Edited text starts a fresh cycle for the new token
+        let fresh = AutocompleteCycle::advance(
+            Some(wrapped),
+            "Fix @path @cl",
+            13,
+            &placeholders,
+            '@',
+            false,
+        )
+        .unwrap();
+        assert_eq!(fresh.completed, "Fix @path @clipboard ");
+        assert_eq!(fresh.upcoming(), None);
+
+        assert_eq!(
+            AutocompleteCycle::advance(None, "Fix @zz", 7, &placeholders, '@', false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_autocomplete() {
+        let placeholders = vec![
+            "clipboard".to_string(),
+            "selection".to_string(),
+            "sysinfo".to_string(),
+        ];
+        assert_eq!(
+            find_autocomplete("Review @slct", 12, &placeholders, '@', true),
+            ("@selection".to_string(), true)
+        );
+        assert_eq!(
+            find_autocomplete("Review @slct", 12, &placeholders, '@', false),
+            (String::new(), false)
+        );
+        assert_eq!(
+            find_autocomplete("Review @xyz", 11, &placeholders, '@', true),
+            (String::new(), false)
+        );
+        // Prefix-like queries still rank the contiguous match first
+        assert_eq!(
+            find_autocomplete_all("@s", 2, &placeholders, '@', true),
+            vec!["selection", "sysinfo"]
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("slct", "selection").is_some());
+        assert_eq!(fuzzy_score("xyz", "selection"), None);
+        assert!(fuzzy_score("sel", "selection") > fuzzy_score("sel", "some-label"));
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn test_autocomplete_at_caret() {
+        let placeholders = vec!["clipboard".to_string(), "path".to_string()];
+
+        // A stray trailing space doesn't hide the suggestion, and is replaced
+        assert_eq!(
+            find_autocomplete("hello @cl ", 10, &placeholders, '@', false),
+            ("@clipboard".to_string(), true)
+        );
+        assert_eq!(
+            apply_autocomplete("hello @cl ", 10, &placeholders, '@'),
+            "hello @clipboard "
+        );
+        // Mid-text: the token under the caret, not the last one
+        assert_eq!(
+            find_autocomplete("Fix @pa and @cl", 7, &placeholders, '@', false),
+            ("@path".to_string(), true)
+        );
+        let cycle =
+            AutocompleteCycle::advance(None, "Fix @pa and @cl", 7, &placeholders, '@', false)
+                .unwrap();
+        assert_eq!(cycle.completed, "Fix @path and @cl");
+        // The caret lands after the existing space
+        assert_eq!(cycle.caret, 10);
+        // What follows the caret in the token is overwritten
+        assert_eq!(
+            apply_autocomplete("Fix @paXY now", 7, &placeholders, '@'),
+            "Fix @path now"
+        );
+        // A caret before the prefix, or in plain words, has no token
+        assert!(!find_autocomplete("Fix @pa", 4, &placeholders, '@', false).1);
+        assert!(!find_autocomplete("Fix @pa and", 11, &placeholders, '@', false).1);
+
+        // Moving the caret away ends a Tab cycle
+        let placeholders = vec!["path".to_string(), "pathname".to_string()];
+        let first =
+            AutocompleteCycle::advance(None, "@pa @pa", 3, &placeholders, '@', false).unwrap();
+        assert_eq!((first.completed.as_str(), first.caret), ("@path @pa", 6));
+        let moved = AutocompleteCycle::advance(
+            Some(first.clone()),
+            "@path @pa",
+            9,
+            &placeholders,
+            '@',
+            false,
+        )
+        .unwrap();
+        assert_eq!(moved.completed, "@path @path ");
+        let next =
+            AutocompleteCycle::advance(Some(first), "@path @pa", 6, &placeholders, '@', false)
+                .unwrap();
+        assert_eq!(next.completed, "@pathname @pa");
+    }
+
+    #[test]
+    fn test_apply_autocomplete_at_only() {
+        let placeholders = vec!["clipboard".to_string()];
+        let result = apply_autocomplete("Fix @", 5, &placeholders, '@');
+        assert_eq!(result, "Fix @clipboard ");
+    }
+
+    #[test]
+    fn test_custom_prefix_highlight_and_autocomplete() {
+        let placeholders = vec!["path".to_string()];
+        assert_eq!(
+            build_highlight_text("Fix $path @path", &placeholders, '$'),
+            "    $path      "
+        );
+        assert_eq!(
+            find_autocomplete("Fix $pa", 7, &placeholders, '$', false),
+            ("$path".to_string(), true)
+        );
+        assert_eq!(
+            apply_autocomplete("Fix $pa", 7, &placeholders, '$'),
+            "Fix $path "
+        );
+    }
+}
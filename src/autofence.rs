@@ -0,0 +1,166 @@
+//! Automatic code fencing for outgoing prompts
+//!
+//! Detects paragraphs that look like source code and wraps them in markdown
+//! fences with a guessed language, so pasted snippets render as code in OpenCode.
+
+/// Minimum share of code-like lines for a paragraph to count as code
+const CODE_LINE_RATIO: f32 = 0.5;
+
+/// Heuristically decide whether a block of text is source code
+pub fn looks_like_code(block: &str) -> bool {
+    let lines: Vec<&str> = block.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.is_empty() {
+        return false;
+    }
+
+    let code_lines = lines.iter().filter(|l| is_code_line(l)).count();
+    code_lines as f32 / lines.len() as f32 >= CODE_LINE_RATIO
+}
+
+/// Check a single line for code-like shape: indentation, braces, statement ends, keywords
+fn is_code_line(line: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "fn ",
+        "let ",
+        "impl ",
+        "pub ",
+        "use ",
+        "def ",
+        "class ",
+        "import ",
+        "from ",
+        "function ",
+        "const ",
+        "return ",
+        "#include",
+        "package ",
+        "func ",
+        "if (",
+        "for (",
+        "while (",
+    ];
+
+    let trimmed = line.trim();
+    if line.starts_with("    ") || line.starts_with('\t') {
+        return true;
+    }
+    if trimmed.ends_with('{')
+        || trimmed.ends_with('}')
+        || trimmed.ends_with(';')
+        || trimmed.contains("=>")
+        || trimmed.contains("->")
+    {
+        return true;
+    }
+    KEYWORDS.iter().any(|k| trimmed.starts_with(k))
+}
+
+/// Guess a markdown fence language for a code block (empty when unsure)
+pub fn guess_language(block: &str) -> &'static str {
+    let has = |needle: &str| block.contains(needle);
+
+    if has("fn ") || has("let mut ") || has("impl ") || has("pub fn") || has("::") {
+        "rust"
+    } else if has("#include") {
+        "c"
+    } else if has("package ") || has("func ") {
+        "go"
+    } else if has("def ") || (has("import ") && !has(";")) {
+        "python"
+    } else if has("function ") || has("const ") || has("=>") {
+        "javascript"
+    } else {
+        ""
+    }
+}
+
+/// Wrap code-looking paragraphs of `text` in fenced code blocks.
+///
+/// Paragraphs are separated by blank lines; consecutive code paragraphs are
+/// merged into one fence. Text that already contains a fence is left untouched.
+pub fn auto_fence(text: &str) -> String {
+    if text.contains("```") {
+        return text.to_string();
+    }
+
+    let mut out: Vec<String> = Vec::new();
+    let mut code_run: Vec<&str> = Vec::new();
+
+    let flush = |run: &mut Vec<&str>, out: &mut Vec<String>| {
+        if run.is_empty() {
+            return;
+        }
+        let code = run.join("\n\n");
+        out.push(format!("```{}\n{}\n```", guess_language(&code), code));
+        run.clear();
+    };
+
+    for block in text.split("\n\n") {
+        if looks_like_code(block) {
+            code_run.push(block.trim_matches('\n'));
+        } else {
+            flush(&mut code_run, &mut out);
+            out.push(block.to_string());
+        }
+    }
+    flush(&mut code_run, &mut out);
+
+    out.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looks_like_code_rust() {
+        let code = "fn main() {\n    println!(\"hi\");\n}";
+        assert!(looks_like_code(code));
+    }
+
+    #[test]
+    fn test_looks_like_code_prose() {
+        let prose = "Please fix the bug in the parser.\nIt crashes on empty input.";
+        assert!(!looks_like_code(prose));
+    }
+
+    #[test]
+    fn test_guess_language() {
+        assert_eq!(guess_language("fn main() {}"), "rust");
+        assert_eq!(guess_language("def run():\n    pass"), "python");
+        assert_eq!(guess_language("const x = () => 1;"), "javascript");
+        assert_eq!(guess_language("#include <stdio.h>"), "c");
+        assert_eq!(guess_language("x {\n}"), "");
+    }
+
+    #[test]
+    fn test_auto_fence_mixed() {
+        let text =
+            "Why does this panic?\n\nfn main() {\n    let v: Vec<u8> = vec![];\n    v[0];\n}";
+        let fenced = auto_fence(text);
+        assert_eq!(
+            fenced,
+            "Why does this panic?\n\n```rust\nfn main() {\n    let v: Vec<u8> = vec![];\n    v[0];\n}\n```"
+        );
+    }
+
+    #[test]
+    fn test_auto_fence_merges_adjacent_code() {
+        let text = "fn a() {\n    1;\n}\n\nfn b() {\n    2;\n}";
+        let fenced = auto_fence(text);
+        assert_eq!(fenced.matches("```").count(), 2);
+        assert!(fenced.starts_with("```rust\nfn a()"));
+    }
+
+    #[test]
+    fn test_auto_fence_prose_unchanged() {
+        let text = "Refactor the module.\n\nKeep the API stable.";
+        assert_eq!(auto_fence(text), text);
+    }
+
+    #[test]
+    fn test_auto_fence_existing_fence_unchanged() {
+        let text = "```\nfn main() {}\n```";
+        assert_eq!(auto_fence(text), text);
+    }
+}
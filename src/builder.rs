@@ -0,0 +1,206 @@
+//! Prompt builder mode
+//!
+//! Assembles the labeled fields of the builder window (task, context,
+//! constraints) into a single markdown prompt before expansion and send, and
+//! runs that window in place of the single-input dialog (`--builder`).
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use slint::ComponentHandle;
+
+use crate::expand::ExpandContext;
+use crate::finalize::finalize_prompt;
+use crate::redact::Redactor;
+use crate::{
+    build_status_text, dismissed_error, exit_event_loop, exit_with_failure_report, expand_title,
+    finish_dry_run, ignore_submit, place_window, send_mode, server, server_client, spawn_send,
+    AfterSend, Cli, PromptBuilder, DEFAULT_WINDOW_WIDTH, NOT_CONNECTED,
+};
+
+/// Builder window height in logical pixels (includes shadow margins)
+const WINDOW_HEIGHT: f32 = 420.0;
+
+/// Section headers, in the order they appear in the assembled prompt
+pub const SECTION_LABELS: [&str; 3] = ["Task", "Context", "Constraints"];
+
+/// Join labeled sections into one prompt with a `## Label` header each.
+///
+/// Values are trimmed, and sections left empty are omitted entirely.
+pub fn assemble_sections(sections: &[(&str, &str)]) -> String {
+    sections
+        .iter()
+        .map(|(label, value)| (label, value.trim()))
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(label, value)| format!("## {}\n{}", label, value))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Run the prompt builder window instead of the single-input dialog
+pub fn run(
+    cli: Rc<Cli>,
+    params: HashMap<String, String>,
+    discovery_result: &Result<server::Server>,
+    expand_cwd: PathBuf,
+    redactor: Option<Redactor>,
+    rt_handle: tokio::runtime::Handle,
+) -> Result<()> {
+    let window = PromptBuilder::new().context("Failed to create prompt builder window")?;
+
+    if let Some(template) = &cli.title {
+        window.set_window_title(
+            expand_title(template, &params, Some(&expand_cwd), cli.prefix).into(),
+        );
+    }
+    place_window(
+        window.window(),
+        DEFAULT_WINDOW_WIDTH,
+        WINDOW_HEIGHT,
+        cli.position,
+    );
+    window.set_dry_run(cli.dry_run);
+
+    match discovery_result {
+        Ok(server) => {
+            window.set_connected(true);
+            window.set_status_text(build_status_text(true, server.tcp_port()).into());
+        }
+        Err(e) => {
+            window.set_connected(false);
+            window.set_status_text(build_status_text(false, None).into());
+            window.set_error_text(format!("{}", e).into());
+        }
+    }
+
+    let server = discovery_result.as_ref().ok().cloned();
+    let sent = Arc::new(AtomicBool::new(false));
+
+    {
+        let weak = window.as_weak();
+        let cli = cli.clone();
+        let pending_redaction = RefCell::new(None);
+        let sent = sent.clone();
+
+        window.on_submit(move |task, context, constraints| {
+            if weak
+                .upgrade()
+                .is_some_and(|w| ignore_submit(w.get_sending(), w.get_closing()))
+            {
+                return;
+            }
+            let values = [task.as_str(), context.as_str(), constraints.as_str()];
+            let sections: Vec<(&str, &str)> = SECTION_LABELS.into_iter().zip(values).collect();
+            let text = assemble_sections(&sections);
+            if text.is_empty() {
+                return;
+            }
+
+            let ctx = ExpandContext::from_cli(&cli, &params).with_cwd(&expand_cwd);
+            let pending = Some(&pending_redaction);
+            let expanded = match finalize_prompt(&text, &ctx, &cli, redactor.as_ref(), pending) {
+                Ok(prompt) => prompt,
+                Err(msg) => {
+                    if let Some(w) = weak.upgrade() {
+                        w.set_error_text(msg.into());
+                    }
+                    return;
+                }
+            };
+
+            if cli.dry_run {
+                finish_dry_run(&expanded);
+            }
+
+            if let Some(server) = &server {
+                if let Some(w) = weak.upgrade() {
+                    w.set_sending(true);
+                }
+                let weak = weak.clone();
+                let sent = sent.clone();
+                spawn_send(
+                    &rt_handle,
+                    server_client(&cli, server),
+                    expanded,
+                    text,
+                    send_mode(&cli),
+                    AfterSend::from_cli(&cli).with_report(&cli, server),
+                    move |result| {
+                        if let Some(w) = weak.upgrade() {
+                            w.set_sending(false);
+                            match result {
+                                Ok(()) => {
+                                    sent.store(true, Ordering::SeqCst);
+                                    w.set_closing(true);
+                                    w.set_dialog_open(false);
+                                    w.set_start_close_timer(true);
+                                }
+                                Err(msg) => w.set_error_text(msg.into()),
+                            }
+                        }
+                    },
+                );
+            } else if let Some(w) = weak.upgrade() {
+                w.set_error_text(NOT_CONNECTED.into());
+            }
+        });
+    }
+
+    {
+        let weak = window.as_weak();
+        let report_json = cli.report_json;
+        window.on_close_window(move || {
+            let mut error = String::new();
+            if let Some(w) = weak.upgrade() {
+                let _ = w.hide();
+                error = w.get_error_text().to_string();
+            }
+            if report_json && !sent.load(Ordering::SeqCst) {
+                exit_with_failure_report(&dismissed_error(&error));
+            }
+            exit_event_loop();
+        });
+    }
+
+    window.run().context("Slint event loop failed")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_all_sections() {
+        let prompt = assemble_sections(&[
+            ("Task", "Fix the parser"),
+            ("Context", "File: @path"),
+            ("Constraints", "Keep the API stable"),
+        ]);
+        assert_eq!(
+            prompt,
+            "## Task\nFix the parser\n\n## Context\nFile: @path\n\n## Constraints\nKeep the API stable"
+        );
+    }
+
+    #[test]
+    fn test_assemble_omits_empty_sections() {
+        let prompt = assemble_sections(&[
+            ("Task", "  Fix the parser\n"),
+            ("Context", ""),
+            ("Constraints", " \n "),
+        ]);
+        assert_eq!(prompt, "## Task\nFix the parser");
+    }
+
+    #[test]
+    fn test_assemble_nothing() {
+        assert_eq!(assemble_sections(&[("Task", ""), ("Context", "")]), "");
+    }
+}
@@ -0,0 +1,297 @@
+//! System clipboard access
+//!
+//! Wraps `arboard` behind a small trait so placeholder expansion can be tested
+//! without touching the real clipboard.
+
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Which clipboard flavor `@clipboard` should prefer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ClipboardFormat {
+    /// Plain text (default)
+    #[default]
+    Plain,
+    /// HTML flavor converted to text, falling back to plain text
+    Html,
+}
+
+/// Source of clipboard content for built-in placeholders
+pub trait ClipboardProvider {
+    /// Read the current clipboard text, if any
+    fn read_text(&self) -> Option<String>;
+
+    /// Read the clipboard's HTML flavor, if the platform offers one
+    fn read_html(&self) -> Option<String> {
+        None
+    }
+
+    /// Read the clipboard image, if it holds one
+    fn read_image(&self) -> Option<ClipboardImage> {
+        None
+    }
+}
+
+/// An image read from the clipboard, as 8-bit RGBA pixels
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClipboardImage {
+    pub width: usize,
+    pub height: usize,
+    /// `width * height * 4` bytes, row by row
+    pub rgba: Vec<u8>,
+}
+
+/// The real system clipboard
+pub struct SystemClipboard;
+
+impl ClipboardProvider for SystemClipboard {
+    fn read_text(&self) -> Option<String> {
+        arboard::Clipboard::new()
+            .ok()
+            .and_then(|mut cb| cb.get_text().ok())
+            .filter(|s| !s.is_empty())
+    }
+
+    fn read_html(&self) -> Option<String> {
+        arboard::Clipboard::new()
+            .ok()
+            .and_then(|mut cb| cb.get().html().ok())
+            .filter(|s| !s.is_empty())
+    }
+
+    fn read_image(&self) -> Option<ClipboardImage> {
+        let image = arboard::Clipboard::new().ok()?.get_image().ok()?;
+        Some(ClipboardImage {
+            width: image.width,
+            height: image.height,
+            rgba: image.bytes.into_owned(),
+        })
+    }
+}
+
+/// Encode a clipboard image as PNG
+pub fn encode_png(image: &ClipboardImage) -> Result<Vec<u8>> {
+    let width = u32::try_from(image.width).context("Clipboard image is too wide")?;
+    let height = u32::try_from(image.height).context("Clipboard image is too tall")?;
+
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .context("Failed to encode clipboard image")?;
+    writer
+        .write_image_data(&image.rgba)
+        .context("Failed to encode clipboard image")?;
+    writer
+        .finish()
+        .context("Failed to encode clipboard image")?;
+    Ok(data)
+}
+
+/// Write `image` as a PNG into `dir` and return its path.
+///
+/// The name is derived from the pixels (`prompt-dialog-clipboard-<hash>.png`),
+/// so pasting the same image again reuses the file. Files are never removed;
+/// cleaning up `dir` is up to the caller.
+pub fn save_image(image: &ClipboardImage, dir: &Path) -> Result<PathBuf> {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    image.hash(&mut hasher);
+    let path = dir.join(format!(
+        "prompt-dialog-clipboard-{:016x}.png",
+        hasher.finish()
+    ));
+
+    let data = encode_png(image)?;
+    std::fs::write(&path, data)
+        .with_context(|| format!("Failed to write clipboard image {}", path.display()))?;
+    Ok(path)
+}
+
+/// Read clipboard text, honoring the preferred format.
+///
+/// The HTML flavor is stripped to text; if it's missing or empty the plain
+/// text flavor is used instead.
+pub fn read_preferred(provider: &dyn ClipboardProvider, format: ClipboardFormat) -> Option<String> {
+    match format {
+        ClipboardFormat::Plain => provider.read_text(),
+        ClipboardFormat::Html => provider
+            .read_html()
+            .map(|html| strip_html(&html))
+            .filter(|text| !text.trim().is_empty())
+            .or_else(|| provider.read_text()),
+    }
+}
+
+/// Convert an HTML fragment to plain text.
+///
+/// Drops tags (and `<script>`/`<style>` bodies), turns block-level closing tags
+/// and `<br>` into newlines, and decodes the common character entities.
+pub fn strip_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(lt) = rest.find('<') {
+        out.push_str(&rest[..lt]);
+        let after = &rest[lt..];
+        let Some(gt) = after.find('>') else {
+            // Unterminated tag: keep the remainder verbatim
+            out.push_str(after);
+            rest = "";
+            break;
+        };
+
+        let tag = after[1..gt].trim().to_lowercase();
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("");
+        rest = &after[gt + 1..];
+
+        if !tag.starts_with('/') && (name == "script" || name == "style") {
+            let close = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(end) => rest[end..].find('>').map_or("", |g| &rest[end + g + 1..]),
+                None => "",
+            };
+            continue;
+        }
+
+        let is_break = name == "br"
+            || (tag.starts_with('/')
+                && matches!(
+                    name,
+                    "p" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "pre"
+                ));
+        if is_break {
+            out.push('\n');
+        }
+    }
+    out.push_str(rest);
+
+    decode_entities(&out).trim().to_string()
+}
+
+/// Decode the handful of HTML entities commonly found in clipboard fragments
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeClipboard {
+        text: Option<String>,
+        html: Option<String>,
+    }
+
+    impl ClipboardProvider for FakeClipboard {
+        fn read_text(&self) -> Option<String> {
+            self.text.clone()
+        }
+
+        fn read_html(&self) -> Option<String> {
+            self.html.clone()
+        }
+    }
+
+    #[test]
+    fn test_read_preferred_plain_ignores_html() {
+        let cb = FakeClipboard {
+            text: Some("plain".to_string()),
+            html: Some("<b>rich</b>".to_string()),
+        };
+        assert_eq!(
+            read_preferred(&cb, ClipboardFormat::Plain).unwrap(),
+            "plain"
+        );
+    }
+
+    #[test]
+    fn test_read_preferred_html_strips_tags() {
+        let cb = FakeClipboard {
+            text: Some("plain".to_string()),
+            html: Some("<p>Hello <b>world</b> &amp; co</p>".to_string()),
+        };
+        assert_eq!(
+            read_preferred(&cb, ClipboardFormat::Html).unwrap(),
+            "Hello world & co"
+        );
+    }
+
+    #[test]
+    fn test_read_preferred_html_falls_back_to_plain() {
+        let cb = FakeClipboard {
+            text: Some("plain".to_string()),
+            html: None,
+        };
+        assert_eq!(read_preferred(&cb, ClipboardFormat::Html).unwrap(), "plain");
+
+        let cb = FakeClipboard {
+            text: Some("plain".to_string()),
+            html: Some("<div> </div>".to_string()),
+        };
+        assert_eq!(read_preferred(&cb, ClipboardFormat::Html).unwrap(), "plain");
+    }
+
+    /// A 2x1 image: one red and one transparent pixel
+    fn tiny_image() -> ClipboardImage {
+        ClipboardImage {
+            width: 2,
+            height: 1,
+            rgba: vec![255, 0, 0, 255, 0, 0, 0, 0],
+        }
+    }
+
+    #[test]
+    fn test_save_image_writes_png() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = save_image(&tiny_image(), dir.path()).unwrap();
+
+        let name = path.file_name().unwrap().to_string_lossy();
+        assert!(name.starts_with("prompt-dialog-clipboard-") && name.ends_with(".png"));
+
+        let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(pixels, tiny_image().rgba);
+    }
+
+    #[test]
+    fn test_save_image_names_are_stable() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = save_image(&tiny_image(), dir.path()).unwrap();
+        let again = save_image(&tiny_image(), dir.path()).unwrap();
+        assert_eq!(first, again);
+
+        let mut other = tiny_image();
+        other.rgba[0] = 0;
+        assert_ne!(save_image(&other, dir.path()).unwrap(), first);
+    }
+
+    #[test]
+    fn test_encode_png_rejects_short_buffer() {
+        let mut image = tiny_image();
+        image.rgba.truncate(4);
+        assert!(encode_png(&image).is_err());
+    }
+
+    #[test]
+    fn test_strip_html_blocks_and_scripts() {
+        let html =
+            "<style>p { color: red }</style><p>one</p><p>two<br>three</p><script>x()</script>";
+        assert_eq!(strip_html(html), "one\ntwo\nthree");
+    }
+}
@@ -0,0 +1,112 @@
+//! `--config`: default flag values from a TOML file
+//!
+//! The file (`<config dir>/prompt-dialog/config.toml` unless `--config` names
+//! another) fills in flags that weren't given on the command line or through
+//! their environment variable; explicit flags always win. Unknown keys are an
+//! error so typos don't go unnoticed.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::theme::Theme;
+
+/// File name of the config under the config directory
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Default location: `<config dir>/prompt-dialog/config.toml`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("prompt-dialog").join(CONFIG_FILE))
+}
+
+/// Flag defaults a config file may set; absent keys leave the built-in default
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// `--host`
+    pub host: Option<String>,
+    /// `--port`
+    pub port: Option<u16>,
+    /// `--timeout`, in seconds
+    pub timeout: Option<u64>,
+    /// `--theme`
+    pub theme: Option<Theme>,
+    /// `--prefix`
+    pub prefix: Option<char>,
+    /// `--retries`
+    pub retries: Option<u32>,
+}
+
+impl Config {
+    /// Parse a config file's contents
+    pub fn parse(data: &str) -> Result<Self> {
+        toml::from_str(data).context("Failed to parse TOML")
+    }
+
+    /// Load the config at `path`. A missing file is an empty config unless
+    /// `required` (it was named with `--config`).
+    pub fn load(path: &Path, required: bool) -> Result<Self> {
+        let data = match std::fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default());
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read config {}", path.display()));
+            }
+        };
+        Self::parse(&data).with_context(|| format!("Invalid config {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_all_fields() {
+        let config = Config::parse(
+            r#"
+host = "10.0.0.2"
+port = 4096
+timeout = 30
+theme = "light"
+prefix = "$"
+retries = 1
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                host: Some("10.0.0.2".to_string()),
+                port: Some(4096),
+                timeout: Some(30),
+                theme: Some(Theme::Light),
+                prefix: Some('$'),
+                retries: Some(1),
+            }
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_and_invalid() {
+        assert!(Config::parse("hots = \"x\"").is_err());
+        assert!(Config::parse("theme = \"sepia\"").is_err());
+        assert!(Config::parse("prefix = \"ab\"").is_err());
+        assert!(Config::parse("port = 70000").is_err());
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE);
+        assert_eq!(Config::load(&path, false).unwrap(), Config::default());
+        assert!(Config::load(&path, true).is_err());
+
+        std::fs::write(&path, "timeout = 9\n").unwrap();
+        assert_eq!(Config::load(&path, true).unwrap().timeout, Some(9));
+    }
+}
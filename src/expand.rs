@@ -0,0 +1,1568 @@
+//! Placeholder expansion: `--macro`s, `--param`s and the `@` built-ins
+//!
+//! Macros expand first, then params used as a built-in's argument, then the
+//! built-ins, then the remaining params. Filesystem built-ins resolve against
+//! the context's working directory (the server's cwd when known).
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::clipboard::{self, ClipboardFormat, ClipboardProvider, SystemClipboard};
+use crate::{git, parse_params, Cli};
+
+/// Everything placeholder expansion needs besides the text itself
+pub struct ExpandContext<'a> {
+    /// User params, expanded as `@key`
+    pub params: &'a HashMap<String, String>,
+    /// Source for `@clipboard`
+    pub clipboard: &'a dyn ClipboardProvider,
+    /// Preferred clipboard flavor
+    pub clipboard_format: ClipboardFormat,
+    /// User-defined macros, expanded before everything else
+    pub macros: HashMap<String, String>,
+    /// Working directory for filesystem built-ins (the server's cwd when known)
+    pub cwd: Option<PathBuf>,
+    /// Character that starts a placeholder (`--prefix`)
+    pub prefix: char,
+    /// Report params that never appeared in the prompt on stderr
+    pub warn_unused: bool,
+    /// Byte cap for `@git-diff` output
+    pub git_diff_max_bytes: usize,
+    /// Most paths one `@files:<glob>` lists
+    pub files_max: usize,
+    /// File the editor wrote its selection to, read by `@selection-file`
+    pub selection_path: Option<PathBuf>,
+}
+
+impl<'a> ExpandContext<'a> {
+    /// Context using the system clipboard (plain text) and the given params
+    pub fn new(params: &'a HashMap<String, String>) -> Self {
+        Self {
+            params,
+            clipboard: &SystemClipboard,
+            clipboard_format: ClipboardFormat::Plain,
+            macros: HashMap::new(),
+            cwd: None,
+            prefix: DEFAULT_PREFIX,
+            warn_unused: false,
+            git_diff_max_bytes: DEFAULT_GIT_DIFF_MAX_BYTES,
+            files_max: DEFAULT_FILES_MAX,
+            selection_path: None,
+        }
+    }
+
+    /// Context configured from CLI flags
+    pub fn from_cli(cli: &Cli, params: &'a HashMap<String, String>) -> Self {
+        Self {
+            clipboard_format: cli.clipboard_format,
+            macros: parse_macros(&cli.macros),
+            prefix: cli.prefix,
+            warn_unused: cli.warn_unused_params,
+            git_diff_max_bytes: cli.git_diff_max_bytes,
+            files_max: cli.files_max,
+            selection_path: cli.selection_path.clone(),
+            ..Self::new(params)
+        }
+    }
+
+    /// Set the working directory used by filesystem built-ins
+    pub fn with_cwd(mut self, cwd: &Path) -> Self {
+        self.cwd = Some(cwd.to_path_buf());
+        self
+    }
+
+    /// The placeholder token for `name`, e.g. `@clipboard`
+    pub fn token(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    /// The tokens param `key` is written as: always `@{key}`, and `@key`
+    /// unless the key has a dot, which ends a bare token
+    pub fn param_tokens(&self, key: &str) -> Vec<String> {
+        let braced = braced_token(key, self.prefix);
+        if needs_braces(key) {
+            vec![braced]
+        } else {
+            vec![braced, self.token(key)]
+        }
+    }
+}
+
+/// Default placeholder prefix
+pub const DEFAULT_PREFIX: char = '@';
+
+/// Default byte cap for `@git-diff` (`--git-diff-max-bytes`)
+pub const DEFAULT_GIT_DIFF_MAX_BYTES: usize = 64 * 1024;
+
+/// Default cap on the paths one `@files:<glob>` lists (`--files-max`)
+pub const DEFAULT_FILES_MAX: usize = 200;
+
+/// Built-in placeholder names, always offered for highlighting and autocomplete
+pub const BUILTIN_PLACEHOLDERS: &[&str] = &[
+    "clipboard",
+    "clipboard-image",
+    "cursor",
+    "date",
+    "env",
+    "file",
+    "files",
+    "git-branch",
+    "git-diff",
+    "git-root",
+    "param-list",
+    "selection-file",
+    "sysinfo",
+    "time",
+];
+
+/// Built-ins whose argument follows a `:` (`@file:<path>`, `@files:<glob>`,
+/// `@env:<NAME>`)
+pub const ARGUMENT_TOKENS: [&str; 3] = [FILE_TOKEN, FILES_TOKEN, ENV_TOKEN];
+
+/// Maximum rounds of macro expansion (bounds self-referencing macros)
+const MAX_MACRO_DEPTH: usize = 10;
+
+/// Maximum nesting of params referencing other params (`--param file=@base/x`)
+const MAX_PARAM_DEPTH: usize = 10;
+
+/// Parse --macro name=template pairs, unescaping `\n` and `\t` in templates
+pub fn parse_macros(raw: &[String]) -> HashMap<String, String> {
+    parse_params(raw)
+        .into_iter()
+        .map(|(name, template)| (name, template.replace("\\n", "\n").replace("\\t", "\t")))
+        .collect()
+}
+
+/// Expand user-defined macros.
+///
+/// Macro bodies may reference other macros; expansion repeats until nothing
+/// changes or `MAX_MACRO_DEPTH` rounds have run, so cycles terminate.
+fn expand_macros(text: &str, macros: &HashMap<String, String>, prefix: char) -> String {
+    if macros.is_empty() {
+        return text.to_string();
+    }
+
+    let mut names: Vec<&String> = macros.keys().collect();
+    names.sort_by_key(|k| std::cmp::Reverse(k.len()));
+
+    let mut result = text.to_string();
+    for _ in 0..MAX_MACRO_DEPTH {
+        let before = result.clone();
+        for name in &names {
+            result = result.replace(&braced_token(name, prefix), &macros[*name]);
+            if !needs_braces(name) {
+                result = result.replace(&format!("{}{}", prefix, name), &macros[*name]);
+            }
+        }
+        if result == before {
+            break;
+        }
+    }
+    result
+}
+
+/// Expand @placeholder tokens in text with param values and built-in special tokens.
+///
+/// Built-in tokens (always available):
+///   - `@clipboard` — current system clipboard text content
+///   - `@clipboard:path` — clipboard as a file reference if it names an existing path
+///   - `@git-root` — repository root above the working directory (or the directory itself)
+///   - `@git-branch` / `@git-diff` — current branch and staged diff (capped) from `git`
+///   - `@selection-file` — contents of `--selection-path`, minus a trailing newline
+///   - `@sysinfo` — short OS / arch / shell block
+///   - `@param-list` — every param as a `- key: value` line, sorted by key
+///   - `@file:<path>` — contents of a file, relative to the working directory
+///   - `@files:<glob>` — matching file paths, one per line, relative to the working directory
+///   - `@env:<NAME>` — value of an environment variable (empty if unset)
+///   - `@date` / `@time` — local date and time, with an optional `{strftime}` format
+///
+/// `@cursor` is left in place for `take_cursor`, which removes it once the
+/// prompt is final.
+///
+/// User params from `--param key=value` are expanded as `@key`.
+/// Matches the longest key first to avoid partial replacements.
+pub fn expand_placeholders_with(text: &str, ctx: &ExpandContext) -> String {
+    expand_placeholders_tracked(text, ctx).text
+}
+
+/// `expand_placeholders_with` using the system clipboard and just `params`
+#[cfg(test)]
+fn expand_placeholders(text: &str, params: &HashMap<String, String>) -> String {
+    expand_placeholders_with(text, &ExpandContext::new(params))
+}
+
+/// Expanded text, with what the expansion ran into
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion<'p> {
+    pub text: String,
+    /// Params that were substituted
+    pub used: BTreeSet<&'p str>,
+    /// Built-ins that resolved to nothing, e.g. `clipboard` with an empty clipboard
+    pub empty: BTreeSet<&'static str>,
+}
+
+/// Like `expand_placeholders_with`, also reporting the params that were
+/// substituted and the built-ins that came out empty
+pub fn expand_placeholders_tracked<'p>(text: &str, ctx: &ExpandContext<'p>) -> Expansion<'p> {
+    let mut used = BTreeSet::new();
+
+    // Macros go first so their bodies get the usual placeholder expansion
+    let mut result = expand_macros(text, &ctx.macros, ctx.prefix);
+
+    // Params used as a built-in's argument must resolve before the built-in runs
+    result = expand_nested_arguments(&result, ctx, &mut used);
+
+    // Expand built-in special tokens, then their braced forms
+    let (expanded, mut empty) = expand_builtins(&result, ctx);
+    result = expand_braced_builtins(&expanded, ctx, &mut empty);
+
+    let result = expand_params(result, ctx, &mut used);
+
+    Expansion {
+        text: result,
+        used,
+        empty,
+    }
+}
+
+/// Expand only macros and user params, leaving every built-in as typed.
+///
+/// Nothing is read or run (no clipboard, files, globs or `git`), so this is
+/// cheap and side-effect free enough to call on every keystroke, as
+/// `--count-expanded` does.
+pub fn expand_without_builtins(text: &str, ctx: &ExpandContext) -> String {
+    let result = expand_macros(text, &ctx.macros, ctx.prefix);
+    expand_params(result, ctx, &mut BTreeSet::new())
+}
+
+/// Expand user params in `text`, with their references to other params
+/// resolved; the keys substituted are added to `used`
+fn expand_params<'p>(
+    mut text: String,
+    ctx: &ExpandContext<'p>,
+    used: &mut BTreeSet<&'p str>,
+) -> String {
+    for key in params_longest_first(ctx.params) {
+        let tokens = ctx.param_tokens(key);
+        if !tokens.iter().any(|token| text.contains(token)) {
+            continue;
+        }
+        used.insert(key.as_str());
+        let value = resolve_param(key, ctx, &mut Vec::new(), used);
+        for placeholder in &tokens {
+            text = expand_indexed(&text, placeholder, &value);
+            text = text.replace(placeholder, &value);
+        }
+    }
+    text
+}
+
+/// Param keys, longest first so `@pathname` isn't mistaken for `@path`
+fn params_longest_first(params: &HashMap<String, String>) -> Vec<&String> {
+    let mut keys: Vec<&String> = params.keys().collect();
+    keys.sort_by_key(|k| std::cmp::Reverse(k.len()));
+    keys
+}
+
+/// The value of param `key` with references to other params expanded, so
+/// `--param file=@base/notes.txt` picks up `@base`.
+///
+/// `chain` holds the params being resolved further up. A reference back into
+/// it (a cycle like `a=@a`) stays literal, and so does anything nested deeper
+/// than `MAX_PARAM_DEPTH`. Built-ins in values are left alone: they only
+/// expand once, in the prompt itself.
+fn resolve_param<'p>(
+    key: &'p str,
+    ctx: &ExpandContext<'p>,
+    chain: &mut Vec<&'p str>,
+    used: &mut BTreeSet<&'p str>,
+) -> String {
+    let mut value = ctx.params[key].clone();
+    if chain.len() >= MAX_PARAM_DEPTH {
+        return value;
+    }
+
+    chain.push(key);
+    for inner in params_longest_first(ctx.params) {
+        let tokens = ctx.param_tokens(inner);
+        if chain.contains(&inner.as_str()) || !tokens.iter().any(|t| value.contains(t)) {
+            continue;
+        }
+        used.insert(inner.as_str());
+        let inner_value = resolve_param(inner, ctx, chain, used);
+        for token in &tokens {
+            value = value.replace(token, &inner_value);
+        }
+    }
+    chain.pop();
+    value
+}
+
+/// Params (sorted) whose placeholder never appeared, given the `used` ones
+pub fn unused_params<'p>(
+    params: &'p HashMap<String, String>,
+    used: &BTreeSet<&str>,
+) -> Vec<&'p str> {
+    let mut unused: Vec<&str> = params
+        .keys()
+        .map(String::as_str)
+        .filter(|key| !used.contains(key))
+        .collect();
+    unused.sort_unstable();
+    unused
+}
+
+/// Resolve a param used as the argument of an argument-taking built-in
+/// (`@file:@path`, `@file:@base/notes.txt`, `@env:@{var}`) to its value, so
+/// the built-in sees the value. Text after the inner placeholder stays part of
+/// the argument.
+///
+/// An inner placeholder with no value is left as typed (reported under `-v`),
+/// and so is the built-in around it.
+fn expand_nested_arguments<'p>(
+    text: &str,
+    ctx: &ExpandContext<'p>,
+    used: &mut BTreeSet<&'p str>,
+) -> String {
+    let mut result = text.to_string();
+    for name in ARGUMENT_TOKENS {
+        let token = ctx.token(name);
+        let nested = format!("{}{}", token, ctx.prefix);
+        if !result.contains(&nested) {
+            continue;
+        }
+
+        let mut expanded = String::with_capacity(result.len());
+        let mut rest = result.as_str();
+        while let Some(pos) = rest.find(&nested) {
+            expanded.push_str(&rest[..pos + token.len()]);
+            let after = &rest[pos + nested.len()..];
+            let (inner, len) = inner_reference(after);
+            match ctx.params.get_key_value(inner) {
+                Some((key, _)) => {
+                    used.insert(key.as_str());
+                    expanded.push_str(&resolve_param(key, ctx, &mut Vec::new(), used));
+                }
+                None => {
+                    log::debug!("No value for {} in {}", ctx.token(inner), token);
+                    expanded.push(ctx.prefix);
+                    expanded.push_str(&after[..len]);
+                }
+            }
+            rest = &after[len..];
+        }
+        expanded.push_str(rest);
+        result = expanded;
+    }
+    result
+}
+
+/// The param an inner placeholder names, given the text after its prefix, and
+/// the length of the reference: the braced name of `{name}`, else the run of
+/// characters a bare name can have (`[A-Za-z0-9_-]`)
+fn inner_reference(after_prefix: &str) -> (&str, usize) {
+    if let Some(name) = after_prefix.strip_prefix('{').and_then(braced_name) {
+        return (name, name.len() + 2);
+    }
+    let len = after_prefix
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(after_prefix.len());
+    (&after_prefix[..len], len)
+}
+
+/// Expand `<placeholder>[N]` (e.g. `@files[2]`) to the Nth (1-based)
+/// comma-separated item of `value`.
+///
+/// Out-of-range indices expand to nothing (logged at debug level); text
+/// that isn't a well-formed index is left for the plain `@key` replacement.
+fn expand_indexed(text: &str, placeholder: &str, value: &str) -> String {
+    let prefix = format!("{}[", placeholder);
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find(&prefix) {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + prefix.len()..];
+        let index = after
+            .split_once(']')
+            .and_then(|(digits, _)| digits.parse::<usize>().ok().map(|i| (i, digits.len())));
+        let Some((index, len)) = index else {
+            result.push_str(&rest[pos..pos + prefix.len()]);
+            rest = after;
+            continue;
+        };
+
+        let items: Vec<&str> = value.split(',').map(str::trim).collect();
+        match index.checked_sub(1).and_then(|i| items.get(i)) {
+            Some(item) => result.push_str(item),
+            None => log::debug!(
+                "{}[{}] is out of range ({} items)",
+                placeholder,
+                index,
+                items.len()
+            ),
+        }
+        rest = &after[len + 1..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// Expand built-in special tokens like @clipboard, also returning the names
+/// of the single-valued ones (clipboard, git, `@selection-file`) that
+/// resolved to nothing
+fn expand_builtins(text: &str, ctx: &ExpandContext) -> (String, BTreeSet<&'static str>) {
+    let mut result = text.to_string();
+    let mut empty = BTreeSet::new();
+    let mut note_empty = |name: &'static str, value: &str| {
+        if value.is_empty() {
+            empty.insert(name);
+        }
+    };
+    // A param named like a built-in wins, as it does in the braced form
+    let shadowed = |name: &str| ctx.params.contains_key(name);
+
+    // `@clipboard-image` must go before `@clipboard`, which is its prefix
+    let image_token = ctx.token("clipboard-image");
+    if !shadowed("clipboard-image") && result.contains(&image_token) {
+        let path = ctx
+            .clipboard
+            .read_image()
+            .and_then(|image| {
+                clipboard::save_image(&image, &std::env::temp_dir())
+                    .map_err(|e| log::info!("{:#}", e))
+                    .ok()
+            })
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        note_empty("clipboard-image", &path);
+        result = result.replace(&image_token, &path);
+    }
+
+    let clipboard_token = ctx.token("clipboard");
+    if !shadowed("clipboard") && result.contains(&clipboard_token) {
+        let clipboard_text =
+            clipboard::read_preferred(ctx.clipboard, ctx.clipboard_format).unwrap_or_default();
+        // `@clipboard:path` must go first, since `@clipboard` is its prefix
+        let path_token = ctx.token("clipboard:path");
+        if result.contains(&path_token) {
+            note_empty("clipboard:path", &clipboard_text);
+            result = result.replace(&path_token, &clipboard_as_path(&clipboard_text));
+        }
+        if result.contains(&clipboard_token) {
+            note_empty("clipboard", &clipboard_text);
+        }
+        result = result.replace(&clipboard_token, &clipboard_text);
+    }
+
+    let git_root_token = ctx.token("git-root");
+    if !shadowed("git-root") && result.contains(&git_root_token) {
+        let root = ctx
+            .cwd
+            .as_deref()
+            .map(|cwd| git::find_git_root(cwd).unwrap_or_else(|| cwd.to_path_buf()))
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+        note_empty("git-root", &root);
+        result = result.replace(&git_root_token, &root);
+    }
+
+    let branch_token = ctx.token("git-branch");
+    if !shadowed("git-branch") && result.contains(&branch_token) {
+        let branch = ctx
+            .cwd
+            .as_deref()
+            .and_then(git::current_branch)
+            .unwrap_or_default();
+        note_empty("git-branch", &branch);
+        result = result.replace(&branch_token, &branch);
+    }
+
+    let diff_token = ctx.token("git-diff");
+    if !shadowed("git-diff") && result.contains(&diff_token) {
+        let diff = ctx
+            .cwd
+            .as_deref()
+            .and_then(git::staged_diff)
+            .map(|diff| git::truncate(&diff, ctx.git_diff_max_bytes))
+            .unwrap_or_default();
+        note_empty("git-diff", &diff);
+        result = result.replace(&diff_token, &diff);
+    }
+
+    let selection_token = ctx.token("selection-file");
+    if !shadowed("selection-file") && result.contains(&selection_token) {
+        let selection = read_selection_file(ctx);
+        note_empty("selection-file", &selection);
+        result = result.replace(&selection_token, &selection);
+    }
+
+    if result.contains(&ctx.token(FILE_TOKEN)) {
+        result = expand_file_tokens(&result, ctx);
+    }
+
+    if result.contains(&ctx.token(FILES_TOKEN)) {
+        result = expand_files_tokens(&result, ctx);
+    }
+
+    let env_token = ctx.token(ENV_TOKEN);
+    if result.contains(&env_token) {
+        result = expand_env_tokens(&result, &env_token);
+    }
+
+    let sysinfo_token = ctx.token("sysinfo");
+    if !shadowed("sysinfo") && result.contains(&sysinfo_token) {
+        let shell = std::env::var("SHELL")
+            .or_else(|_| std::env::var("ComSpec"))
+            .ok();
+        let info = format_sysinfo(
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            shell.as_deref(),
+        );
+        result = result.replace(&sysinfo_token, &info);
+    }
+
+    if DATETIME_TOKENS
+        .iter()
+        .any(|(name, _)| !shadowed(name) && result.contains(&ctx.token(name)))
+    {
+        let now = chrono::Local::now().fixed_offset();
+        result = expand_datetime_tokens(&result, ctx.prefix, ctx.params, now);
+    }
+
+    // Last, so placeholders inside param values are listed verbatim
+    let param_list_token = ctx.token("param-list");
+    if !shadowed("param-list") && result.contains(&param_list_token) {
+        result = result.replace(&param_list_token, &format_param_list(ctx.params));
+    }
+
+    (result, empty)
+}
+
+/// Expand the braced forms of built-ins (`@{git-branch}`, `@{file:notes.md}`).
+///
+/// Braced params are left for the param pass, and a braced name that isn't a
+/// built-in stays literal. `@{cursor}` becomes a plain `@cursor` marker.
+/// Built-ins that resolve to nothing are added to `empty`.
+fn expand_braced_builtins(
+    text: &str,
+    ctx: &ExpandContext,
+    empty: &mut BTreeSet<&'static str>,
+) -> String {
+    let open = format!("{}{{", ctx.prefix);
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find(&open) {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + open.len()..];
+        let Some(name) = braced_name(after) else {
+            result.push_str(&open);
+            rest = after;
+            continue;
+        };
+
+        let token = ctx.token(name);
+        if name == CURSOR_TOKEN {
+            result.push_str(&token);
+        } else if is_builtin_name(name) && !ctx.params.contains_key(name) {
+            let (value, resolved_empty) = expand_builtins(&token, ctx);
+            result.push_str(&value);
+            empty.extend(resolved_empty);
+        } else {
+            result.push_str(&rest[pos..pos + open.len() + name.len() + 1]);
+        }
+        rest = &after[name.len() + 1..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// The name of a braced token, given the text after its `@{`: everything up
+/// to the closing `}`, which must be non-empty and free of whitespace and `{`
+pub fn braced_name(after_open: &str) -> Option<&str> {
+    let (name, _) = after_open.split_once('}')?;
+    let valid = !name.is_empty() && !name.contains(|c: char| c == '{' || c.is_whitespace());
+    valid.then_some(name)
+}
+
+/// Whether `name` is a built-in, or an argument-taking one with its argument
+/// (`file:notes.md`)
+pub fn is_builtin_name(name: &str) -> bool {
+    BUILTIN_PLACEHOLDERS.contains(&name)
+        || name == "clipboard:path"
+        || ARGUMENT_TOKENS
+            .iter()
+            .any(|token| name.len() > token.len() && name.starts_with(token))
+}
+
+/// The braced token for `name`, e.g. `@{some.file}`
+pub fn braced_token(name: &str, prefix: char) -> String {
+    format!("{}{{{}}}", prefix, name)
+}
+
+/// Whether `name` can only be written braced: a dot ends a bare `@name`
+pub fn needs_braces(name: &str) -> bool {
+    name.contains('.')
+}
+
+/// Name part of the `@env:<NAME>` built-in
+const ENV_TOKEN: &str = "env:";
+
+/// Replace each `@env:NAME` (`token` is the prefixed `@env:`) with the
+/// variable's value, or nothing if unset.
+///
+/// The name is the run of `[A-Za-z0-9_]` after the colon. A placeholder left
+/// in its place (`@env:@var` with no `var` param) stays as typed.
+fn expand_env_tokens(text: &str, token: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    let prefix = &token[..1];
+
+    while let Some(pos) = rest.find(token) {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + token.len()..];
+        let end = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let name = &after[..end];
+        if !name.is_empty() {
+            result.push_str(&std::env::var(name).unwrap_or_default());
+        } else if after.starts_with(prefix) {
+            result.push_str(token);
+        }
+        rest = &after[end..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// `@date` / `@time` names and their default (ISO-8601) formats
+const DATETIME_TOKENS: &[(&str, &str)] = &[("date", "%Y-%m-%d"), ("time", "%H:%M:%S")];
+
+/// Expand `@date`, `@time` and their `@date{<strftime>}` forms using `now`.
+///
+/// A token directly followed by a letter, digit or `_` is a different name and
+/// left alone; so is a token whose format string chrono can't parse, and one
+/// that is also a key of `params`, which the param pass expands instead.
+fn expand_datetime_tokens(
+    text: &str,
+    prefix: char,
+    params: &HashMap<String, String>,
+    now: chrono::DateTime<chrono::FixedOffset>,
+) -> String {
+    let mut result = text.to_string();
+
+    for (name, default_format) in DATETIME_TOKENS {
+        if params.contains_key(*name) {
+            continue;
+        }
+        let token = format!("{}{}", prefix, name);
+        let token = token.as_str();
+        let mut expanded = String::with_capacity(result.len());
+        let mut rest = result.as_str();
+
+        while let Some(pos) = rest.find(token) {
+            expanded.push_str(&rest[..pos]);
+            let after = &rest[pos + token.len()..];
+            if after.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+                expanded.push_str(token);
+                rest = after;
+                continue;
+            }
+
+            let (format, consumed) =
+                match after.strip_prefix('{').and_then(|arg| arg.split_once('}')) {
+                    Some((format, _)) => (format, format.len() + 2),
+                    None => (*default_format, 0),
+                };
+            match format_datetime(&now, format) {
+                Some(value) => expanded.push_str(&value),
+                None => expanded.push_str(&rest[pos..pos + token.len() + consumed]),
+            }
+            rest = &after[consumed..];
+        }
+        expanded.push_str(rest);
+        result = expanded;
+    }
+
+    result
+}
+
+/// Format `now` with a strftime-style string, or `None` if it's invalid
+fn format_datetime(now: &chrono::DateTime<chrono::FixedOffset>, format: &str) -> Option<String> {
+    use chrono::format::{Item, StrftimeItems};
+
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return None;
+    }
+    Some(now.format_with_items(items.into_iter()).to_string())
+}
+
+/// Name part of the `@file:<path>` built-in
+const FILE_TOKEN: &str = "file:";
+
+/// Name part of the `@files:<glob>` built-in
+const FILES_TOKEN: &str = "files:";
+
+/// Name part of the `@cursor` caret marker
+const CURSOR_TOKEN: &str = "cursor";
+
+/// Remove every `@cursor` from the final prompt, returning the text and the
+/// character offset of the first one (logged under `--debug`).
+///
+/// OpenCode's TUI API has no documented event to move the caret, so the
+/// offset isn't sent anywhere. Runs after post-processing and redaction, so it
+/// matches the text that is actually sent.
+pub fn take_cursor(text: &str, prefix: char) -> (String, Option<usize>) {
+    let token = format!("{}{}", prefix, CURSOR_TOKEN);
+    let offset = text.find(&token).map(|pos| text[..pos].chars().count());
+    if let Some(offset) = offset {
+        log::debug!("Cursor offset: {}", offset);
+    }
+    (text.replace(&token, ""), offset)
+}
+
+/// Replace each `@file:<path>` with the file's contents.
+///
+/// The path runs to the next whitespace and is resolved against the context's
+/// working directory. Unreadable files expand to nothing (reported under
+/// `-v`); non-UTF-8 bytes are decoded lossily. A path that is still a
+/// placeholder (no value for it) stays as typed.
+fn expand_file_tokens(text: &str, ctx: &ExpandContext) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    let token = ctx.token(FILE_TOKEN);
+    while let Some(pos) = rest.find(&token) {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + token.len()..];
+        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+        let path = &after[..end];
+        if path.starts_with(ctx.prefix) {
+            result.push_str(&token);
+            result.push_str(path);
+        } else {
+            result.push_str(&read_file_token(path, ctx));
+        }
+        rest = &after[end..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// Read the file named by an `@file:` token, or an empty string on failure
+fn read_file_token(path: &str, ctx: &ExpandContext) -> String {
+    let full = match &ctx.cwd {
+        Some(cwd) => cwd.join(path),
+        None => PathBuf::from(path),
+    };
+    match std::fs::read(&full) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(e) => {
+            log::info!("Failed to read @file:{} ({}): {}", path, full.display(), e);
+            String::new()
+        }
+    }
+}
+
+/// Replace each `@files:<glob>` with the matching file paths, one per line.
+///
+/// The pattern runs to the next whitespace, like an `@file:` path, and a
+/// pattern that is still a placeholder stays as typed.
+fn expand_files_tokens(text: &str, ctx: &ExpandContext) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    let token = ctx.token(FILES_TOKEN);
+    while let Some(pos) = rest.find(&token) {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + token.len()..];
+        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+        let pattern = &after[..end];
+        if pattern.starts_with(ctx.prefix) {
+            result.push_str(&token);
+            result.push_str(pattern);
+        } else {
+            result.push_str(&glob_files(pattern, ctx).join("\n"));
+        }
+        rest = &after[end..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// Files matching `pattern` (resolved against the context's working
+/// directory), sorted, relative to that directory, and capped at
+/// `files_max`. An invalid pattern matches nothing (reported under `-v`).
+fn glob_files(pattern: &str, ctx: &ExpandContext) -> Vec<String> {
+    let full = match &ctx.cwd {
+        Some(cwd) => Path::new(&glob::Pattern::escape(&cwd.to_string_lossy())).join(pattern),
+        None => PathBuf::from(pattern),
+    };
+    let matches = match glob::glob(&full.to_string_lossy()) {
+        Ok(paths) => paths,
+        Err(e) => {
+            log::info!("Invalid @files:{} pattern: {}", pattern, e);
+            return Vec::new();
+        }
+    };
+    let mut files: Vec<String> = matches
+        .filter_map(|entry| entry.map_err(|e| log::debug!("{}", e)).ok())
+        .filter(|path| path.is_file())
+        .map(|path| {
+            let relative = ctx
+                .cwd
+                .as_deref()
+                .and_then(|cwd| path.strip_prefix(cwd).ok())
+                .unwrap_or(&path);
+            relative.display().to_string()
+        })
+        .collect();
+    files.sort();
+    if files.len() > ctx.files_max {
+        log::info!(
+            "@files:{} matched {} files, listing the first {}",
+            pattern,
+            files.len(),
+            ctx.files_max
+        );
+        files.truncate(ctx.files_max);
+    }
+    files
+}
+
+/// Contents of `--selection-path` without its trailing newline, or an empty
+/// string if it isn't set or can't be read
+fn read_selection_file(ctx: &ExpandContext) -> String {
+    let Some(path) = &ctx.selection_path else {
+        return String::new();
+    };
+    match std::fs::read(path) {
+        Ok(bytes) => {
+            let text = String::from_utf8_lossy(&bytes);
+            let text = text.strip_suffix('\n').unwrap_or(&text);
+            text.strip_suffix('\r').unwrap_or(text).to_string()
+        }
+        Err(e) => {
+            log::info!("Failed to read selection file {}: {}", path.display(), e);
+            String::new()
+        }
+    }
+}
+
+/// Format the short system description inlined by `@sysinfo`
+fn format_sysinfo(os: &str, arch: &str, shell: Option<&str>) -> String {
+    format!(
+        "OS: {}\nArch: {}\nShell: {}",
+        os,
+        arch,
+        shell.unwrap_or("unknown")
+    )
+}
+
+/// Longest value `@param-list` shows before cutting it off with `…`
+const PARAM_LIST_VALUE_CHARS: usize = 80;
+
+/// Format `params` as the bulleted `- key: value` block inlined by
+/// `@param-list`, sorted by key. Multi-line values keep their first line only.
+fn format_param_list(params: &HashMap<String, String>) -> String {
+    let mut keys: Vec<&String> = params.keys().collect();
+    keys.sort();
+    keys.iter()
+        .map(|key| {
+            let value = &params[*key];
+            let first_line = value.lines().next().unwrap_or("");
+            let mut shown: String = first_line.chars().take(PARAM_LIST_VALUE_CHARS).collect();
+            if shown.len() < value.len() {
+                shown.push('…');
+            }
+            format!("- {}: {}", key, shown)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Interpret clipboard text as an OpenCode file reference (`@<path>`) when it
+/// names an existing path; otherwise fall back to the text itself.
+fn clipboard_as_path(text: &str) -> String {
+    let candidate = text.trim();
+    if !candidate.is_empty() && !candidate.contains('\n') && Path::new(candidate).exists() {
+        format!("@{}", candidate)
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clipboard provider returning fixed content
+    struct FakeClipboard(Option<String>);
+
+    impl ClipboardProvider for FakeClipboard {
+        fn read_text(&self) -> Option<String> {
+            self.0.clone()
+        }
+    }
+
+    /// Expansion context with no params and a fake clipboard
+    fn ctx_with_clipboard<'a>(
+        params: &'a HashMap<String, String>,
+        clipboard: &'a FakeClipboard,
+    ) -> ExpandContext<'a> {
+        ExpandContext {
+            clipboard,
+            ..ExpandContext::new(params)
+        }
+    }
+
+    /// 2024-01-02 15:04:05 at UTC+7
+    fn fixed_now() -> chrono::DateTime<chrono::FixedOffset> {
+        chrono::DateTime::parse_from_rfc3339("2024-01-02T15:04:05+07:00").unwrap()
+    }
+
+    #[test]
+    fn test_take_cursor() {
+        assert_eq!(
+            take_cursor("Fix @cursor in main", '@'),
+            ("Fix  in main".to_string(), Some(4))
+        );
+        // Only the first marker counts; the rest are dropped too
+        assert_eq!(
+            take_cursor("añb@cursor c @cursor", '@'),
+            ("añb c ".to_string(), Some(3))
+        );
+        assert_eq!(take_cursor("plain", '@'), ("plain".to_string(), None));
+        assert_eq!(
+            take_cursor("a %cursor b @cursor", '%'),
+            ("a  b @cursor".to_string(), Some(2))
+        );
+    }
+
+    #[test]
+    fn test_cursor_survives_expansion() {
+        let params = HashMap::from([("path".to_string(), "src/main.rs".to_string())]);
+        let expanded = expand_placeholders("Open @path then @cursor", &params);
+        assert_eq!(
+            take_cursor(&expanded, '@'),
+            ("Open src/main.rs then ".to_string(), Some(22))
+        );
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let mut params = HashMap::new();
+        params.insert("path".to_string(), "/src/main.rs".to_string());
+        params.insert("selection".to_string(), "fn main()".to_string());
+
+        let text = "Fix the bug in @path near @selection";
+        let result = expand_placeholders(text, &params);
+        assert_eq!(result, "Fix the bug in /src/main.rs near fn main()");
+    }
+
+    #[test]
+    fn test_expand_placeholders_no_match() {
+        let params = HashMap::new();
+        let text = "No placeholders here";
+        let result = expand_placeholders(text, &params);
+        assert_eq!(result, "No placeholders here");
+    }
+
+    #[test]
+    fn test_expand_placeholders_multiple_occurrences() {
+        let mut params = HashMap::new();
+        params.insert("file".to_string(), "test.rs".to_string());
+
+        let text = "Compare @file with @file";
+        let result = expand_placeholders(text, &params);
+        assert_eq!(result, "Compare test.rs with test.rs");
+    }
+
+    #[test]
+    fn test_expand_braced_placeholders() {
+        let mut params = HashMap::new();
+        params.insert("some".to_string(), "A".to_string());
+        params.insert("some.file".to_string(), "B".to_string());
+        params.insert("path".to_string(), "src/main.rs".to_string());
+        params.insert("files".to_string(), "a.rs,b.rs".to_string());
+
+        assert_eq!(expand_placeholders("@{some.file}", &params), "B");
+        // A bare token ends at the dot
+        assert_eq!(expand_placeholders("@some.file", &params), "A.file");
+        // Both forms coexist, and braces mark the end next to letters
+        assert_eq!(
+            expand_placeholders("@path and @{path}s", &params),
+            "src/main.rs and src/main.rss"
+        );
+        assert_eq!(expand_placeholders("@{files}[2]", &params), "b.rs");
+        // Unknown braced names stay literal
+        assert_eq!(expand_placeholders("@{nope} @{}", &params), "@{nope} @{}");
+
+        // Param values may reference params braced too
+        params.insert("note".to_string(), "see @{some.file}".to_string());
+        assert_eq!(expand_placeholders("@note", &params), "see B");
+    }
+
+    #[test]
+    fn test_expand_braced_builtins() {
+        let clipboard = FakeClipboard(Some("copied".to_string()));
+        let params = HashMap::new();
+        let ctx = ctx_with_clipboard(&params, &clipboard);
+
+        assert_eq!(
+            expand_placeholders_with("@{clipboard}-image", &ctx),
+            "copied-image"
+        );
+        // Not a built-in, even though `@clipboard` is its prefix
+        assert_eq!(
+            expand_placeholders_with("@{clipboardx}", &ctx),
+            "@{clipboardx}"
+        );
+        std::env::set_var("PROMPT_DIALOG_TEST_BRACED", "value");
+        assert_eq!(
+            expand_placeholders_with("@{env:PROMPT_DIALOG_TEST_BRACED}.", &ctx),
+            "value."
+        );
+        // `@{cursor}` still marks the caret
+        let expanded = expand_placeholders_with("ab@{cursor}c", &ctx);
+        assert_eq!(take_cursor(&expanded, '@'), ("abc".to_string(), Some(2)));
+    }
+
+    #[test]
+    fn test_expand_longest_key_first() {
+        let mut params = HashMap::new();
+        params.insert("path".to_string(), "short".to_string());
+        params.insert("pathname".to_string(), "long".to_string());
+
+        let text = "Use @pathname and @path";
+        let result = expand_placeholders(text, &params);
+        assert_eq!(result, "Use long and short");
+    }
+
+    #[test]
+    fn test_parse_macros_unescapes_newlines() {
+        let macros = parse_macros(&["context=File: @path\\n---\\n@selection".to_string()]);
+        assert_eq!(
+            macros.get("context").unwrap(),
+            "File: @path\n---\n@selection"
+        );
+    }
+
+    #[test]
+    fn test_expand_macro_with_nested_placeholders() {
+        let mut params = HashMap::new();
+        params.insert("path".to_string(), "/src/main.rs".to_string());
+        params.insert("selection".to_string(), "fn main() {}".to_string());
+        let ctx = ExpandContext {
+            macros: parse_macros(&["context=File: @path\\n---\\n@selection".to_string()]),
+            ..ExpandContext::new(&params)
+        };
+
+        let result = expand_placeholders_with("Explain:\n@context", &ctx);
+        assert_eq!(result, "Explain:\nFile: /src/main.rs\n---\nfn main() {}");
+    }
+
+    #[test]
+    fn test_expand_macro_referencing_macro() {
+        let macros = parse_macros(&["outer=[@inner]".to_string(), "inner=@path".to_string()]);
+        assert_eq!(expand_macros("@outer", &macros, '@'), "[@path]");
+    }
+
+    #[test]
+    fn test_expand_macro_cycle_terminates() {
+        let macros = parse_macros(&["loop=again @loop".to_string()]);
+        let result = expand_macros("@loop", &macros, '@');
+        assert_eq!(result.matches("again").count(), MAX_MACRO_DEPTH);
+        assert!(result.ends_with("@loop"));
+    }
+
+    #[test]
+    fn test_expand_nested_params() {
+        let mut params = HashMap::new();
+        params.insert("base".to_string(), "/home/me".to_string());
+        params.insert("dir".to_string(), "@base/notes".to_string());
+        params.insert("file".to_string(), "@dir/todo.txt".to_string());
+
+        assert_eq!(
+            expand_placeholders("open @file", &params),
+            "open /home/me/notes/todo.txt"
+        );
+
+        let ctx = ExpandContext::new(&params);
+        let used = expand_placeholders_tracked("@file", &ctx).used;
+        assert_eq!(unused_params(&params, &used), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_expand_param_cycles_stay_literal() {
+        let mut params = HashMap::new();
+        params.insert("a".to_string(), "@a".to_string());
+        assert_eq!(expand_placeholders("x @a y", &params), "x @a y");
+
+        let mut params = HashMap::new();
+        params.insert("ping".to_string(), "<@pong>".to_string());
+        params.insert("pong".to_string(), "[@ping]".to_string());
+        assert_eq!(expand_placeholders("@ping", &params), "<[@ping]>");
+    }
+
+    #[test]
+    fn test_expand_param_depth_limit() {
+        // p0=@p1, p1=@p2, ... p11=end: the param MAX_PARAM_DEPTH levels down
+        // is left unresolved
+        let params: HashMap<String, String> = (0..12)
+            .map(|i| {
+                let value = if i == 11 {
+                    "end".to_string()
+                } else {
+                    format!("@p{}", i + 1)
+                };
+                (format!("p{}", i), value)
+            })
+            .collect();
+        let ctx = ExpandContext::new(&params);
+        let value = resolve_param("p0", &ctx, &mut Vec::new(), &mut BTreeSet::new());
+        assert_eq!(value, format!("@p{}", MAX_PARAM_DEPTH + 1));
+    }
+
+    #[test]
+    fn test_expand_nested_param_keeps_builtins_single_pass() {
+        let mut params = HashMap::new();
+        params.insert("note".to_string(), "see @clipboard".to_string());
+        let clipboard = FakeClipboard(Some("copied".to_string()));
+        let ctx = ctx_with_clipboard(&params, &clipboard);
+        assert_eq!(
+            expand_placeholders_with("@clipboard / @note", &ctx),
+            "copied / see @clipboard"
+        );
+    }
+
+    #[test]
+    fn test_unused_params() {
+        let mut params = HashMap::new();
+        params.insert("path".to_string(), "src/main.rs".to_string());
+        params.insert("pathname".to_string(), "main".to_string());
+        params.insert("files".to_string(), "a.rs,b.rs".to_string());
+        params.insert("lang".to_string(), "rust".to_string());
+        params.insert("stale".to_string(), "x".to_string());
+        let ctx = ExpandContext::new(&params);
+
+        let Expansion {
+            text: result, used, ..
+        } = expand_placeholders_tracked("@pathname in @lang, @files[2]", &ctx);
+        assert_eq!(result, "main in rust, b.rs");
+        assert_eq!(unused_params(&params, &used), vec!["path", "stale"]);
+
+        let used = expand_placeholders_tracked("@path @pathname @files @lang @stale", &ctx).used;
+        assert!(unused_params(&params, &used).is_empty());
+    }
+
+    #[test]
+    fn test_expand_indexed_list_param() {
+        let mut params = HashMap::new();
+        params.insert("files".to_string(), "a.rs, b.rs,c.rs".to_string());
+
+        let result = expand_placeholders("Compare @files[1] and @files[3] (@files)", &params);
+        assert_eq!(result, "Compare a.rs and c.rs (a.rs, b.rs,c.rs)");
+    }
+
+    #[test]
+    fn test_expand_indexed_out_of_range() {
+        let mut params = HashMap::new();
+        params.insert("files".to_string(), "a.rs,b.rs".to_string());
+
+        assert_eq!(expand_placeholders("[@files[3]]", &params), "[]");
+        assert_eq!(expand_placeholders("[@files[0]]", &params), "[]");
+        // Not an index: falls through to the plain placeholder
+        assert_eq!(expand_placeholders("@files[x]", &params), "a.rs,b.rs[x]");
+    }
+
+    #[test]
+    fn test_expand_file_relative_to_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+
+        let params = HashMap::new();
+        let ctx = ExpandContext::new(&params).with_cwd(dir.path());
+        let result = expand_placeholders_with("Review @file:src/main.rs next", &ctx);
+        assert_eq!(result, "Review fn main() {} next");
+    }
+
+    #[test]
+    fn test_expand_without_builtins() {
+        let mut params = HashMap::new();
+        params.insert("path".to_string(), "src/main.rs".to_string());
+        let clipboard = FakeClipboard(Some("pasted".to_string()));
+        let ctx = ExpandContext {
+            macros: parse_macros(&["ctx=File: @path".to_string()]),
+            ..ctx_with_clipboard(&params, &clipboard)
+        };
+        assert_eq!(
+            expand_without_builtins("@ctx @{path} @clipboard @git-branch @file:x", &ctx),
+            "File: src/main.rs src/main.rs @clipboard @git-branch @file:x"
+        );
+    }
+
+    #[test]
+    fn test_expand_files_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "src/main.rs",
+            "src/server/mod.rs",
+            "src/server/client.rs",
+            "README.md",
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let params = HashMap::new();
+        let ctx = ExpandContext::new(&params).with_cwd(dir.path());
+        let result = expand_placeholders_with("Files:\n@files:src/**/*.rs\nok", &ctx);
+        assert_eq!(
+            result,
+            "Files:\nsrc/main.rs\nsrc/server/client.rs\nsrc/server/mod.rs\nok"
+        );
+        // Directories aren't listed; no match or a bad pattern is empty
+        assert_eq!(
+            expand_placeholders_with("@files:src/* end", &ctx),
+            "src/main.rs end"
+        );
+        assert_eq!(expand_placeholders_with("A @files:*.txt B", &ctx), "A  B");
+        assert_eq!(expand_placeholders_with("A @files:[ B", &ctx), "A  B");
+
+        let ctx = ExpandContext {
+            files_max: 2,
+            ..ExpandContext::new(&params).with_cwd(dir.path())
+        };
+        assert_eq!(
+            expand_placeholders_with("@files:**/*.rs", &ctx),
+            "src/main.rs\nsrc/server/client.rs"
+        );
+    }
+
+    #[test]
+    fn test_expand_file_missing_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let params = HashMap::new();
+        let ctx = ExpandContext::new(&params).with_cwd(dir.path());
+        let result = expand_placeholders_with("A @file:nope.rs B", &ctx);
+        assert_eq!(result, "A  B");
+    }
+
+    #[test]
+    fn test_expand_file_from_inner_placeholder() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+
+        let mut params = HashMap::new();
+        params.insert("path".to_string(), "src/main.rs".to_string());
+        params.insert("var".to_string(), "PROMPT_DIALOG_TEST_NESTED".to_string());
+        std::env::set_var("PROMPT_DIALOG_TEST_NESTED", "nested-ok");
+        let ctx = ExpandContext::new(&params).with_cwd(dir.path());
+
+        let Expansion {
+            text: result, used, ..
+        } = expand_placeholders_tracked("Review @file:@path (@path) @env:@var", &ctx);
+        assert_eq!(result, "Review fn main() {} (src/main.rs) nested-ok");
+        assert_eq!(unused_params(&params, &used), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_expand_file_missing_inner_placeholder() {
+        let dir = tempfile::tempdir().unwrap();
+        let params = HashMap::new();
+        let ctx = ExpandContext::new(&params).with_cwd(dir.path());
+        let result = expand_placeholders_with("A @file:@path B @env:@var C @files:@{g}", &ctx);
+        assert_eq!(result, "A @file:@path B @env:@var C @files:@{g}");
+    }
+
+    #[test]
+    fn test_expand_file_inner_placeholder_with_suffix() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/x.rs"), "fn x() {}").unwrap();
+
+        let mut params = HashMap::new();
+        params.insert("base".to_string(), "src".to_string());
+        params.insert("dir.name".to_string(), "src".to_string());
+        let ctx = ExpandContext::new(&params).with_cwd(dir.path());
+        let result = expand_placeholders_with("@file:@base/x.rs | @file:@{dir.name}/x.rs", &ctx);
+        assert_eq!(result, "fn x() {} | fn x() {}");
+
+        // A missing inner value keeps the text, suffix included
+        let result = expand_placeholders_with("@file:@other/x.rs", &ctx);
+        assert_eq!(result, "@file:@other/x.rs");
+    }
+
+    #[test]
+    fn test_expand_file_lossy_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("blob.bin"), [b'o', b'k', 0xff, 0xfe]).unwrap();
+        let params = HashMap::new();
+        let ctx = ExpandContext::new(&params).with_cwd(dir.path());
+        let result = expand_placeholders_with("@file:blob.bin", &ctx);
+        assert_eq!(result, "ok\u{FFFD}\u{FFFD}");
+    }
+
+    #[test]
+    fn test_format_sysinfo() {
+        let info = format_sysinfo("linux", "x86_64", Some("/bin/zsh"));
+        assert_eq!(info, "OS: linux\nArch: x86_64\nShell: /bin/zsh");
+        assert_eq!(info, format_sysinfo("linux", "x86_64", Some("/bin/zsh")));
+        assert!(format_sysinfo("macos", "aarch64", None).ends_with("Shell: unknown"));
+    }
+
+    #[test]
+    fn test_format_param_list() {
+        let params = HashMap::from([
+            ("selection".to_string(), "fn main() {\n}".to_string()),
+            ("path".to_string(), "/src/main.rs".to_string()),
+            ("long".to_string(), "x".repeat(PARAM_LIST_VALUE_CHARS + 1)),
+            ("empty".to_string(), String::new()),
+        ]);
+        let expected = format!(
+            "- empty: \n- long: {}…\n- path: /src/main.rs\n- selection: fn main() {{…",
+            "x".repeat(PARAM_LIST_VALUE_CHARS)
+        );
+        assert_eq!(format_param_list(&params), expected);
+        assert_eq!(format_param_list(&HashMap::new()), "");
+    }
+
+    #[test]
+    fn test_expand_param_list() {
+        let params = HashMap::from([
+            ("path".to_string(), "/src/main.rs".to_string()),
+            ("ref".to_string(), "@file:notes.md".to_string()),
+        ]);
+        assert_eq!(
+            expand_placeholders("Context:\n@param-list\nUse @path", &params),
+            "Context:\n- path: /src/main.rs\n- ref: @file:notes.md\nUse /src/main.rs"
+        );
+    }
+
+    #[test]
+    fn test_expand_sysinfo_contains_os_and_arch() {
+        let params = HashMap::new();
+        let result = expand_placeholders("Env:\n@sysinfo", &params);
+        assert!(result.starts_with("Env:\nOS: "));
+        assert!(result.contains(std::env::consts::OS));
+        assert!(result.contains(std::env::consts::ARCH));
+    }
+
+    #[test]
+    fn test_expand_git_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let nested = repo.join("src");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(&nested).unwrap();
+
+        let params = HashMap::new();
+        let ctx = ExpandContext::new(&params).with_cwd(&nested);
+        let result = expand_placeholders_with("Root: @git-root", &ctx);
+        assert_eq!(result, format!("Root: {}", repo.display()));
+    }
+
+    #[test]
+    fn test_expand_selection_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("selection.txt");
+        std::fs::write(&path, "fn main() {}\n\n").unwrap();
+        let params = HashMap::new();
+        let ctx = ExpandContext {
+            selection_path: Some(path.clone()),
+            ..ExpandContext::new(&params)
+        };
+        // Only one trailing newline is dropped
+        assert_eq!(
+            expand_placeholders_with("Explain @selection-file", &ctx),
+            "Explain fn main() {}\n"
+        );
+
+        std::fs::write(&path, "a\r\n").unwrap();
+        assert_eq!(expand_placeholders_with("[@selection-file]", &ctx), "[a]");
+    }
+
+    #[test]
+    fn test_expand_selection_file_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut params = HashMap::new();
+        params.insert("selection".to_string(), "inline".to_string());
+        let ctx = ExpandContext {
+            selection_path: Some(dir.path().join("missing.txt")),
+            ..ExpandContext::new(&params)
+        };
+        assert_eq!(
+            expand_placeholders_with("[@selection-file] @selection", &ctx),
+            "[] inline"
+        );
+
+        let ctx = ExpandContext::new(&params);
+        assert_eq!(expand_placeholders_with("[@selection-file]", &ctx), "[]");
+    }
+
+    #[test]
+    fn test_expand_git_tokens_outside_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        if git::find_git_root(dir.path()).is_some() {
+            return;
+        }
+        let params = HashMap::new();
+        let ctx = ExpandContext::new(&params).with_cwd(dir.path());
+        let result = expand_placeholders_with("[@git-branch] [@git-diff]", &ctx);
+        assert_eq!(result, "[] []");
+    }
+
+    #[test]
+    fn test_expand_git_root_without_cwd() {
+        let params = HashMap::new();
+        let result = expand_placeholders_with("Root: @git-root", &ExpandContext::new(&params));
+        assert_eq!(result, "Root: ");
+    }
+
+    #[test]
+    fn test_expand_env_var() {
+        std::env::set_var("PROMPT_DIALOG_TEST_PROJECT", "crate-x");
+        let params = HashMap::new();
+        let result = expand_placeholders("Working on @env:PROMPT_DIALOG_TEST_PROJECT.", &params);
+        assert_eq!(result, "Working on crate-x.");
+    }
+
+    #[test]
+    fn test_expand_env_unset_is_empty() {
+        std::env::remove_var("PROMPT_DIALOG_TEST_UNSET");
+        let params = HashMap::new();
+        let result = expand_placeholders("[@env:PROMPT_DIALOG_TEST_UNSET]", &params);
+        assert_eq!(result, "[]");
+    }
+
+    #[test]
+    fn test_expand_date_time_defaults() {
+        let result = expand_datetime_tokens("Log @date @time.", '@', &HashMap::new(), fixed_now());
+        assert_eq!(result, "Log 2024-01-02 15:04:05.");
+    }
+
+    #[test]
+    fn test_expand_date_custom_format() {
+        let result = expand_datetime_tokens(
+            "@date{%Y/%m/%d} @time{%H:%M}",
+            '@',
+            &HashMap::new(),
+            fixed_now(),
+        );
+        assert_eq!(result, "2024/01/02 15:04");
+    }
+
+    #[test]
+    fn test_expand_date_leaves_other_names_and_bad_formats() {
+        let result = expand_datetime_tokens(
+            "@dates @timeline @date{%Q}",
+            '@',
+            &HashMap::new(),
+            fixed_now(),
+        );
+        assert_eq!(result, "@dates @timeline @date{%Q}");
+        // Unclosed brace: plain @date, the brace stays
+        assert_eq!(
+            expand_datetime_tokens("@date{%Y", '@', &HashMap::new(), fixed_now()),
+            "2024-01-02{%Y"
+        );
+    }
+
+    #[test]
+    fn test_param_shadows_bare_builtin() {
+        let mut params = HashMap::new();
+        params.insert("date".to_string(), "release day".to_string());
+        params.insert("sysinfo".to_string(), "n/a".to_string());
+        let result = expand_placeholders("@date @{date} @sysinfo", &params);
+        assert_eq!(result, "release day release day n/a");
+
+        let result = expand_datetime_tokens("@date @time", '@', &params, fixed_now());
+        assert_eq!(result, "@date 15:04:05");
+    }
+
+    #[test]
+    fn test_custom_prefix_leaves_at_alone() {
+        let mut params = HashMap::new();
+        params.insert("user".to_string(), "me@example.com".to_string());
+        params.insert("example".to_string(), "WRONG".to_string());
+        let ctx = ExpandContext {
+            prefix: '$',
+            ..ExpandContext::new(&params)
+        };
+
+        let result = expand_placeholders_with("Mail $user, keep @user and @date", &ctx);
+        assert_eq!(result, "Mail me@example.com, keep @user and @date");
+    }
+
+    #[test]
+    fn test_expand_clipboard_token() {
+        // @clipboard expands to whatever is on the system clipboard.
+        // We can't control clipboard in CI, so just verify it doesn't panic
+        // and the token is consumed (replaced with something).
+        let params = HashMap::new();
+        let text = "Paste: @clipboard";
+        let result = expand_placeholders(text, &params);
+        assert!(!result.contains("@clipboard"));
+    }
+
+    #[test]
+    fn test_expand_clipboard_with_params() {
+        let mut params = HashMap::new();
+        params.insert("file".to_string(), "main.rs".to_string());
+
+        let text = "Fix @file using @clipboard";
+        let result = expand_placeholders(text, &params);
+        assert!(!result.contains("@clipboard"));
+        assert!(result.contains("main.rs"));
+    }
+
+    #[test]
+    fn test_expand_clipboard_path_existing() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let clipboard = FakeClipboard(Some(format!("{}\n", path)));
+        let params = HashMap::new();
+        let ctx = ctx_with_clipboard(&params, &clipboard);
+        let (result, _) = expand_builtins("Look at @clipboard:path", &ctx);
+        assert_eq!(result, format!("Look at @{}", path));
+    }
+
+    #[test]
+    fn test_expand_clipboard_path_not_a_path() {
+        let clipboard = FakeClipboard(Some("just some text".to_string()));
+        let params = HashMap::new();
+        let ctx = ctx_with_clipboard(&params, &clipboard);
+        let (result, _) = expand_builtins("Look at @clipboard:path", &ctx);
+        assert_eq!(result, "Look at just some text");
+    }
+
+    #[test]
+    fn test_expand_clipboard_image_without_image() {
+        let clipboard = FakeClipboard(Some("text".to_string()));
+        let params = HashMap::new();
+        let ctx = ctx_with_clipboard(&params, &clipboard);
+        let (result, _) = expand_builtins("See @clipboard-image, not @clipboard", &ctx);
+        assert_eq!(result, "See , not text");
+    }
+
+    #[test]
+    fn test_expand_clipboard_text_and_path_together() {
+        let clipboard = FakeClipboard(Some("no/such/file.rs".to_string()));
+        let params = HashMap::new();
+        let ctx = ctx_with_clipboard(&params, &clipboard);
+        let (result, _) = expand_builtins("@clipboard:path and @clipboard", &ctx);
+        assert_eq!(result, "no/such/file.rs and no/such/file.rs");
+    }
+}
@@ -0,0 +1,455 @@
+//! The submit pipeline: what turns the typed prompt into the text that is sent
+//!
+//! Every way of sending (the dialog, the prompt builder, a headless send and
+//! `--dry-run`) goes through `finalize_prompt`, so they all check, redact and
+//! cut a prompt the same way.
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::expand::{
+    braced_name, braced_token, expand_placeholders_tracked, is_builtin_name, parse_macros,
+    take_cursor, unused_params, ExpandContext, Expansion, BUILTIN_PLACEHOLDERS,
+};
+use crate::redact::Redactor;
+use crate::{autofence, log_info, Cli};
+
+/// Post-expansion processing applied to every outgoing prompt
+#[derive(Debug, Clone, Default)]
+pub struct PostProcess {
+    /// Wrap code-looking paragraphs in markdown fences
+    pub auto_fence: bool,
+    /// Tag of the envelope wrapped around the whole prompt
+    pub envelope: Option<String>,
+}
+
+impl PostProcess {
+    pub fn from_cli(cli: &Cli) -> Self {
+        Self {
+            auto_fence: cli.auto_fence,
+            envelope: cli.envelope.clone(),
+        }
+    }
+
+    /// Apply every enabled step to an expanded prompt
+    fn apply(&self, text: String) -> String {
+        let text = if self.auto_fence {
+            autofence::auto_fence(&text)
+        } else {
+            text
+        };
+        match &self.envelope {
+            Some(tag) => wrap_envelope(&text, tag),
+            None => text,
+        }
+    }
+}
+
+/// Wrap `text` as `<tag>text</tag>`, escaping angle brackets so the content
+/// can't close or open tags of its own
+fn wrap_envelope(text: &str, tag: &str) -> String {
+    let escaped = text.replace('<', "&lt;").replace('>', "&gt;");
+    format!("<{}>\n{}\n</{}>", tag, escaped, tag)
+}
+
+/// Expand placeholders and apply post-processing, producing the text to send
+pub fn prepare_prompt<'p>(
+    text: &str,
+    ctx: &ExpandContext<'p>,
+    post: &PostProcess,
+) -> Expansion<'p> {
+    let mut expansion = expand_placeholders_tracked(text, ctx);
+    if ctx.warn_unused {
+        for key in unused_params(ctx.params, &expansion.used) {
+            log::warn!(
+                "Unused param: {} never appears in the prompt",
+                ctx.token(key)
+            );
+        }
+    }
+    expansion.text = post.apply(expansion.text);
+    expansion
+}
+
+/// Take a typed prompt to the text that is sent: expanded and post-processed, checked
+/// (`--warn-empty-clipboard`, `--strict`), redacted, with `@cursor` taken out
+/// and `--max-length` applied.
+///
+/// With `pending`, redacted text only goes out on a second submit (see
+/// `confirm_redaction`); without it (headless) it goes out right away and
+/// what was redacted is logged. The error is the notice for the user.
+pub fn finalize_prompt(
+    text: &str,
+    ctx: &ExpandContext,
+    cli: &Cli,
+    redactor: Option<&Redactor>,
+    pending: Option<&RefCell<Option<String>>>,
+) -> std::result::Result<String, String> {
+    let expansion = prepare_prompt(text, ctx, &PostProcess::from_cli(cli));
+    if cli.warn_empty_clipboard {
+        check_empty_clipboard(&expansion.empty, cli.prefix)?;
+    }
+    let expanded = expansion.text;
+    if cli.strict {
+        check_unknown_tokens(&expanded, &known_placeholders(cli, ctx.params), cli.prefix)?;
+    }
+    let expanded = match (redactor, pending) {
+        (Some(_), Some(pending)) => confirm_redaction(redactor, expanded, pending)?,
+        (Some(redactor), None) => {
+            let redaction = redactor.redact(&expanded);
+            if !redaction.redacted.is_empty() {
+                log_info(cli.quiet, redaction.summary());
+            }
+            redaction.text
+        }
+        (None, _) => expanded,
+    };
+    let (expanded, _) = take_cursor(&expanded, cli.prefix);
+    limit_length(expanded, cli.max_length, cli.truncate)
+}
+
+/// `--warn-empty-clipboard`: fail with a notice if a clipboard built-in in the
+/// prompt (`empty` from the expansion) found nothing to insert
+fn check_empty_clipboard(
+    empty: &BTreeSet<&'static str>,
+    prefix: char,
+) -> std::result::Result<(), String> {
+    if empty.contains("clipboard-image") {
+        return Err(format!(
+            "No image on the clipboard for {}clipboard-image; copy one and submit again",
+            prefix
+        ));
+    }
+    match ["clipboard", "clipboard:path"]
+        .into_iter()
+        .find(|name| empty.contains(name))
+    {
+        Some(name) => Err(format!(
+            "Clipboard is empty, so {}{} would be blank; copy some text and submit again",
+            prefix, name
+        )),
+        None => Ok(()),
+    }
+}
+
+/// All known placeholder names: built-ins, then params and macros sorted
+pub fn known_placeholders(cli: &Cli, params: &HashMap<String, String>) -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_PLACEHOLDERS.iter().map(|s| s.to_string()).collect();
+    let mut param_keys: Vec<String> = params
+        .keys()
+        .cloned()
+        .chain(parse_macros(&cli.macros).into_keys())
+        .collect();
+    param_keys.sort();
+    param_keys.dedup();
+    names.append(&mut param_keys);
+    names
+}
+
+/// `@word` tokens in `text` that aren't a known placeholder, in order of
+/// first appearance.
+///
+/// A known placeholder must end at a word boundary, as in `highlight_spans`,
+/// so `@paths` is unknown even when `@path` exists. A prefix inside a word
+/// (`user@example.com`) doesn't start a token.
+fn unknown_tokens(text: &str, placeholders: &[String], prefix: char) -> Vec<String> {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut unknown: Vec<String> = Vec::new();
+
+    for (pos, c) in text.char_indices() {
+        if c != prefix || text[..pos].chars().next_back().is_some_and(is_word) {
+            continue;
+        }
+        let rest = &text[pos + c.len_utf8()..];
+        if let Some(name) = rest.strip_prefix('{').and_then(braced_name) {
+            let token = braced_token(name, prefix);
+            let known = placeholders.iter().any(|p| p == name) || is_builtin_name(name);
+            if !known && !unknown.contains(&token) {
+                unknown.push(token);
+            }
+            continue;
+        }
+        let known = placeholders.iter().any(|name| {
+            rest.strip_prefix(name.as_str())
+                .is_some_and(|after| !after.starts_with(is_word))
+        });
+        if known || !rest.starts_with(is_word) {
+            continue;
+        }
+        let len = rest
+            .find(|c: char| !is_word(c) && c != '-')
+            .unwrap_or(rest.len());
+        let token = format!("{}{}", prefix, &rest[..len]);
+        if !unknown.contains(&token) {
+            unknown.push(token);
+        }
+    }
+
+    unknown
+}
+
+/// `--strict`: fail with the unknown tokens listed if the prompt has any
+pub fn check_unknown_tokens(
+    text: &str,
+    placeholders: &[String],
+    prefix: char,
+) -> std::result::Result<(), String> {
+    let unknown = unknown_tokens(text, placeholders, prefix);
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Unknown placeholders: {}", unknown.join(" ")))
+    }
+}
+
+/// Redact an expanded prompt, requiring a second submit to send redacted text.
+///
+/// Returns the text to send, or a notice describing what was redacted when
+/// the same redacted text hasn't been confirmed yet.
+fn confirm_redaction(
+    redactor: Option<&Redactor>,
+    expanded: String,
+    pending: &RefCell<Option<String>>,
+) -> std::result::Result<String, String> {
+    let Some(redactor) = redactor else {
+        return Ok(expanded);
+    };
+    let redaction = redactor.redact(&expanded);
+    if redaction.redacted.is_empty() {
+        return Ok(expanded);
+    }
+
+    let mut pending = pending.borrow_mut();
+    if pending.as_deref() == Some(redaction.text.as_str()) {
+        pending.take();
+        return Ok(redaction.text);
+    }
+    let notice = format!("{} — submit again to send", redaction.summary());
+    *pending = Some(redaction.text);
+    Err(notice)
+}
+
+/// `--max-length`: the prompt if it fits, else cut to `max_length` characters
+/// with `truncate` or an error saying how long it is
+fn limit_length(
+    text: String,
+    max_length: Option<usize>,
+    truncate: bool,
+) -> std::result::Result<String, String> {
+    let Some(max) = max_length else {
+        return Ok(text);
+    };
+    let len = text.chars().count();
+    if len <= max {
+        Ok(text)
+    } else if truncate {
+        Ok(text.chars().take(max).collect())
+    } else {
+        Err(format!(
+            "Prompt is {} characters, over --max-length {}",
+            len, max
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::ClipboardProvider;
+    use clap::Parser;
+
+    /// Clipboard provider returning fixed content
+    struct FakeClipboard(Option<String>);
+
+    impl ClipboardProvider for FakeClipboard {
+        fn read_text(&self) -> Option<String> {
+            self.0.clone()
+        }
+    }
+
+    /// Expansion context with no params and a fake clipboard
+    fn ctx_with_clipboard<'a>(
+        params: &'a HashMap<String, String>,
+        clipboard: &'a FakeClipboard,
+    ) -> ExpandContext<'a> {
+        ExpandContext {
+            clipboard,
+            ..ExpandContext::new(params)
+        }
+    }
+
+    #[test]
+    fn test_limit_length_boundaries() {
+        let text = || "aé".repeat(3);
+        assert_eq!(limit_length(text(), None, false), Ok(text()));
+        // Exactly N characters is fine, however many bytes they take
+        assert_eq!(limit_length(text(), Some(6), false), Ok(text()));
+        assert_eq!(
+            limit_length(text(), Some(5), false),
+            Err("Prompt is 6 characters, over --max-length 5".to_string())
+        );
+        assert_eq!(limit_length(text(), Some(6), true), Ok(text()));
+        assert_eq!(limit_length(text(), Some(5), true), Ok("aéaéa".to_string()));
+    }
+
+    #[test]
+    fn test_envelope_wraps_prompt() {
+        let post = PostProcess {
+            envelope: Some("task".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            post.apply("Fix the bug".to_string()),
+            "<task>\nFix the bug\n</task>"
+        );
+    }
+
+    #[test]
+    fn test_envelope_escapes_angle_brackets() {
+        let wrapped = wrap_envelope("Use Vec<u8> and </task> -> ok", "task");
+        assert_eq!(
+            wrapped,
+            "<task>\nUse Vec&lt;u8&gt; and &lt;/task&gt; -&gt; ok\n</task>"
+        );
+    }
+
+    #[test]
+    fn test_confirm_redaction_needs_second_submit() {
+        let redactor = Redactor::new(&[]).unwrap();
+        let pending = RefCell::new(None);
+        let text = "key: sk-abcdefghijklmnopqrstuvwx".to_string();
+
+        let notice = confirm_redaction(Some(&redactor), text.clone(), &pending).unwrap_err();
+        assert!(notice.starts_with("Redacted 1 secret"));
+
+        let sent = confirm_redaction(Some(&redactor), text, &pending).unwrap();
+        assert_eq!(sent, "key: [REDACTED]");
+        assert!(pending.borrow().is_none());
+    }
+
+    #[test]
+    fn test_confirm_redaction_passes_clean_text() {
+        let redactor = Redactor::new(&[]).unwrap();
+        let pending = RefCell::new(None);
+        let sent = confirm_redaction(Some(&redactor), "hello".to_string(), &pending).unwrap();
+        assert_eq!(sent, "hello");
+        let sent = confirm_redaction(None, "sk-abcdefghijklmnopqrstuvwx".to_string(), &pending);
+        assert_eq!(sent.unwrap(), "sk-abcdefghijklmnopqrstuvwx");
+    }
+
+    #[test]
+    fn test_unknown_tokens() {
+        let placeholders = vec!["path".to_string(), "git-root".to_string()];
+        let unknown = |text| unknown_tokens(text, &placeholders, '@');
+
+        assert!(unknown("fix @path in @git-root").is_empty());
+        assert_eq!(
+            unknown("fix @slection and @paths"),
+            vec!["@slection", "@paths"]
+        );
+        // Repeats are reported once
+        assert_eq!(unknown("@x @x"), vec!["@x"]);
+        // Emails and a lone prefix aren't tokens
+        assert!(unknown("mail user@example.com @ noon").is_empty());
+        // Names may contain dashes
+        assert_eq!(unknown("@git-rot"), vec!["@git-rot"]);
+        // Followed by punctuation, a known name still counts
+        assert!(unknown("(@path), @path.").is_empty());
+        assert_eq!(
+            unknown_tokens("#slection", &placeholders, '#'),
+            vec!["#slection"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_braced_tokens() {
+        let placeholders = vec!["path".to_string(), "some.file".to_string()];
+        let unknown = |text| unknown_tokens(text, &placeholders, '@');
+
+        assert!(unknown("@{path} @{some.file} @{file:notes.md}").is_empty());
+        assert_eq!(unknown("@{pth} @{pth}"), vec!["@{pth}"]);
+    }
+
+    #[test]
+    fn test_check_unknown_tokens() {
+        let placeholders = vec!["path".to_string()];
+        assert_eq!(check_unknown_tokens("@path", &placeholders, '@'), Ok(()));
+        assert_eq!(
+            check_unknown_tokens("@pth @slection", &placeholders, '@'),
+            Err("Unknown placeholders: @pth @slection".to_string())
+        );
+    }
+
+    #[test]
+    fn test_empty_clipboard_is_reported() {
+        let params = HashMap::new();
+        let clipboard = FakeClipboard(None);
+        let ctx = ctx_with_clipboard(&params, &clipboard);
+
+        let expansion = expand_placeholders_tracked("Explain @clipboard", &ctx);
+        assert_eq!(expansion.text, "Explain ");
+        assert_eq!(expansion.empty, BTreeSet::from(["clipboard"]));
+        let err = check_empty_clipboard(&expansion.empty, '@').unwrap_err();
+        assert!(err.contains("@clipboard would be blank"), "{}", err);
+
+        // A missing image gets its own notice, braced or not
+        let expansion = expand_placeholders_tracked("See @{clipboard-image}", &ctx);
+        let err = check_empty_clipboard(&expansion.empty, '@').unwrap_err();
+        assert!(err.starts_with("No image on the clipboard"), "{}", err);
+
+        // Other empty built-ins are reported but don't trip the clipboard check
+        let empty = BTreeSet::from(["git-branch"]);
+        assert_eq!(check_empty_clipboard(&empty, '@'), Ok(()));
+
+        let clipboard = FakeClipboard(Some("copied".to_string()));
+        let ctx = ctx_with_clipboard(&params, &clipboard);
+        let expansion = expand_placeholders_tracked("Explain @clipboard", &ctx);
+        assert!(expansion.empty.is_empty());
+        assert_eq!(check_empty_clipboard(&expansion.empty, '@'), Ok(()));
+    }
+
+    #[test]
+    fn test_finalize_prompt_headless() {
+        let params = HashMap::new();
+        let finish = |args: &[&str], text: &str| {
+            let cli = Cli::try_parse_from(args).unwrap();
+            let redactor = Redactor::new(&cli.redact_patterns).unwrap();
+            let ctx = ExpandContext::from_cli(&cli, &params);
+            finalize_prompt(text, &ctx, &cli, Some(&redactor), None)
+        };
+        let args = ["prompt-dialog", "--quiet"];
+
+        let redacting = [&args[..], &["--redact-pattern", "hunter[0-9]"]].concat();
+        let expanded = finish(&redacting, "password is hunter2").unwrap();
+        assert!(!expanded.contains("hunter2"), "{}", expanded);
+
+        let strict = [&args[..], &["--strict"]].concat();
+        assert!(finish(&strict, "Fix @slection").is_err());
+
+        let limited = [&args[..], &["--max-length", "3", "--truncate"]].concat();
+        assert_eq!(finish(&limited, "a@cursorbcdef").unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_finalize_prompt_confirms_redaction() {
+        let cli =
+            Cli::try_parse_from(["prompt-dialog", "--redact-pattern", "hunter[0-9]"]).unwrap();
+        let redactor = Redactor::new(&cli.redact_patterns).unwrap();
+        let params = HashMap::new();
+        let ctx = ExpandContext::from_cli(&cli, &params);
+        let pending = RefCell::new(None);
+        let finish = || {
+            finalize_prompt(
+                "password is hunter2",
+                &ctx,
+                &cli,
+                Some(&redactor),
+                Some(&pending),
+            )
+        };
+
+        assert!(finish().is_err());
+        let expanded = finish().unwrap();
+        assert!(!expanded.contains("hunter2"), "{}", expanded);
+    }
+}
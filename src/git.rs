@@ -0,0 +1,113 @@
+//! Git repository helpers for built-in placeholders
+//!
+//! The repository root is found by walking the filesystem; the branch and the
+//! staged diff come from the `git` executable.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Appended to `@git-diff` output cut at the byte cap
+pub const TRUNCATED_MARKER: &str = "…(truncated)";
+
+/// Find the repository root containing `start` by walking up to the nearest `.git`.
+///
+/// `.git` may be a directory or a file (worktrees and submodules use a file).
+pub fn find_git_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Current branch of the repository at `cwd` (`git rev-parse --abbrev-ref HEAD`)
+pub fn current_branch(cwd: &Path) -> Option<String> {
+    run_git(cwd, &["rev-parse", "--abbrev-ref", "HEAD"]).map(|out| out.trim().to_string())
+}
+
+/// Staged changes of the repository at `cwd` (`git diff --staged`)
+pub fn staged_diff(cwd: &Path) -> Option<String> {
+    run_git(cwd, &["diff", "--staged"])
+}
+
+/// Run `git` in `cwd`, returning its stdout if it succeeded
+fn run_git(cwd: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(cwd)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Cut `text` to at most `max_bytes` (on a char boundary), marking the cut
+pub fn truncate(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &text[..end], TRUNCATED_MARKER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_git_root_from_nested_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let nested = repo.join("src").join("server");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(&nested).unwrap();
+
+        assert_eq!(find_git_root(&nested), Some(repo.clone()));
+        assert_eq!(find_git_root(&repo), Some(repo));
+    }
+
+    #[test]
+    fn test_find_git_root_worktree_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("wt");
+        std::fs::create_dir_all(&repo).unwrap();
+        std::fs::write(repo.join(".git"), "gitdir: /elsewhere").unwrap();
+
+        assert_eq!(find_git_root(&repo), Some(repo));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly", 7), "exactly");
+        assert_eq!(truncate("+line one\n+line two", 9), "+line one…(truncated)");
+        // Never splits a multi-byte character
+        assert_eq!(truncate("aé", 2), "a…(truncated)");
+    }
+
+    #[test]
+    fn test_git_outside_repo_is_none() {
+        let dir = tempfile::tempdir().unwrap();
+        // The temp dir itself might live inside a repo on some machines
+        if find_git_root(dir.path()).is_none() {
+            assert_eq!(current_branch(dir.path()), None);
+            assert_eq!(staged_diff(dir.path()), None);
+        }
+    }
+
+    #[test]
+    fn test_find_git_root_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("plain");
+        std::fs::create_dir_all(&plain).unwrap();
+
+        // The temp dir itself might live inside a repo on some machines
+        if let Some(root) = find_git_root(&plain) {
+            assert!(!root.starts_with(&plain));
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Placeholder highlighting: known tokens and their arguments as byte spans,
+//! and the overlay texts the input draws them with
+
+use crate::expand::{braced_token, needs_braces};
+
+/// Which part of a placeholder a highlight span covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpanKind {
+    /// The `@name` token, including the `:` of `@name:argument`
+    Token,
+    /// The argument after `@name:`, up to the next whitespace
+    Argument,
+}
+
+/// A highlighted byte range of the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HighlightSpan {
+    start: usize,
+    end: usize,
+    kind: SpanKind,
+}
+
+/// Find the highlighted spans of every known @placeholder in `text`.
+///
+/// A placeholder followed by `:` and a non-space argument (e.g. `@file:/x/y`)
+/// yields two spans, so the prefix and the argument can be styled differently.
+/// A `{...}` format right after the name (e.g. `@date{%Y}`) is an argument too.
+fn highlight_spans(text: &str, placeholders: &[String], prefix: char) -> Vec<HighlightSpan> {
+    let mut spans = Vec::new();
+
+    // Braced tokens are delimited, so they need no boundary checks
+    for name in placeholders {
+        let token = braced_token(name, prefix);
+        spans.extend(text.match_indices(&token).map(|(pos, _)| HighlightSpan {
+            start: pos,
+            end: pos + token.len(),
+            kind: SpanKind::Token,
+        }));
+    }
+
+    for name in placeholders.iter().filter(|name| !needs_braces(name)) {
+        let token = format!("{}{}", prefix, name);
+        let mut search_from = 0;
+        while let Some(pos) = text[search_from..].find(&token) {
+            let abs_pos = search_from + pos;
+            let end = abs_pos + token.len();
+            search_from = abs_pos + 1;
+
+            // Check that the token ends at a word boundary
+            let at_end = end >= text.len()
+                || !text.as_bytes()[end].is_ascii_alphanumeric() && text.as_bytes()[end] != b'_';
+            if !at_end {
+                continue;
+            }
+
+            let rest = &text[end..];
+            if let Some(close) = rest.strip_prefix('{').and_then(|arg| arg.find('}')) {
+                spans.push(HighlightSpan {
+                    start: abs_pos,
+                    end,
+                    kind: SpanKind::Token,
+                });
+                spans.push(HighlightSpan {
+                    start: end,
+                    end: end + close + 2,
+                    kind: SpanKind::Argument,
+                });
+                continue;
+            }
+
+            let arg_len = rest
+                .strip_prefix(':')
+                .map(|arg| arg.find(char::is_whitespace).unwrap_or(arg.len()))
+                .unwrap_or(0);
+            if arg_len > 0 {
+                spans.push(HighlightSpan {
+                    start: abs_pos,
+                    end: end + 1,
+                    kind: SpanKind::Token,
+                });
+                spans.push(HighlightSpan {
+                    start: end + 1,
+                    end: end + 1 + arg_len,
+                    kind: SpanKind::Argument,
+                });
+            } else {
+                spans.push(HighlightSpan {
+                    start: abs_pos,
+                    end,
+                    kind: SpanKind::Token,
+                });
+            }
+        }
+    }
+
+    spans
+}
+
+/// Build a highlight overlay text where only @placeholder tokens are visible
+/// and all other characters are replaced with spaces (preserving positions).
+///
+/// This works because the overlay Text uses the same font/size/wrap as the input,
+/// so characters at the same positions line up exactly.
+pub fn build_highlight_text(text: &str, placeholders: &[String], prefix: char) -> String {
+    mask_spans(
+        text,
+        &highlight_spans(text, placeholders, prefix),
+        SpanKind::Token,
+    )
+}
+
+/// Build the overlay for placeholder arguments (the `/x/y` of `@file:/x/y`),
+/// drawn in a different color than the tokens themselves
+pub fn build_argument_highlight_text(text: &str, placeholders: &[String], prefix: char) -> String {
+    mask_spans(
+        text,
+        &highlight_spans(text, placeholders, prefix),
+        SpanKind::Argument,
+    )
+}
+
+/// Keep the characters covered by spans of `kind`, blank out everything else
+pub fn mask_spans(text: &str, spans: &[HighlightSpan], kind: SpanKind) -> String {
+    let mut mask = vec![false; text.len()];
+    for span in spans.iter().filter(|s| s.kind == kind) {
+        for item in mask.iter_mut().take(span.end).skip(span.start) {
+            *item = true;
+        }
+    }
+    // An argument never shows through the token layer, even if another
+    // placeholder's token happens to cover it
+    if kind == SpanKind::Token {
+        for span in spans.iter().filter(|s| s.kind == SpanKind::Argument) {
+            for item in mask.iter_mut().take(span.end).skip(span.start) {
+                *item = false;
+            }
+        }
+    }
+
+    // Build overlay: keep highlighted chars, replace everything else with spaces
+    text.char_indices()
+        .map(|(i, c)| {
+            if i < mask.len() && mask[i] {
+                c
+            } else if c == '\n' {
+                '\n' // Preserve newlines for wrap alignment
+            } else {
+                ' '
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expand::BUILTIN_PLACEHOLDERS;
+
+    #[test]
+    fn test_build_highlight_text() {
+        let placeholders = vec!["path".to_string(), "clipboard".to_string()];
+        let text = "Fix @path and @clipboard now";
+        let result = build_highlight_text(text, &placeholders, '@');
+        // @path and @clipboard should be visible, rest spaces
+        assert_eq!(result, "    @path     @clipboard    ");
+    }
+
+    #[test]
+    fn test_build_highlight_braced() {
+        let placeholders = vec!["some".to_string(), "some.file".to_string()];
+        assert_eq!(
+            build_highlight_text("@{some.file} @some.file", &placeholders, '@'),
+            "@{some.file} @some     "
+        );
+        assert_eq!(
+            build_highlight_text("@{nope}", &placeholders, '@'),
+            "       "
+        );
+    }
+
+    #[test]
+    fn test_build_highlight_preserves_newlines() {
+        let placeholders = vec!["file".to_string()];
+        let text = "hello\n@file";
+        let result = build_highlight_text(text, &placeholders, '@');
+        assert_eq!(result, "     \n@file");
+    }
+
+    #[test]
+    fn test_highlight_spans_split_argument() {
+        let placeholders = vec!["file".to_string()];
+        let spans = highlight_spans("see @file:/x/y now", &placeholders, '@');
+        assert_eq!(
+            spans,
+            vec![
+                HighlightSpan {
+                    start: 4,
+                    end: 10,
+                    kind: SpanKind::Token
+                },
+                HighlightSpan {
+                    start: 10,
+                    end: 14,
+                    kind: SpanKind::Argument
+                },
+            ]
+        );
+
+        let text = "see @file:/x/y now";
+        assert_eq!(
+            build_highlight_text(text, &placeholders, '@'),
+            "    @file:        "
+        );
+        assert_eq!(
+            build_argument_highlight_text(text, &placeholders, '@'),
+            "          /x/y    "
+        );
+    }
+
+    #[test]
+    fn test_highlight_datetime_format_argument() {
+        let placeholders: Vec<String> =
+            BUILTIN_PLACEHOLDERS.iter().map(|s| s.to_string()).collect();
+        let text = "On @date{%Y/%m} at @time";
+        assert_eq!(
+            build_highlight_text(text, &placeholders, '@'),
+            "   @date           @time"
+        );
+        assert_eq!(
+            build_argument_highlight_text(text, &placeholders, '@'),
+            "        {%Y/%m}         "
+        );
+    }
+
+    #[test]
+    fn test_highlight_spans_bare_colon_is_plain_token() {
+        let placeholders = vec!["path".to_string()];
+        let text = "Fix @path: now";
+        assert_eq!(
+            build_highlight_text(text, &placeholders, '@'),
+            "    @path     "
+        );
+        assert_eq!(
+            build_argument_highlight_text(text, &placeholders, '@').trim(),
+            ""
+        );
+    }
+}
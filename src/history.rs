@@ -0,0 +1,255 @@
+//! Persistent prompt history
+//!
+//! Successfully submitted prompts (pre-expansion) are stored as a JSON array
+//! under the OS config dir so they can be listed, resent, and recalled with
+//! Up/Down in the dialog.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+/// File name of the history store inside the app config dir
+pub const HISTORY_FILE: &str = "history.json";
+
+/// Most entries kept; older ones are dropped on push
+pub const MAX_ENTRIES: usize = 100;
+
+/// Default history location: `<config dir>/prompt-dialog/history.json`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("prompt-dialog").join(HISTORY_FILE))
+}
+
+/// Submitted prompts, oldest first
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    /// Load history from `path`; a missing file yields an empty history
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read history file {}", path.display()))?;
+        let entries = serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse history file {}", path.display()))?;
+        Ok(Self { entries })
+    }
+
+    /// Write history to `path`, creating parent directories as needed
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let data =
+            serde_json::to_string_pretty(&self.entries).context("Failed to serialize history")?;
+        std::fs::write(path, data)
+            .with_context(|| format!("Failed to write history file {}", path.display()))
+    }
+
+    /// Append a prompt as the most recent entry.
+    ///
+    /// Repeating the most recent prompt is a no-op, and only the newest
+    /// [`MAX_ENTRIES`] are kept.
+    pub fn push(&mut self, prompt: &str) {
+        if self.entries.last().map(String::as_str) == Some(prompt) {
+            return;
+        }
+        self.entries.push(prompt.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+
+    /// Look up an entry by 1-based index, where 1 is the most recent prompt
+    pub fn get(&self, index: usize) -> Result<&str> {
+        if index == 0 || index > self.entries.len() {
+            return Err(anyhow!(
+                "History index {} out of range (1..={})",
+                index,
+                self.entries.len()
+            ));
+        }
+        Ok(&self.entries[self.entries.len() - index])
+    }
+
+    /// Iterate entries newest first, paired with their 1-based index
+    pub fn recent(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.entries
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, entry)| (i + 1, entry.as_str()))
+    }
+}
+
+/// Up/Down navigation through history in the dialog.
+///
+/// Recalling only kicks in while the prompt is empty or still shows the
+/// recalled entry, so arrow keys never throw away text the user typed.
+#[derive(Debug, Default)]
+pub struct Recall {
+    /// Entries, newest first
+    entries: Vec<String>,
+    /// Index into `entries` of the entry currently shown
+    pos: Option<usize>,
+}
+
+impl Recall {
+    pub fn new(history: &History) -> Self {
+        Self {
+            entries: history.recent().map(|(_, e)| e.to_string()).collect(),
+            pos: None,
+        }
+    }
+
+    /// Whether `current` is text arrow keys may replace
+    fn can_replace(&self, current: &str) -> bool {
+        match self.pos {
+            Some(pos) => self.entries[pos] == current,
+            None => current.is_empty(),
+        }
+    }
+
+    /// The next older entry to show in place of `current`, if any
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        if !self.can_replace(current) {
+            return None;
+        }
+        let next = self.pos.map_or(0, |pos| pos + 1);
+        if next >= self.entries.len() {
+            return None;
+        }
+        self.pos = Some(next);
+        Some(&self.entries[next])
+    }
+
+    /// The next newer entry to show in place of `current`; past the newest
+    /// entry this is the empty prompt again
+    pub fn newer(&mut self, current: &str) -> Option<&str> {
+        if !self.can_replace(current) {
+            return None;
+        }
+        match self.pos? {
+            0 => {
+                self.pos = None;
+                Some("")
+            }
+            pos => {
+                self.pos = Some(pos - 1);
+                Some(&self.entries[pos - 1])
+            }
+        }
+    }
+}
+
+/// Append a prompt to the history file at `path`
+pub fn record(path: &Path, prompt: &str) -> Result<()> {
+    let mut history = History::load(path)?;
+    history.push(prompt);
+    history.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> History {
+        History {
+            entries: vec![
+                "first".to_string(),
+                "second".to_string(),
+                "third".to_string(),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_get_most_recent_first() {
+        let history = sample();
+        assert_eq!(history.get(1).unwrap(), "third");
+        assert_eq!(history.get(3).unwrap(), "first");
+    }
+
+    #[test]
+    fn test_get_out_of_range() {
+        let history = sample();
+        assert!(history.get(0).is_err());
+        assert!(history.get(4).is_err());
+        assert!(History::default().get(1).is_err());
+    }
+
+    #[test]
+    fn test_recent_indices() {
+        let history = sample();
+        let listed: Vec<(usize, &str)> = history.recent().collect();
+        assert_eq!(listed, vec![(1, "third"), (2, "second"), (3, "first")]);
+    }
+
+    #[test]
+    fn test_push_dedupes_consecutive() {
+        let mut history = sample();
+        history.push("third");
+        history.push("second");
+        history.push("second");
+        let listed: Vec<&str> = history.recent().map(|(_, e)| e).collect();
+        assert_eq!(listed, vec!["second", "third", "second", "first"]);
+    }
+
+    #[test]
+    fn test_push_caps_entries() {
+        let mut history = History::default();
+        for i in 0..MAX_ENTRIES + 5 {
+            history.push(&format!("prompt {}", i));
+        }
+        assert_eq!(history.recent().count(), MAX_ENTRIES);
+        assert_eq!(
+            history.get(1).unwrap(),
+            format!("prompt {}", MAX_ENTRIES + 4)
+        );
+        assert_eq!(history.get(MAX_ENTRIES).unwrap(), "prompt 5");
+    }
+
+    #[test]
+    fn test_recall_cycles_up_and_down() {
+        let mut recall = Recall::new(&sample());
+        assert_eq!(recall.newer(""), None);
+        assert_eq!(recall.older(""), Some("third"));
+        assert_eq!(recall.older("third"), Some("second"));
+        assert_eq!(recall.older("second"), Some("first"));
+        assert_eq!(recall.older("first"), None);
+        assert_eq!(recall.newer("first"), Some("second"));
+        assert_eq!(recall.newer("second"), Some("third"));
+        assert_eq!(recall.newer("third"), Some(""));
+        assert_eq!(recall.newer(""), None);
+    }
+
+    #[test]
+    fn test_recall_keeps_typed_text() {
+        let mut recall = Recall::new(&sample());
+        assert_eq!(recall.older("my draft"), None);
+
+        assert_eq!(recall.older(""), Some("third"));
+        // Once the recalled entry is edited, arrows move the cursor again
+        assert_eq!(recall.older("third, edited"), None);
+        assert_eq!(recall.newer("third, edited"), None);
+    }
+
+    #[test]
+    fn test_record_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(HISTORY_FILE);
+
+        assert_eq!(History::load(&path).unwrap(), History::default());
+        record(&path, "Fix @path").unwrap();
+        record(&path, "multi\nline").unwrap();
+
+        let history = History::load(&path).unwrap();
+        assert_eq!(history.recent().count(), 2);
+        assert_eq!(history.get(1).unwrap(), "multi\nline");
+    }
+}
@@ -0,0 +1,136 @@
+//! `--on-success` command hook
+//!
+//! Starts a user command through the shell after a prompt was sent, with the
+//! expanded prompt exposed as an environment variable for automation chaining.
+//! The command runs detached: nothing waits for it, and its exit status is
+//! only logged.
+//!
+//! The command is whatever the user passed on the command line, run with their
+//! privileges; it is never taken from the prompt or the server.
+
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+
+/// Environment variable holding the expanded prompt that was sent
+pub const PROMPT_ENV: &str = "PROMPT_DIALOG_PROMPT";
+
+/// Starts shell commands; injectable so hook wiring can be tested
+pub trait CommandRunner {
+    /// Start `command` through the shell with extra environment variables,
+    /// without waiting for it; fails only if it can't be started
+    fn spawn(&self, command: &str, env: &[(&str, &str)]) -> Result<()>;
+}
+
+/// Runs commands with `sh -c` (`cmd /C` on Windows) in the background
+pub struct ShellRunner;
+
+impl CommandRunner for ShellRunner {
+    fn spawn(&self, command: &str, env: &[(&str, &str)]) -> Result<()> {
+        let mut cmd = if cfg!(windows) {
+            let mut cmd = Command::new("cmd");
+            cmd.args(["/C", command]);
+            cmd
+        } else {
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", command]);
+            cmd
+        };
+
+        let mut child = cmd
+            .envs(env.iter().copied())
+            .stdin(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to run `{}`", command))?;
+
+        // Reap the child off the caller's thread, just to report how it ended
+        let command = command.to_string();
+        std::thread::spawn(move || match child.wait() {
+            Ok(status) => log::debug!("`{}` exited with {}", command, status),
+            Err(e) => log::debug!("Failed to wait for `{}`: {}", command, e),
+        });
+        Ok(())
+    }
+}
+
+/// Start the `--on-success` command with the sent prompt in [`PROMPT_ENV`]
+pub fn run_on_success(runner: &dyn CommandRunner, command: &str, prompt: &str) -> Result<()> {
+    runner.spawn(command, &[(PROMPT_ENV, prompt)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::cell::RefCell;
+    use std::time::{Duration, Instant};
+
+    /// Records invocations instead of running anything
+    #[derive(Default)]
+    struct RecordingRunner {
+        calls: RefCell<Vec<(String, Vec<(String, String)>)>>,
+        fail: bool,
+    }
+
+    impl CommandRunner for RecordingRunner {
+        fn spawn(&self, command: &str, env: &[(&str, &str)]) -> Result<()> {
+            let env = env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            self.calls.borrow_mut().push((command.to_string(), env));
+            if self.fail {
+                Err(anyhow!("boom"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_on_success_passes_prompt_env() {
+        let runner = RecordingRunner::default();
+        run_on_success(&runner, "notify-send sent", "Fix src/main.rs").unwrap();
+
+        let calls = runner.calls.borrow();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "notify-send sent");
+        assert_eq!(
+            calls[0].1,
+            vec![(PROMPT_ENV.to_string(), "Fix src/main.rs".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_on_success_reports_failure() {
+        let runner = RecordingRunner {
+            fail: true,
+            ..Default::default()
+        };
+        assert!(run_on_success(&runner, "false", "x").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_runner_is_detached() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("prompt.txt");
+        let command = format!(
+            r#"sleep 0.2; printf %s "$PROMPT_DIALOG_PROMPT" > {}"#,
+            out.display()
+        );
+
+        // Returns before the command has finished
+        run_on_success(&ShellRunner, &command, "hello").unwrap();
+        assert!(!out.exists());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while std::fs::read_to_string(&out).ok().as_deref() != Some("hello") {
+            assert!(Instant::now() < deadline, "hook never wrote its output");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        // A failing command only fails in its own exit status
+        run_on_success(&ShellRunner, "exit 3", "x").unwrap();
+    }
+}
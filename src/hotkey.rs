@@ -0,0 +1,59 @@
+//! Global hotkey that shows the dialog in `--daemon` mode (global-hotkey)
+//!
+//! Hotkeys are grabbed system-wide, which only some platforms allow: Windows,
+//! macOS and X11 work, Wayland compositors don't let clients register them.
+
+use anyhow::{Context, Result};
+use global_hotkey::hotkey::HotKey;
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+
+/// Default `--hotkey`
+pub const DEFAULT_HOTKEY: &str = "ctrl+alt+space";
+
+/// Parse a `--hotkey` spec such as `ctrl+alt+space` or `super+shift+KeyP`
+pub fn parse(spec: &str) -> Result<HotKey> {
+    spec.parse::<HotKey>()
+        .with_context(|| format!("Invalid hotkey '{}'", spec))
+}
+
+/// Register `hotkey` system-wide; it stays registered while the returned
+/// manager is alive
+pub fn register(hotkey: HotKey) -> Result<GlobalHotKeyManager> {
+    let manager =
+        GlobalHotKeyManager::new().context("Failed to start the global hotkey manager")?;
+    manager
+        .register(hotkey)
+        .context("Failed to register the global hotkey (is another app using it?)")?;
+    Ok(manager)
+}
+
+/// Whether the hotkey with `id` was pressed since the last call; drains every
+/// pending hotkey event
+pub fn take_pressed(id: u32) -> bool {
+    let mut pressed = false;
+    while let Ok(event) = GlobalHotKeyEvent::receiver().try_recv() {
+        pressed |= event.id == id && event.state == HotKeyState::Pressed;
+    }
+    pressed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use global_hotkey::hotkey::{Code, Modifiers};
+
+    #[test]
+    fn test_parse_default() {
+        let hotkey = parse(DEFAULT_HOTKEY).unwrap();
+        assert_eq!(
+            hotkey,
+            HotKey::new(Some(Modifiers::CONTROL | Modifiers::ALT), Code::Space)
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("ctrl+alt+nosuchkey").is_err());
+        assert!(parse("").is_err());
+    }
+}
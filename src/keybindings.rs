@@ -0,0 +1,225 @@
+//! Keyboard shortcut registry
+//!
+//! The single source for the dialog's shortcuts: the keys are handed to the
+//! Slint UI for matching, and the help overlay text is generated from the same
+//! table, so the two can't drift apart.
+
+use slint::platform::Key;
+use slint::SharedString;
+
+/// Something the dialog can do from the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Submit,
+    AcceptAutocomplete,
+    ShowHelp,
+    Dismiss,
+    HistoryPrevious,
+    HistoryNext,
+    ExpandPreview,
+    Undo,
+    Redo,
+}
+
+/// A key as seen in Slint key events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyName {
+    Return,
+    Tab,
+    Escape,
+    F1,
+    Up,
+    Down,
+    Char(char),
+}
+
+impl KeyName {
+    /// Human-readable key name for the help overlay
+    pub fn label(self) -> String {
+        match self {
+            KeyName::Return => "Enter".to_string(),
+            KeyName::Tab => "Tab".to_string(),
+            KeyName::Escape => "Escape".to_string(),
+            KeyName::F1 => "F1".to_string(),
+            KeyName::Up => "Up".to_string(),
+            KeyName::Down => "Down".to_string(),
+            KeyName::Char(c) if c.is_ascii_uppercase() => format!("Shift+{}", c),
+            KeyName::Char(c) => c.to_string(),
+        }
+    }
+
+    /// The key's text in Slint key events (`KeyEvent.text`)
+    pub fn event_text(self) -> SharedString {
+        match self {
+            KeyName::Return => Key::Return.into(),
+            KeyName::Tab => Key::Tab.into(),
+            KeyName::Escape => Key::Escape.into(),
+            KeyName::F1 => Key::F1.into(),
+            KeyName::Up => Key::UpArrow.into(),
+            KeyName::Down => Key::DownArrow.into(),
+            KeyName::Char(c) => c.to_string().into(),
+        }
+    }
+}
+
+/// One keyboard shortcut
+#[derive(Debug, Clone, Copy)]
+pub struct Binding {
+    pub action: Action,
+    pub key: KeyName,
+    /// Requires Cmd (macOS) or Ctrl (elsewhere)
+    pub command_modifier: bool,
+    /// Only active while the prompt is empty (so the key can still be typed)
+    pub when_empty: bool,
+    pub description: &'static str,
+}
+
+/// Every shortcut the dialog understands
+pub const BINDINGS: &[Binding] = &[
+    Binding {
+        action: Action::Submit,
+        key: KeyName::Return,
+        command_modifier: true,
+        when_empty: false,
+        description: "Submit prompt",
+    },
+    Binding {
+        action: Action::AcceptAutocomplete,
+        key: KeyName::Tab,
+        command_modifier: false,
+        when_empty: false,
+        description: "Accept autocomplete suggestion",
+    },
+    Binding {
+        action: Action::ShowHelp,
+        key: KeyName::F1,
+        command_modifier: false,
+        when_empty: false,
+        description: "Show this help",
+    },
+    Binding {
+        action: Action::ShowHelp,
+        key: KeyName::Char('?'),
+        command_modifier: false,
+        when_empty: true,
+        description: "Show this help",
+    },
+    Binding {
+        action: Action::Dismiss,
+        key: KeyName::Escape,
+        command_modifier: false,
+        when_empty: false,
+        description: "Dismiss dialog (or close this help)",
+    },
+    Binding {
+        action: Action::HistoryPrevious,
+        key: KeyName::Up,
+        command_modifier: false,
+        when_empty: false,
+        description: "Previous prompt from history (empty or recalled prompt)",
+    },
+    Binding {
+        action: Action::HistoryNext,
+        key: KeyName::Down,
+        command_modifier: false,
+        when_empty: false,
+        description: "Next prompt from history",
+    },
+    Binding {
+        action: Action::ExpandPreview,
+        key: KeyName::Char('e'),
+        command_modifier: true,
+        when_empty: false,
+        description: "Expand placeholders in place, to review before sending",
+    },
+    Binding {
+        action: Action::Undo,
+        key: KeyName::Char('z'),
+        command_modifier: true,
+        when_empty: false,
+        description: "Undo (typing is grouped by pauses)",
+    },
+    Binding {
+        action: Action::Redo,
+        key: KeyName::Char('Z'),
+        command_modifier: true,
+        when_empty: false,
+        description: "Redo",
+    },
+];
+
+/// Event text of the key bound to `action`, or empty if there is none
+pub fn key_text(action: Action, when_empty: bool) -> SharedString {
+    BINDINGS
+        .iter()
+        .find(|b| b.action == action && b.when_empty == when_empty)
+        .map(|b| b.key.event_text())
+        .unwrap_or_default()
+}
+
+/// Display form of a binding's keys, e.g. `Cmd+Enter`
+pub fn chord_label(binding: &Binding, mac: bool) -> String {
+    let mut label = binding.key.label();
+    if binding.command_modifier {
+        label = format!("{}+{}", if mac { "Cmd" } else { "Ctrl" }, label);
+    }
+    if binding.when_empty {
+        label.push_str(" (empty)");
+    }
+    label
+}
+
+/// Help overlay text: one `keys  description` line per binding
+pub fn help_text(mac: bool) -> String {
+    BINDINGS
+        .iter()
+        .map(|b| format!("{:<16}{}", chord_label(b, mac), b.description))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_help_lists_every_binding() {
+        for mac in [true, false] {
+            let help = help_text(mac);
+            for binding in BINDINGS {
+                let line = format!("{:<16}{}", chord_label(binding, mac), binding.description);
+                assert!(help.lines().any(|l| l == line), "missing: {}", line);
+            }
+            assert_eq!(help.lines().count(), BINDINGS.len());
+        }
+    }
+
+    #[test]
+    fn test_chord_label_modifiers() {
+        let submit = &BINDINGS[0];
+        assert_eq!(chord_label(submit, true), "Cmd+Enter");
+        assert_eq!(chord_label(submit, false), "Ctrl+Enter");
+        let question = BINDINGS.iter().find(|b| b.when_empty).unwrap();
+        assert_eq!(chord_label(question, false), "? (empty)");
+        let redo = BINDINGS.iter().find(|b| b.action == Action::Redo).unwrap();
+        assert_eq!(chord_label(redo, false), "Ctrl+Shift+Z");
+    }
+
+    #[test]
+    fn test_every_action_has_a_key() {
+        for action in [
+            Action::Submit,
+            Action::AcceptAutocomplete,
+            Action::ShowHelp,
+            Action::Dismiss,
+            Action::HistoryPrevious,
+            Action::HistoryNext,
+            Action::ExpandPreview,
+            Action::Undo,
+            Action::Redo,
+        ] {
+            assert!(!key_text(action, false).is_empty());
+        }
+        assert_eq!(key_text(Action::ShowHelp, true), "?");
+    }
+}
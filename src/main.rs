@@ -2,41 +2,726 @@
 //!
 //! A lightweight Slint GUI that sends prompts to a running OpenCode instance.
 
+mod autocomplete;
+mod autofence;
+mod builder;
+mod clipboard;
+mod config;
+mod expand;
+mod finalize;
+mod git;
+mod highlight;
+mod history;
+mod hook;
+mod hotkey;
+mod keybindings;
+mod notify;
+mod params_file;
+mod placement;
+mod port_cache;
+mod project_config;
+mod redact;
+mod reset;
 mod server;
+mod stats;
+mod template;
+mod theme;
+mod undo;
+mod window_state;
 
+use std::cell::RefCell;
 use std::collections::HashMap;
-
-use anyhow::{Context, Result};
-use clap::Parser;
+use std::future::Future;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use crate::autocomplete::{find_autocomplete, AutocompleteCycle};
+use crate::clipboard::ClipboardFormat;
+use crate::expand::{
+    expand_placeholders_with, expand_without_builtins, take_cursor, ExpandContext,
+    BUILTIN_PLACEHOLDERS, DEFAULT_FILES_MAX, DEFAULT_GIT_DIFF_MAX_BYTES, DEFAULT_PREFIX,
+};
+use crate::finalize::{
+    check_unknown_tokens, finalize_prompt, known_placeholders, prepare_prompt, PostProcess,
+};
+use crate::highlight::{build_argument_highlight_text, build_highlight_text};
+use crate::redact::Redactor;
 
 slint::include_modules!();
 
 /// Frameless AI prompt dialog for OpenCode
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "prompt-dialog", version, about)]
+#[command(group(clap::ArgGroup::new("endpoint").args(["port", "socket"])))]
 struct Cli {
     /// OpenCode server port (auto-discovers if not specified)
     #[arg(short, long)]
     port: Option<u16>,
 
-    /// Enable debug logging
+    /// Host the OpenCode server runs on; anything but this machine needs --port,
+    /// since process scanning only sees local servers
+    #[arg(long, default_value = "localhost")]
+    host: String,
+
+    /// Connect over HTTPS (e.g. to a TLS-only reverse proxy)
+    #[arg(long, default_value_t = false)]
+    tls: bool,
+
+    /// With --tls, accept invalid certificates (self-signed, wrong host)
+    #[arg(long, default_value_t = false, requires = "tls")]
+    insecure: bool,
+
+    /// Path prefix the OpenCode API is served under, e.g. `/opencode/` behind a
+    /// reverse proxy (needs --port or --socket)
+    #[arg(long, value_name = "PATH", default_value = "", requires = "endpoint")]
+    base_path: String,
+
+    /// Talk to OpenCode over this Unix domain socket instead of TCP; no
+    /// discovery runs (Unix only)
+    #[arg(long, value_name = "PATH", conflicts_with = "mdns")]
+    socket: Option<PathBuf>,
+
+    /// Enable debug logging (same as -vv)
     #[arg(long, default_value_t = false)]
     debug: bool,
 
+    /// Log more on stderr: -v for progress, -vv for debug details, -vvv for
+    /// everything; `RUST_LOG` (e.g. `RUST_LOG=prompt_dialog::server=debug`)
+    /// tunes it per module
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Print nothing to stdout/stderr except requested output (e.g. --print-expanded);
+    /// the exit code reports the outcome. Overrides --debug and -v.
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+
+    /// Character that starts a placeholder (single ASCII character), for when
+    /// prompts or param values legitimately contain `@`
+    #[arg(long, value_name = "CHAR", default_value_t = DEFAULT_PREFIX, value_parser = parse_prefix)]
+    prefix: char,
+
     /// Prompt parameters as key=value pairs, usable as @key placeholders
     /// Example: --param path=/src/main.rs --param selection="some code"
-    #[arg(long = "param", value_name = "KEY=VALUE")]
+    #[arg(long = "param", value_name = "KEY=VALUE", global = true)]
     params: Vec<String>,
+
+    /// Read params from environment variables starting with this prefix
+    /// Example: --param-env-prefix PROMPT_ turns PROMPT_PATH into @path
+    #[arg(long, value_name = "PREFIX")]
+    param_env_prefix: Option<String>,
+
+    /// Load params from a JSON or TOML file (flat table; chosen by extension).
+    /// --param flags win on conflicts
+    #[arg(long, value_name = "PATH")]
+    params_file: Option<PathBuf>,
+
+    /// Don't load `[params]` from the nearest `.prompt-dialog.toml` at or above
+    /// the current directory (up to the repository root)
+    #[arg(long, default_value_t = false)]
+    no_project_config: bool,
+
+    /// Directory of prompt templates (`.txt` / `.md`, optional front-matter)
+    /// offered in a dropdown that fills the input
+    #[arg(long, value_name = "DIR")]
+    template_dir: Option<PathBuf>,
+
+    /// Discover OpenCode servers advertised via mDNS on the local network
+    #[arg(long, default_value_t = false)]
+    mdns: bool,
+
+    /// Expand placeholders in TEXT, print the result, and exit (no server, no
+    /// window); the same as the `expand` subcommand
+    #[arg(long, value_name = "TEXT")]
+    print_expanded: Option<String>,
+
+    /// Minimum window width in logical pixels (grows further to fit the placeholder hint)
+    #[arg(long, value_name = "PX")]
+    min_width: Option<f32>,
+
+    /// Wrap paragraphs that look like code in markdown fences before sending
+    #[arg(long, default_value_t = false)]
+    auto_fence: bool,
+
+    /// Wrap the expanded prompt in `<TAG>...</TAG>`, escaping `<` and `>` inside
+    #[arg(long, value_name = "TAG", value_parser = parse_envelope_tag)]
+    envelope: Option<String>,
+
+    /// On submit, print the final prompt to stdout and exit instead of sending;
+    /// works without a server
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// After a successful send, print a JSON object with the prompt, the
+    /// expanded prompt and the server's port, pid and cwd to stdout; on failure
+    /// print `{"ok":false,"error":...}` and exit nonzero
+    #[arg(long, default_value_t = false, conflicts_with_all = ["dry_run", "daemon"])]
+    report_json: bool,
+
+    /// Per-request HTTP timeout in seconds when sending prompts, connecting
+    /// included (0 = no timeout)
+    #[arg(long, value_name = "SECONDS", default_value_t = 5)]
+    timeout: u64,
+
+    /// Give up connecting to the server after this many seconds, separately
+    /// from --timeout (0 = no connect timeout)
+    #[arg(long, value_name = "SECONDS", default_value_t = server::client::DEFAULT_CONNECT_TIMEOUT.as_secs())]
+    connect_timeout: u64,
+
+    /// Retry each request this many times (with exponential backoff) when the
+    /// server can't be reached
+    #[arg(long, value_name = "N", default_value_t = server::client::DEFAULT_RETRIES)]
+    retries: u32,
+
+    /// After expansion, report `--param`s whose placeholder never appeared
+    #[arg(long, default_value_t = false)]
+    warn_unused_params: bool,
+
+    /// Refuse to send when `@clipboard` (or `@clipboard:path` /
+    /// `@clipboard-image`) finds nothing on the clipboard, and say so in the
+    /// dialog instead of sending the prompt without it
+    #[arg(long, default_value_t = false)]
+    warn_empty_clipboard: bool,
+
+    /// Refuse to send while the expanded prompt still has unknown `@tokens`
+    /// (typos like `@slection`)
+    #[arg(long, default_value_t = false, global = true)]
+    strict: bool,
+
+    /// Refuse to send prompts longer than N characters once expanded (e.g. a
+    /// huge clipboard pasted by accident)
+    #[arg(long, value_name = "N")]
+    max_length: Option<usize>,
+
+    /// With --max-length, cut overlong prompts to N characters instead of refusing
+    #[arg(long, default_value_t = false, requires = "max_length")]
+    truncate: bool,
+
+    /// Pre-fill the prompt from stdin even when it's a terminal (piped stdin is
+    /// always read)
+    #[arg(long, default_value_t = false)]
+    stdin: bool,
+
+    /// Count characters and words with macros and params expanded instead of
+    /// the raw input (built-ins are counted as typed)
+    #[arg(long, default_value_t = false)]
+    count_expanded: bool,
+
+    /// Autocomplete placeholders by fuzzy subsequence match (`@slct` → `@selection`)
+    /// instead of by prefix
+    #[arg(long, default_value_t = false)]
+    fuzzy: bool,
+
+    /// Undo steps kept for the prompt input (Ctrl+Z / Ctrl+Shift+Z)
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = undo::DEFAULT_DEPTH,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    undo_depth: usize,
+
+    /// File holding the editor's current selection, inlined by `@selection-file`
+    #[arg(long, value_name = "PATH", env = "PROMPT_DIALOG_SELECTION_PATH")]
+    selection_path: Option<PathBuf>,
+
+    /// Cut `@git-diff` output after this many bytes, marking it `…(truncated)`
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_GIT_DIFF_MAX_BYTES)]
+    git_diff_max_bytes: usize,
+
+    /// List at most this many paths per `@files:<glob>`
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_FILES_MAX)]
+    files_max: usize,
+
+    /// Give up on server discovery after this many seconds and start disconnected
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    discovery_timeout: u64,
+
+    /// Directory of OpenCode instance files (`*.json` with `port` and `cwd`), tried
+    /// before process scanning [default: $XDG_RUNTIME_DIR/opencode, then
+    /// <config dir>/opencode]
+    #[arg(long, value_name = "DIR")]
+    instance_dir: Option<PathBuf>,
+
+    /// File holding the server's port, tried after instance files and before
+    /// process scanning [default: ~/.opencode/port, then
+    /// $XDG_RUNTIME_DIR/opencode-<cwd hash>.port]
+    #[arg(long, value_name = "PATH")]
+    port_file: Option<PathBuf>,
+
+    /// Only use a server running in this exact directory, not a parent or
+    /// subdirectory; among nested servers the deepest one is checked
+    #[arg(long, default_value_t = false)]
+    exact_cwd: bool,
+
+    /// When several servers match the cwd, offer a picker in the dialog instead
+    /// of using the newest one
+    #[arg(long, default_value_t = false)]
+    pick_server: bool,
+
+    /// Stop the process scan once this many OpenCode candidates were found
+    #[arg(long, value_name = "N")]
+    scan_max_candidates: Option<usize>,
+
+    /// Examine at most this many processes (newest first) during discovery
+    #[arg(long, value_name = "N")]
+    scan_max_processes: Option<usize>,
+
+    /// Window title; supports placeholders plus @cwd (the server's working directory)
+    /// Example: --title "Prompt: @cwd"
+    #[arg(long, value_name = "TEMPLATE")]
+    title: Option<String>,
+
+    /// Clipboard flavor @clipboard prefers (html is converted to text)
+    #[arg(long, value_enum, default_value_t = ClipboardFormat::Plain)]
+    clipboard_format: ClipboardFormat,
+
+    /// Macros as name=template pairs; @name expands to the template, whose own
+    /// placeholders are expanded too. `\n` in the template becomes a newline.
+    /// Example: --macro context='File: @path\n---\n@selection'
+    #[arg(long = "macro", value_name = "NAME=TEMPLATE")]
+    macros: Vec<String>,
+
+    /// Replace secret-looking text (API keys, tokens, private keys) with
+    /// [REDACTED] before sending; the dialog asks for a second submit to confirm
+    #[arg(long, default_value_t = false)]
+    redact: bool,
+
+    /// Extra regex to redact before sending (repeatable, implies --redact)
+    #[arg(long = "redact-pattern", value_name = "REGEX")]
+    redact_patterns: Vec<String>,
+
+    /// Where the window opens: screen center, or over the focused application window
+    #[arg(long, value_enum, default_value_t = placement::Position::Center)]
+    position: placement::Position,
+
+    /// Color scheme; `system` follows the OS appearance (dark if unknown)
+    #[arg(long, value_enum, default_value_t = theme::Theme::System)]
+    theme: theme::Theme,
+
+    /// Desktop notification after a send from the dialog: on failure only,
+    /// after every send (a brief confirmation on success), or never
+    #[arg(long, value_enum, default_value_t = notify::NotifyMode::OnError)]
+    notify: notify::NotifyMode,
+
+    /// Always center the window instead of reopening it where it last closed
+    #[arg(long, default_value_t = false)]
+    no_restore: bool,
+
+    /// Keep the window at its fixed height instead of growing it with the prompt
+    #[arg(long, default_value_t = false)]
+    no_autosize: bool,
+
+    /// Shell command started after a successful send, before the window closes;
+    /// the expanded prompt is in $PROMPT_DIALOG_PROMPT. It runs detached, with
+    /// its exit status logged under --debug, and never affects the send.
+    #[arg(long, value_name = "CMD")]
+    on_success: Option<String>,
+
+    /// Keep local usage stats (sends, placeholder counts, prompt length) in
+    /// <config dir>/prompt-dialog/stats.json; never transmitted
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    /// Only submit what is already in OpenCode's TUI prompt; the dialog's text is
+    /// not sent (and may be left empty)
+    #[arg(long, default_value_t = false)]
+    submit_only: bool,
+
+    /// Where prompts go: the TUI's prompt, or a new session created for each send
+    #[arg(long, value_enum, default_value_t = SendTarget::Tui, conflicts_with = "submit_only")]
+    mode: SendTarget,
+
+    /// Append the prompt to OpenCode's TUI prompt without submitting it, to keep
+    /// editing there
+    #[arg(long, default_value_t = false, conflicts_with_all = ["submit_only", "mode"])]
+    append_only: bool,
+
+    /// TUI command to execute after appending, instead of `prompt.submit`
+    /// (letters, digits, `.`, `_` and `-`)
+    #[arg(
+        long = "command",
+        value_name = "NAME",
+        default_value = server::client::DEFAULT_SUBMIT_COMMAND,
+        value_parser = parse_command_name,
+        conflicts_with_all = ["append_only", "mode"]
+    )]
+    submit_command: String,
+
+    /// Re-check the server's cwd right before sending and abort if it no longer
+    /// matches the discovered one (e.g. OpenCode switched projects)
+    #[arg(long, default_value_t = false)]
+    recheck_cwd: bool,
+
+    /// Open the prompt builder: task, context and constraints fields assembled
+    /// into one prompt with section headers
+    #[arg(long, default_value_t = false)]
+    builder: bool,
+
+    /// Stay running in the background and open the dialog with --hotkey;
+    /// dismissing it hides the window instead of quitting
+    #[arg(long, default_value_t = false, conflicts_with = "builder")]
+    daemon: bool,
+
+    /// Global shortcut that opens the dialog in --daemon mode (e.g.
+    /// `ctrl+alt+space`, `super+shift+KeyP`)
+    #[arg(long, value_name = "KEYS", default_value = hotkey::DEFAULT_HOTKEY)]
+    hotkey: String,
+
+    /// Attach a file to the prompt as an `@<path>` reference (repeatable); the
+    /// path must exist
+    #[arg(long, value_name = "PATH", value_parser = parse_attachment)]
+    attach: Vec<PathBuf>,
+
+    /// Prompt to pre-fill the dialog with (sent right away with --submit-on-start)
+    #[arg(value_name = "PROMPT")]
+    prompt: Option<String>,
+
+    /// Expand and send PROMPT without opening the window; exits nonzero on failure
+    #[arg(long, default_value_t = false, requires = "prompt")]
+    submit_on_start: bool,
+
+    /// TOML file with defaults for --host, --port, --timeout, --theme, --prefix
+    /// and --retries; flags given on the command line win
+    /// [default: <config dir>/prompt-dialog/config.toml, if it exists]
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Cli {
+    /// Parse the command line, then fill in unset flags from the config file
+    fn parse_with_config() -> Result<Self> {
+        let matches = Cli::command().get_matches();
+        let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        let config = match &cli.config {
+            Some(path) => config::Config::load(path, true)?,
+            None => match config::default_path() {
+                Some(path) => config::Config::load(&path, false)?,
+                None => config::Config::default(),
+            },
+        };
+        cli.with_config(&config, &matches)
+    }
+
+    /// Take values from `config` for the flags `matches` got neither from the
+    /// command line nor from the environment
+    fn with_config(mut self, config: &config::Config, matches: &clap::ArgMatches) -> Result<Self> {
+        let unset = |id: &str| {
+            !matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+
+        if let (Some(host), true) = (&config.host, unset("host")) {
+            self.host = host.clone();
+        }
+        // --socket replaces the port altogether
+        if let (Some(port), true) = (config.port, unset("port") && self.socket.is_none()) {
+            self.port = Some(port);
+        }
+        if let (Some(timeout), true) = (config.timeout, unset("timeout")) {
+            self.timeout = timeout;
+        }
+        if let (Some(theme), true) = (config.theme, unset("theme")) {
+            self.theme = theme;
+        }
+        if let (Some(prefix), true) = (config.prefix, unset("prefix")) {
+            self.prefix = parse_prefix(&prefix.to_string())
+                .map_err(|e| anyhow!("Invalid prefix in config: {}", e))?;
+        }
+        if let (Some(retries), true) = (config.retries, unset("retries")) {
+            self.retries = retries;
+        }
+        Ok(self)
+    }
+
+    /// Apply `--quiet`, which turns off debug logging too
+    fn silenced(mut self) -> Self {
+        if self.quiet {
+            self.debug = false;
+            self.verbose = 0;
+            self.warn_unused_params = false;
+        }
+        self
+    }
+
+    /// Log level from `--quiet`, `--debug` and `-v`; warnings only by default
+    fn log_level(&self) -> log::LevelFilter {
+        match (self.quiet, self.debug, self.verbose) {
+            (true, _, _) => log::LevelFilter::Off,
+            (_, _, 3..) => log::LevelFilter::Trace,
+            (_, true, _) | (_, _, 2) => log::LevelFilter::Debug,
+            (_, _, 1) => log::LevelFilter::Info,
+            _ => log::LevelFilter::Warn,
+        }
+    }
+}
+
+/// Log to stderr at the CLI's level, refined by `RUST_LOG` unless `--quiet`
+fn init_logging(cli: &Cli) {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(cli.log_level()).format_timestamp(None);
+    if !cli.quiet {
+        builder.parse_default_env();
+    }
+    builder.init();
+}
+
+/// Non-GUI subcommands
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// List recently submitted prompts, most recent first
+    History {
+        /// Maximum number of entries to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Re-expand and send history entry N headlessly (1 = most recent)
+    Resend {
+        /// History index as shown by `prompt-dialog history`
+        index: usize,
+    },
+    /// Check that every @placeholder in a template file is declared in its
+    /// front-matter (`params: a, b`) or built in; exits nonzero otherwise
+    ValidateTemplate {
+        /// Template file to check
+        file: PathBuf,
+    },
+    /// Print TEXT with its placeholders expanded, relative to the current
+    /// directory; no window and no server discovery
+    Expand {
+        /// Text to expand, e.g. "Fix @path"
+        text: String,
+    },
+    /// List the OpenCode servers discoverable on this machine (pid, port, cwd)
+    ListServers {
+        /// Print a JSON array instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check that an OpenCode server answers on a port (exits nonzero if not);
+    /// prints its cwd with --debug
+    Ping {
+        /// Port to probe, on --host
+        #[arg(long)]
+        port: u16,
+        /// Give up after this many seconds (0 = no timeout)
+        #[arg(long, value_name = "SECONDS", default_value_t = 2)]
+        timeout: u64,
+    },
+    /// Delete persisted state under <config dir>/prompt-dialog
+    #[command(group(clap::ArgGroup::new("state").required(true).multiple(true)))]
+    Reset {
+        /// Delete every kind of state
+        #[arg(long, group = "state")]
+        all: bool,
+        /// Delete prompt history
+        #[arg(long, group = "state")]
+        history: bool,
+        /// Delete --stats usage counters
+        #[arg(long, group = "state")]
+        stats: bool,
+        /// Delete the last-used port cache
+        #[arg(long, group = "state")]
+        cache: bool,
+        /// Delete the saved window position
+        #[arg(long, group = "state")]
+        window: bool,
+    },
+}
+
+/// Parse `--envelope`: an XML-style name (letter or `_`, then letters, digits, `_-.`)
+fn parse_envelope_tag(s: &str) -> std::result::Result<String, String> {
+    let mut chars = s.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(s.to_string())
+    } else {
+        Err(format!("{:?} is not a valid tag name", s))
+    }
+}
+
+/// `--mode`: where prompts are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+enum SendTarget {
+    /// Append to the TUI's prompt and submit it
+    #[default]
+    Tui,
+    /// Post to a new session via the session API
+    Session,
+}
+
+/// Work done after every successful send
+#[derive(Debug, Clone, Default)]
+struct AfterSend {
+    quiet: bool,
+    /// Update the local usage stats file
+    stats: bool,
+    /// Shell command run with the expanded prompt in its environment
+    on_success: Option<String>,
+    /// Server described by the `--report-json` object; `None` when not requested
+    report: Option<server::Server>,
+    /// Desktop notification for the outcome (`--notify`)
+    notify: notify::NotifyMode,
+}
+
+impl AfterSend {
+    fn from_cli(cli: &Cli) -> Self {
+        Self {
+            quiet: cli.quiet,
+            stats: cli.stats,
+            on_success: cli.on_success.clone(),
+            report: None,
+            notify: cli.notify,
+        }
+    }
+
+    /// Describe `server` in the `--report-json` object, if it was requested
+    fn with_report(mut self, cli: &Cli, server: &server::Server) -> Self {
+        if cli.report_json {
+            self.report = Some(server.clone());
+        }
+        self
+    }
+
+    /// Report the session `--mode session` created
+    fn log_session(&self, session_id: Option<&str>) {
+        if let Some(id) = session_id {
+            log::info!("Created session {}", id);
+        }
+    }
+
+    /// Record history and stats, start the `--on-success` hook, then print
+    /// the `--report-json` object.
+    ///
+    /// Failures are logged, never returned: the prompt was already sent.
+    fn run(&self, text: &str, expanded: &str) {
+        if !text.is_empty() {
+            record_history(text);
+        }
+        if self.stats {
+            record_stats(text, expanded);
+        }
+        if let Some(command) = &self.on_success {
+            if let Err(e) = hook::run_on_success(&hook::ShellRunner, command, expanded) {
+                log_info(
+                    self.quiet,
+                    format_args!("on-success hook failed to start: {:#}", e),
+                );
+            }
+        }
+        if let Some(server) = &self.report {
+            let report = send_report(text, expanded, server);
+            if let Err(e) = write_report(&report, &mut std::io::stdout().lock()) {
+                log::error!("{:#}", e);
+            }
+        }
+    }
 }
 
+/// How often `--daemon` checks for hotkey presses
+const HOTKEY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Parse `--prefix`: exactly one ASCII, non-alphanumeric, non-space character
+fn parse_prefix(s: &str) -> std::result::Result<char, String> {
+    let mut chars = s.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return Err(format!("expected a single character, got {:?}", s));
+    };
+    if !c.is_ascii() {
+        return Err(format!(
+            "{:?} is a multi-byte character; the prefix must be ASCII",
+            c
+        ));
+    }
+    if c.is_ascii_alphanumeric() || c.is_ascii_whitespace() || c == '_' {
+        return Err(format!(
+            "{:?} can't start a placeholder; use a punctuation character",
+            c
+        ));
+    }
+    Ok(c)
+}
+
+/// Template picker entry that clears the prompt
+const NO_TEMPLATE_LABEL: &str = "No template";
+
+/// Error shown for a submit with no server to send it to
+const NOT_CONNECTED: &str = "Not connected to an OpenCode server";
+
+/// Default window size in logical pixels (includes shadow margins)
+const DEFAULT_WINDOW_WIDTH: f32 = 680.0;
+const DEFAULT_WINDOW_HEIGHT: f32 = 240.0;
+
+/// Upper bound for the computed window width
+const MAX_WINDOW_WIDTH: f32 = 1600.0;
+
+/// Upper bound for the window height as the prompt grows
+const MAX_WINDOW_HEIGHT: f32 = 720.0;
+
+/// Approximate advance and line height of the 16px mononoki input text
+const INPUT_CHAR_WIDTH: f32 = 9.6;
+const INPUT_LINE_HEIGHT: f32 = 20.0;
+
+/// Prompt lines the default height shows above the status bar
+const AUTOSIZE_BASE_LINES: usize = 7;
+
+/// Horizontal space around the input text: body inset + input padding
+const INPUT_RESERVED_WIDTH: f32 = 44.0;
+
+/// Physical window position used when no monitor reports its size
+const FALLBACK_WINDOW_POSITION: (i32, i32) = (100, 100);
+
+/// Approximate advance of one hint character (11px mononoki is ~0.6em wide)
+const HINT_CHAR_WIDTH: f32 = 6.6;
+
+/// Horizontal space around the placeholder hint: submit hint column + paddings + shadow
+const HINT_RESERVED_WIDTH: f32 = 212.0;
+
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let cli = Cli::parse_with_config()?.silenced();
+    init_logging(&cli);
+    let quiet = cli.quiet;
+    let report_json = cli.report_json;
+    match run(Rc::new(cli)) {
+        // The JSON object carries the error instead of stderr
+        Err(e) if report_json => exit_with_failure_report(&format!("{:#}", e)),
+        // Don't let `main` print the error; the exit code says enough
+        Err(_) if quiet => std::process::exit(1),
+        result => result,
+    }
+}
+
+fn run(cli: Rc<Cli>) -> Result<()> {
+    // The HTTP client only reaches Unix domain sockets on Unix
+    #[cfg(not(unix))]
+    if cli.socket.is_some() {
+        return Err(anyhow!(
+            "--socket needs Unix domain sockets, which this platform lacks"
+        ));
+    }
+
     let cwd = std::env::current_dir().context("Failed to get current directory")?;
 
-    // Parse --param key=value pairs into a HashMap
-    let params = parse_params(&cli.params);
-    if cli.debug && !params.is_empty() {
-        eprintln!(
+    let params = load_params(&cli, &cwd, env_vars())?;
+    // Values may hold clipboard-like secrets, so never above debug
+    if !params.is_empty() {
+        log::debug!(
             "Params: {}",
             params
                 .iter()
@@ -46,144 +731,585 @@ fn main() -> Result<()> {
         );
     }
 
+    // Scripting mode: expand and print without touching the server or the GUI
+    if let Some(text) = &cli.print_expanded {
+        return expand_command(&cli, &params, &cwd, text, &mut std::io::stdout().lock());
+    }
+
+    match &cli.command {
+        Some(Command::History { limit }) => {
+            return list_history(*limit, &mut std::io::stdout().lock());
+        }
+        Some(Command::Resend { index }) => {
+            return resend_history(&cli, &params, &cwd, *index);
+        }
+        Some(Command::ValidateTemplate { file }) => {
+            return validate_template(file, &mut std::io::stdout().lock());
+        }
+        Some(Command::Expand { text }) => {
+            return expand_command(&cli, &params, &cwd, text, &mut std::io::stdout().lock());
+        }
+        Some(Command::ListServers { json }) => {
+            return list_servers(&cli, *json, &mut std::io::stdout().lock());
+        }
+        Some(Command::Ping { port, timeout }) => {
+            return ping(&cli, *port, *timeout, &mut std::io::stdout().lock());
+        }
+        Some(Command::Reset {
+            all,
+            history,
+            stats,
+            cache,
+            window,
+        }) => {
+            let states = reset_selection(*all, *history, *stats, *cache, *window);
+            return reset_state(&states, cli.quiet, &mut std::io::stdout().lock());
+        }
+        None => {}
+    }
+
+    // Headless sender (e.g. for editor keybindings): the window never appears
+    if let (true, Some(text)) = (cli.submit_on_start, &cli.prompt) {
+        return send_headless(&cli, &params, &cwd, text);
+    }
+
     // Create tokio runtime for async HTTP calls
-    let rt = tokio::runtime::Runtime::new().context("Failed to create tokio runtime")?;
+    let (rt, flavor) = build_runtime()?;
+
+    // Discover or connect to the OpenCode server (all matching ones with --pick-server)
+    let server_choices = rt.block_on(with_timeout(
+        discover_servers(&cwd, &cli),
+        Duration::from_secs(cli.discovery_timeout),
+        "Server discovery",
+    ));
+    let (discovery_result, server_choices) = match server_choices {
+        Ok(servers) => (Ok(servers[0].clone()), servers),
+        Err(e) => (Err(e), Vec::new()),
+    };
+
+    // From here on the runtime is only used via its handle from Slint callbacks
+    let rt_handle = rt.handle().clone();
+    let _rt = drive_in_background(rt, flavor);
 
-    // Discover or connect to the OpenCode server
-    let discovery_result = rt.block_on(discover_and_connect(&cwd, cli.port, cli.debug));
+    // Working directory for built-ins: the server's when connected
+    let expand_cwd = discovery_result
+        .as_ref()
+        .map(|s| s.cwd.clone())
+        .unwrap_or_else(|_| cwd.clone());
+
+    let redactor = build_redactor(&cli)?;
+
+    if cli.builder {
+        return builder::run(
+            cli,
+            params,
+            &discovery_result,
+            expand_cwd,
+            redactor,
+            rt_handle,
+        );
+    }
 
     // Create the Slint dialog
     let dialog = PromptDialog::new().context("Failed to create dialog window")?;
 
-    // Center window on screen
-    center_window(&dialog, cli.debug);
+    // Resolve the window title template once at startup
+    if let Some(template) = &cli.title {
+        dialog.set_window_title(
+            expand_title(template, &params, Some(&expand_cwd), cli.prefix).into(),
+        );
+    }
 
-    // Show available placeholders in the UI (built-ins + user params)
-    {
-        let mut hints: Vec<String> = vec!["@clipboard".to_string()];
-        let mut param_keys: Vec<&String> = params.keys().collect();
-        param_keys.sort();
-        for k in param_keys {
-            hints.push(format!("@{}", k));
-        }
-        dialog.set_placeholder_hint(hints.join(" ").into());
+    let all_placeholders = known_placeholders(&cli, &params);
+
+    // Show available placeholders in the UI
+    let placeholder_hint = all_placeholders
+        .iter()
+        .map(|name| format!("{}{}", cli.prefix, name))
+        .collect::<Vec<_>>()
+        .join(" ");
+    dialog.set_placeholder_hint(placeholder_hint.clone().into());
+
+    // Widen the window so the hint bar isn't clipped, then reopen it where it
+    // last closed or center it on screen
+    let win_width = compute_window_width(&placeholder_hint, cli.min_width);
+    dialog.set_window_width(win_width);
+    let restored = cli.position == placement::Position::Center
+        && !cli.no_restore
+        && restore_window(dialog.window());
+    if !restored {
+        place_window(
+            dialog.window(),
+            win_width,
+            DEFAULT_WINDOW_HEIGHT,
+            cli.position,
+        );
     }
 
+    dialog.set_allow_empty_submit(cli.submit_only);
+    dialog.set_dry_run(cli.dry_run);
+    apply_theme(&dialog, cli.theme);
+    apply_keybindings(&dialog);
+
     // Set connection state based on discovery
     match &discovery_result {
         Ok(server) => {
             dialog.set_connected(true);
-            if cli.debug {
-                eprintln!(
-                    "Connected to OpenCode server on port {} (cwd: {})",
-                    server.port,
-                    server.cwd.display()
-                );
-            }
+            dialog.set_status_text(build_status_text(true, server.tcp_port()).into());
+            log::info!(
+                "Connected to OpenCode server at {}:{} (cwd: {})",
+                server.host,
+                server.port,
+                server.cwd.display()
+            );
         }
         Err(e) => {
             dialog.set_connected(false);
+            dialog.set_status_text(build_status_text(false, None).into());
             dialog.set_error_text(format!("{}", e).into());
-            if cli.debug {
-                eprintln!("Server discovery failed: {}", e);
-            }
+            log::info!("Server discovery failed: {:#}", e);
         }
     }
 
-    // Collect all known placeholder names (built-ins + user params)
-    let all_placeholders: Vec<String> = {
-        let mut names = vec!["clipboard".to_string()];
-        let mut param_keys: Vec<String> = params.keys().cloned().collect();
-        param_keys.sort();
-        names.append(&mut param_keys);
-        names
-    };
+    // The server prompts go to; the picker can change it
+    let selected_server = Rc::new(RefCell::new(discovery_result.as_ref().ok().cloned()));
+
+    // Servers offered by the picker (--pick-server); a retry refills it
+    show_server_choices(&dialog, &server_choices);
+    let server_choices = Rc::new(RefCell::new(server_choices));
+
+    // Retry button: re-run discovery on the runtime, then adopt the server
+    {
+        let weak = dialog.as_weak();
+        let rt_handle = rt_handle.clone();
+        let cli = cli.clone();
+        let cwd = cwd.clone();
+        let selected_server = selected_server.clone();
+        let server_choices = server_choices.clone();
+
+        dialog.on_retry_connect(move || {
+            let Some(d) = weak.upgrade() else {
+                return;
+            };
+            d.set_retrying(true);
+            d.set_error_text("".into());
+
+            let discovery = {
+                let cli = (*cli).clone();
+                let cwd = cwd.clone();
+                rt_handle.spawn(async move {
+                    with_timeout(
+                        discover_servers(&cwd, &cli),
+                        Duration::from_secs(cli.discovery_timeout),
+                        "Server discovery",
+                    )
+                    .await
+                })
+            };
+            let weak = weak.clone();
+            let selected_server = selected_server.clone();
+            let server_choices = server_choices.clone();
+            let spawned = slint::spawn_local(async move {
+                let result = discovery.await.map_err(anyhow::Error::from).and_then(|r| r);
+                let Some(d) = weak.upgrade() else {
+                    return;
+                };
+                d.set_retrying(false);
+                match result {
+                    Ok(servers) => {
+                        let server = servers[0].clone();
+                        d.set_connected(true);
+                        d.set_status_text(build_status_text(true, server.tcp_port()).into());
+                        *selected_server.borrow_mut() = Some(server);
+                        show_server_choices(&d, &servers);
+                        *server_choices.borrow_mut() = servers;
+                    }
+                    Err(e) => {
+                        d.set_error_text(format!("{}", e).into());
+                        log::info!("Server discovery failed: {:#}", e);
+                    }
+                }
+            });
+            if let Err(e) = spawned {
+                d.set_retrying(false);
+                d.set_error_text(format!("{}", e).into());
+            }
+        });
+    }
+
+    // Candidates rotated by repeated Tab; typing starts over
+    let autocomplete_cycle: Rc<RefCell<Option<AutocompleteCycle>>> = Rc::new(RefCell::new(None));
+
+    // Input snapshots for Ctrl+Z / Ctrl+Shift+Z; typing is recorded from
+    // text-changed, programmatic edits as checkpoints
+    let undo_stack = Rc::new(RefCell::new(undo::UndoStack::new(cli.undo_depth)));
 
     // Wire up text-changed callback for autocomplete + highlighting
     {
         let weak = dialog.as_weak();
         let placeholders = all_placeholders.clone();
+        let prefix = cli.prefix;
+        let fuzzy = cli.fuzzy;
+        let autocomplete_cycle = autocomplete_cycle.clone();
+        let cli = cli.clone();
+        let params = params.clone();
+        let undo_stack = undo_stack.clone();
 
         dialog.on_text_changed(move |text| {
             let text = text.to_string();
+            autocomplete_cycle.borrow_mut().take();
             if let Some(d) = weak.upgrade() {
+                let cursor = d.invoke_cursor_offset() as usize;
+                undo_stack
+                    .borrow_mut()
+                    .record(&text, cursor, Instant::now());
+
+                // Built-ins stay unexpanded: reading the clipboard or running
+                // git on every keystroke is what send-time expansion avoids
+                let (chars, words) = if cli.count_expanded {
+                    let ctx = ExpandContext::from_cli(&cli, &params);
+                    let expanded = expand_without_builtins(&text, &ctx);
+                    text_counts(&take_cursor(&expanded, cli.prefix).0)
+                } else {
+                    text_counts(&text)
+                };
+                d.set_char_count(chars as i32);
+                d.set_word_count(words as i32);
+
+                // Grow (or shrink back) with the prompt; only the height changes,
+                // so the window stays horizontally where it was
+                if !cli.no_autosize {
+                    let height = compute_window_height(&text, d.get_window_width());
+                    if height != d.get_window_height() {
+                        d.set_window_height(height);
+                        d.window()
+                            .set_size(slint::LogicalSize::new(d.get_window_width(), height));
+                    }
+                }
+
                 // Generate highlight overlay text
-                let highlight = build_highlight_text(&text, &placeholders);
+                let highlight = build_highlight_text(&text, &placeholders, prefix);
                 d.set_highlight_text(highlight.into());
+                let arguments = build_argument_highlight_text(&text, &placeholders, prefix);
+                d.set_argument_highlight_text(arguments.into());
+
+                // Find autocomplete suggestion for the token at the caret
+                let (suggestion, visible) =
+                    find_autocomplete(&text, cursor, &placeholders, prefix, fuzzy);
+                d.set_autocomplete_suggestion(suggestion.into());
+                d.set_autocomplete_visible(visible);
+            }
+        });
+    }
 
-                // Find autocomplete suggestion
-                let (suggestion, visible) = find_autocomplete(&text, &placeholders);
+    // Moving the caret changes which token autocomplete looks at
+    {
+        let weak = dialog.as_weak();
+        let placeholders = all_placeholders.clone();
+        let prefix = cli.prefix;
+        let fuzzy = cli.fuzzy;
+        let autocomplete_cycle = autocomplete_cycle.clone();
+
+        dialog.on_cursor_moved(move || {
+            if let Some(d) = weak.upgrade() {
+                let text = d.get_input_text().to_string();
+                let cursor = d.invoke_cursor_offset() as usize;
+                // Right after a Tab the caret sits behind the completion, and the
+                // next candidate stays on offer (the Tab handler shows it)
+                match autocomplete_cycle.try_borrow() {
+                    Err(_) => return,
+                    Ok(cycle)
+                        if cycle
+                            .as_ref()
+                            .is_some_and(|c| c.completed == text && c.caret == cursor) =>
+                    {
+                        return
+                    }
+                    Ok(_) => {}
+                }
+                let (suggestion, visible) =
+                    find_autocomplete(&text, cursor, &placeholders, prefix, fuzzy);
                 d.set_autocomplete_suggestion(suggestion.into());
                 d.set_autocomplete_visible(visible);
             }
         });
     }
 
+    // Wire up Up/Down history recall
+    {
+        let weak = dialog.as_weak();
+        let recall = RefCell::new(load_recall());
+        let undo_stack = undo_stack.clone();
+
+        dialog.on_recall_history(move |direction| {
+            let Some(d) = weak.upgrade() else {
+                return false;
+            };
+            let current = d.get_input_text().to_string();
+            let mut recall = recall.borrow_mut();
+            let entry = if direction < 0 {
+                recall.older(&current)
+            } else {
+                recall.newer(&current)
+            };
+            let Some(entry) = entry.map(str::to_string) else {
+                return false;
+            };
+            d.set_input_text(entry.clone().into());
+            d.invoke_move_cursor_to_end();
+            undo_stack.borrow_mut().checkpoint(&entry, entry.len());
+            d.invoke_text_changed(entry.into());
+            true
+        });
+    }
+
+    // Ctrl+E: expand placeholders into the editable prompt. Only ever on this
+    // keypress, so already-expanded text isn't re-read behind the user's back
+    {
+        let weak = dialog.as_weak();
+        let cli = cli.clone();
+        let params = params.clone();
+        let expand_cwd = expand_cwd.clone();
+        let selected_server = selected_server.clone();
+        let undo_stack = undo_stack.clone();
+
+        dialog.on_expand_preview(move || {
+            let Some(d) = weak.upgrade() else {
+                return;
+            };
+            let text = d.get_input_text().to_string();
+            let server = selected_server.borrow();
+            let cwd = server.as_ref().map_or(&expand_cwd, |s| &s.cwd);
+            let ctx = ExpandContext::from_cli(&cli, &params).with_cwd(cwd);
+            if let Some(expanded) = preview_expansion(&text, &ctx) {
+                d.set_input_text(expanded.clone().into());
+                d.invoke_move_cursor_to_end();
+                undo_stack
+                    .borrow_mut()
+                    .checkpoint(&expanded, expanded.len());
+                d.invoke_text_changed(expanded.into());
+            }
+        });
+    }
+
+    // Ctrl+Z / Ctrl+Shift+Z: restore the previous or next snapshot
+    {
+        let weak = dialog.as_weak();
+        let undo_stack = undo_stack.clone();
+
+        dialog.on_undo_step(move |direction| {
+            let Some(d) = weak.upgrade() else {
+                return;
+            };
+            let mut stack = undo_stack.borrow_mut();
+            let snapshot = if direction < 0 {
+                stack.undo()
+            } else {
+                stack.redo()
+            };
+            let Some(undo::Snapshot { text, cursor }) = snapshot.cloned() else {
+                return;
+            };
+            drop(stack);
+            d.set_input_text(text.clone().into());
+            d.invoke_set_cursor_offset(cursor.min(text.len()) as i32);
+            d.invoke_text_changed(text.into());
+        });
+    }
+
+    // Pre-fill the prompt from PROMPT, else piped stdin (or --stdin);
+    // text-changed runs the highlighting so placeholders are marked right away
+    let prefill = match &cli.prompt {
+        Some(text) => Some(text.clone()),
+        None if cli.stdin || !std::io::stdin().is_terminal() => {
+            read_prefill(std::io::stdin().lock())?
+        }
+        None => None,
+    };
+    if let Some(text) = prefill {
+        dialog.set_input_text(text.clone().into());
+        dialog.invoke_move_cursor_to_end();
+        undo_stack.borrow_mut().checkpoint(&text, text.len());
+        dialog.invoke_text_changed(text.into());
+    }
+
     // Wire up accept-autocomplete callback
     {
         let weak = dialog.as_weak();
         let placeholders = all_placeholders.clone();
+        let prefix = cli.prefix;
+        let fuzzy = cli.fuzzy;
 
         dialog.on_accept_autocomplete(move || {
             if let Some(d) = weak.upgrade() {
                 let text = d.get_input_text().to_string();
-                let completed = apply_autocomplete(&text, &placeholders);
-                d.set_input_text(completed.into());
-                d.invoke_move_cursor_to_end();
+                let cursor = d.invoke_cursor_offset() as usize;
+                let mut cycle = autocomplete_cycle.borrow_mut();
+                // Another Tab swaps the last completion, within the same undo step
+                let continuing = cycle
+                    .as_ref()
+                    .is_some_and(|c| c.completed == text && c.caret == cursor);
+                *cycle = AutocompleteCycle::advance(
+                    cycle.take(),
+                    &text,
+                    cursor,
+                    &placeholders,
+                    prefix,
+                    fuzzy,
+                );
+                let Some(cycle) = cycle.as_ref() else {
+                    d.set_autocomplete_visible(false);
+                    return;
+                };
+                d.set_input_text(cycle.completed.clone().into());
+                d.invoke_set_cursor_offset(cycle.caret as i32);
+                let mut undo_stack = undo_stack.borrow_mut();
+                if continuing {
+                    undo_stack.amend(&cycle.completed, cycle.caret);
+                } else {
+                    undo_stack.checkpoint(&cycle.completed, cycle.caret);
+                }
 
                 // Trigger highlight update
-                let highlight = build_highlight_text(d.get_input_text().as_ref(), &placeholders);
+                let highlight =
+                    build_highlight_text(d.get_input_text().as_ref(), &placeholders, prefix);
                 d.set_highlight_text(highlight.into());
-                d.set_autocomplete_visible(false);
+                let arguments = build_argument_highlight_text(
+                    d.get_input_text().as_ref(),
+                    &placeholders,
+                    prefix,
+                );
+                d.set_argument_highlight_text(arguments.into());
+
+                // With several candidates, keep showing the one the next Tab inserts
+                match cycle.upcoming() {
+                    Some(next) => {
+                        d.set_autocomplete_suggestion(format!("{}{}", prefix, next).into());
+                        d.set_autocomplete_visible(true);
+                    }
+                    None => d.set_autocomplete_visible(false),
+                }
             }
         });
     }
 
-    // Wire up the submit callback
-    let client = discovery_result
-        .as_ref()
-        .ok()
-        .map(|s| server::Client::new(s.port));
+    // Template picker: the blank entry first, then one per file in --template-dir
+    if let Some(dir) = &cli.template_dir {
+        let templates = template::load_dir(dir)?;
+        let labels: Vec<slint::SharedString> = std::iter::once(NO_TEMPLATE_LABEL.into())
+            .chain(templates.iter().map(|t| t.name.as_str().into()))
+            .collect();
+        dialog.set_template_choices(Rc::new(slint::VecModel::from(labels)).into());
+
+        let weak = dialog.as_weak();
+        dialog.on_template_selected(move |index| {
+            let Some(d) = weak.upgrade() else {
+                return;
+            };
+            // Index 0 is the blank entry, which clears the prompt
+            let body = (index as usize)
+                .checked_sub(1)
+                .and_then(|i| templates.get(i))
+                .map_or_else(String::new, |t| t.body.clone());
+            d.set_input_text(body.clone().into());
+            d.invoke_move_cursor_to_end();
+            d.invoke_text_changed(body.into());
+            d.invoke_focus_input();
+        });
+    }
+
+    {
+        let weak = dialog.as_weak();
+        let selected_server = selected_server.clone();
+        dialog.on_server_selected(move |index| {
+            let Some(server) = server_choices.borrow().get(index as usize).cloned() else {
+                return;
+            };
+            if let Some(d) = weak.upgrade() {
+                d.set_status_text(build_status_text(true, server.tcp_port()).into());
+            }
+            *selected_server.borrow_mut() = Some(server);
+        });
+    }
 
+    // Set once a send succeeded, so closing otherwise counts as a failure for
+    // --report-json
+    let sent = Arc::new(AtomicBool::new(false));
+
+    // Wire up the submit callback
     {
         let weak = dialog.as_weak();
-        let rt_handle = rt.handle().clone();
+        let rt_handle = rt_handle.clone();
+        let cli = cli.clone();
+        let pending_redaction = RefCell::new(None);
+        let sent = sent.clone();
 
         dialog.on_submit(move |text| {
-            let text = text.to_string();
-            if text.is_empty() {
+            if weak
+                .upgrade()
+                .is_some_and(|d| ignore_submit(d.get_sending(), d.get_closing()))
+            {
                 return;
             }
+            // Submit-only sends nothing from the dialog, so ignore its text
+            let text = match send_mode(&cli) {
+                server::SendMode::SubmitOnly => String::new(),
+                _ if text.is_empty() => return,
+                _ => text.to_string(),
+            };
+
+            let server = selected_server.borrow().clone();
+            let expand_cwd = server
+                .as_ref()
+                .map_or_else(|| expand_cwd.clone(), |s| s.cwd.clone());
+
+            let ctx = ExpandContext::from_cli(&cli, &params).with_cwd(&expand_cwd);
+            let pending = Some(&pending_redaction);
+            let expanded = match finalize_prompt(&text, &ctx, &cli, redactor.as_ref(), pending) {
+                Ok(prompt) => prompt,
+                Err(msg) => {
+                    if let Some(d) = weak.upgrade() {
+                        d.set_error_text(msg.into());
+                    }
+                    return;
+                }
+            };
 
-            // Expand @placeholders with param values
-            let expanded = expand_placeholders(&text, &params);
+            if cli.dry_run {
+                finish_dry_run(&expanded);
+            }
 
-            if let Some(ref client) = client {
-                let client = client.clone();
+            if let Some(server) = &server {
+                if let Some(d) = weak.upgrade() {
+                    d.set_sending(true);
+                }
                 let weak = weak.clone();
-
-                rt_handle.spawn(async move {
-                    match client.send_prompt(&expanded).await {
-                        Ok(()) => {
-                            let _ = slint::invoke_from_event_loop(move || {
-                                if let Some(d) = weak.upgrade() {
+                let sent = sent.clone();
+                spawn_send(
+                    &rt_handle,
+                    server_client(&cli, server),
+                    expanded,
+                    text,
+                    send_mode(&cli),
+                    AfterSend::from_cli(&cli).with_report(&cli, server),
+                    move |result| {
+                        if let Some(d) = weak.upgrade() {
+                            d.set_sending(false);
+                            match result {
+                                Ok(()) => {
+                                    sent.store(true, Ordering::SeqCst);
                                     // Trigger close animation + start close timer
                                     d.set_closing(true);
                                     d.set_dialog_open(false);
                                     d.set_start_close_timer(true);
                                 }
-                            });
-                        }
-                        Err(e) => {
-                            let err_msg = format!("Send failed: {}", e);
-                            let _ = slint::invoke_from_event_loop(move || {
-                                if let Some(d) = weak.upgrade() {
-                                    d.set_error_text(err_msg.into());
-                                }
-                            });
+                                Err(msg) => d.set_error_text(msg.into()),
+                            }
                         }
-                    }
-                });
+                    },
+                );
+            } else if let Some(d) = weak.upgrade() {
+                d.set_error_text(NOT_CONNECTED.into());
             }
         });
     }
@@ -191,253 +1317,1197 @@ fn main() -> Result<()> {
     // Wire up the close-window callback (called after close animation completes)
     {
         let weak = dialog.as_weak();
+        let daemon = cli.daemon;
+        let report_json = cli.report_json;
         dialog.on_close_window(move || {
+            let mut error = String::new();
             if let Some(d) = weak.upgrade() {
+                save_window_state(d.window());
                 let _ = d.hide();
+                // The daemon keeps running until the hotkey brings it back
+                if daemon {
+                    reset_dialog(&d);
+                    return;
+                }
+                error = d.get_error_text().to_string();
             }
-            slint::quit_event_loop().ok();
-
-            // On macOS, the Cocoa NSApplication run loop may not fully terminate
-            // after quit_event_loop(), leaving the process lingering in the Dock
-            // and app switcher. Force-exit to ensure clean teardown.
-            std::process::exit(0);
+            if report_json && !sent.load(Ordering::SeqCst) {
+                exit_with_failure_report(&dismissed_error(&error));
+            }
+            exit_event_loop();
         });
     }
 
-    // Run the Slint event loop
-    dialog.run().context("Slint event loop failed")?;
+    if !cli.daemon {
+        // Run the Slint event loop
+        dialog.run().context("Slint event loop failed")?;
+        return Ok(());
+    }
+
+    // Daemon: start hidden and keep the event loop alive with no window shown
+    let hotkey = hotkey::parse(&cli.hotkey)?;
+    let _manager = hotkey::register(hotkey)?;
+    dialog.set_dialog_open(false);
+    let hotkey_poll = slint::Timer::default();
+    {
+        let weak = dialog.as_weak();
+        let id = hotkey.id();
+        hotkey_poll.start(
+            slint::TimerMode::Repeated,
+            HOTKEY_POLL_INTERVAL,
+            move || {
+                if let (true, Some(d)) = (hotkey::take_pressed(id), weak.upgrade()) {
+                    show_dialog(&d);
+                }
+            },
+        );
+    }
+    log_info(
+        cli.quiet,
+        format!("Running in the background; press {} to open", cli.hotkey),
+    );
+    slint::run_event_loop_until_quit().context("Slint event loop failed")?;
 
     Ok(())
 }
 
-/// Center the dialog window on the primary monitor
-fn center_window(dialog: &PromptDialog, debug: bool) {
-    let window = dialog.window();
+/// Parse `--attach`: the path must exist; it's made absolute so the server can
+/// resolve it regardless of its own cwd
+fn parse_attachment(s: &str) -> std::result::Result<PathBuf, String> {
+    std::fs::canonicalize(s).map_err(|e| format!("cannot attach {}: {}", s, e))
+}
+
+/// Parse `--command`: a non-empty TUI command name such as `prompt.submit`
+fn parse_command_name(s: &str) -> std::result::Result<String, String> {
+    if s.is_empty() {
+        return Err("command name is empty".to_string());
+    }
+    match s
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')))
+    {
+        Some(c) => Err(format!("'{}' is not allowed in a command name", c)),
+        None => Ok(s.to_string()),
+    }
+}
+
+/// HTTP client for a discovered server, honoring `--timeout`, `--connect-timeout`, `--retries`,
+/// `--base-path`, `--command`, `--recheck-cwd` and `--attach`
+fn server_client(cli: &Cli, server: &server::Server) -> server::Client {
+    let client = match &server.socket {
+        Some(path) => server::Client::with_socket(path, request_timeout(cli)),
+        None => {
+            server::Client::with_tls(&server.host, server.port, server.tls, request_timeout(cli))
+        }
+    };
+    let client = client
+        .with_connect_timeout(connect_timeout(cli))
+        .with_attachments(&cli.attach)
+        .with_retries(cli.retries)
+        .with_base_path(&cli.base_path)
+        .with_submit_command(&cli.submit_command);
+    if cli.recheck_cwd {
+        client.with_expected_cwd(&server.cwd)
+    } else {
+        client
+    }
+}
+
+/// HTTP request timeout from `--timeout`; 0 disables it
+fn request_timeout(cli: &Cli) -> Option<Duration> {
+    (cli.timeout > 0).then(|| Duration::from_secs(cli.timeout))
+}
+
+/// Connect timeout from `--connect-timeout`; 0 disables it
+fn connect_timeout(cli: &Cli) -> Option<Duration> {
+    (cli.connect_timeout > 0).then(|| Duration::from_secs(cli.connect_timeout))
+}
+
+/// HTTPS settings from `--tls` / `--insecure`
+fn tls_options(cli: &Cli) -> server::TlsOptions {
+    server::TlsOptions {
+        enabled: cli.tls,
+        insecure: cli.insecure,
+    }
+}
+
+/// How prompts are delivered, from `--mode`, `--submit-only` and `--append-only`
+fn send_mode(cli: &Cli) -> server::SendMode {
+    if cli.mode == SendTarget::Session {
+        server::SendMode::NewSession
+    } else if cli.submit_only {
+        server::SendMode::SubmitOnly
+    } else if cli.append_only {
+        server::SendMode::AppendOnly
+    } else {
+        server::SendMode::AppendAndSubmit
+    }
+}
+
+/// Compile the redaction patterns if `--redact` or `--redact-pattern` was given
+fn build_redactor(cli: &Cli) -> Result<Option<Redactor>> {
+    if !cli.redact && cli.redact_patterns.is_empty() {
+        return Ok(None);
+    }
+    Redactor::new(&cli.redact_patterns).map(Some)
+}
+
+/// Whether a submit must be dropped: a send is still in flight (a repeated
+/// Ctrl+Enter would post the prompt twice) or the dialog is already closing
+fn ignore_submit(sending: bool, closing: bool) -> bool {
+    sending || closing
+}
+
+/// Error shown in the dialog for a failed send: the connect failure or timeout
+/// when that's what happened, else the outermost context
+fn send_error_text(error: &anyhow::Error) -> String {
+    match error.downcast_ref::<server::RequestError>() {
+        Some(cause) => format!("Send failed: {}", cause),
+        None => format!("Send failed: {}", error),
+    }
+}
+
+/// Send an expanded prompt on the runtime, then report back on the UI thread.
+///
+/// `after` runs on success, with the unexpanded `text` going to history.
+/// `on_result` runs in the Slint event loop with the error message if the
+/// send failed. The `--notify` notification is shown from a blocking task, so
+/// neither the runtime nor the event loop waits on the notification daemon.
+fn spawn_send(
+    rt_handle: &tokio::runtime::Handle,
+    client: server::Client,
+    expanded: String,
+    text: String,
+    mode: server::SendMode,
+    after: AfterSend,
+    on_result: impl FnOnce(Result<(), String>) + Send + 'static,
+) {
+    rt_handle.spawn(async move {
+        let result = match client.send_prompt(&expanded, mode).await {
+            Ok(session_id) => {
+                after.log_session(session_id.as_deref());
+                after.run(&text, &expanded);
+                Ok(())
+            }
+            Err(e) => Err(send_error_text(&e)),
+        };
+        let outcome = result.clone();
+        tokio::task::spawn_blocking(move || {
+            notify::notify_outcome(&notify::DesktopNotifier, after.notify, &outcome)
+        });
+        let _ = slint::invoke_from_event_loop(move || on_result(result));
+    });
+}
+
+/// Write the prompt `--dry-run` would have sent
+fn write_dry_run(expanded: &str, out: &mut impl Write) -> Result<()> {
+    writeln!(out, "{}", expanded).context("Failed to write dry-run prompt")?;
+    out.flush().context("Failed to write dry-run prompt")
+}
+
+/// Print the prompt to stdout instead of sending it, then exit successfully
+fn finish_dry_run(expanded: &str) {
+    if let Err(e) = write_dry_run(expanded, &mut std::io::stdout().lock()) {
+        log::error!("{:#}", e);
+    }
+    exit_event_loop();
+}
+
+/// Quit the event loop and the process once a window has closed
+fn exit_event_loop() {
+    slint::quit_event_loop().ok();
+
+    // On macOS, the Cocoa NSApplication run loop may not fully terminate
+    // after quit_event_loop(), leaving the process lingering in the Dock
+    // and app switcher. Force-exit to ensure clean teardown.
+    std::process::exit(0);
+}
+
+/// The `--report-json` object for a prompt that was sent to `server`
+fn send_report(text: &str, expanded: &str, server: &server::Server) -> serde_json::Value {
+    serde_json::json!({
+        "ok": true,
+        "prompt": text,
+        "expanded": expanded,
+        "port": server.tcp_port(),
+        "pid": server.pid,
+        "cwd": server.cwd,
+    })
+}
+
+/// The `--report-json` object for a run that sent nothing
+fn failure_report(error: &str) -> serde_json::Value {
+    serde_json::json!({ "ok": false, "error": error })
+}
+
+/// Why a window closed without sending: the last error it showed, if any
+fn dismissed_error(error_text: &str) -> String {
+    if error_text.is_empty() {
+        "Dismissed without sending".to_string()
+    } else {
+        error_text.to_string()
+    }
+}
+
+/// Write a `--report-json` object to `out` as a single line
+fn write_report(report: &serde_json::Value, out: &mut impl Write) -> Result<()> {
+    writeln!(out, "{}", report).context("Failed to write report")?;
+    out.flush().context("Failed to write report")
+}
+
+/// Print the `--report-json` failure object and exit nonzero
+fn exit_with_failure_report(error: &str) -> ! {
+    if let Err(e) = write_report(&failure_report(error), &mut std::io::stdout().lock()) {
+        log::error!("{:#}", e);
+    }
+    slint::quit_event_loop().ok();
+    std::process::exit(1);
+}
+
+/// Compute the window width needed to show the placeholder hint unclipped.
+///
+/// Never narrower than the default width or `min_width`, never wider than
+/// `MAX_WINDOW_WIDTH`.
+fn compute_window_width(hint: &str, min_width: Option<f32>) -> f32 {
+    let hint_width = hint.chars().count() as f32 * HINT_CHAR_WIDTH + HINT_RESERVED_WIDTH;
+    let floor = min_width.unwrap_or(0.0).max(DEFAULT_WINDOW_WIDTH);
+    hint_width.max(floor).min(MAX_WINDOW_WIDTH)
+}
+
+/// Window height that fits `text` without scrolling, counting wrapped lines at
+/// `win_width`.
+///
+/// Never lower than the default height, never higher than `MAX_WINDOW_HEIGHT`.
+fn compute_window_height(text: &str, win_width: f32) -> f32 {
+    let columns = ((win_width - INPUT_RESERVED_WIDTH) / INPUT_CHAR_WIDTH).max(1.0) as usize;
+    let lines: usize = text
+        .split('\n')
+        .map(|line| line.chars().count().div_ceil(columns).max(1))
+        .sum();
+    let extra = lines.saturating_sub(AUTOSIZE_BASE_LINES) as f32 * INPUT_LINE_HEIGHT;
+    (DEFAULT_WINDOW_HEIGHT + extra).min(MAX_WINDOW_HEIGHT)
+}
+
+/// Position a window of the given logical size according to `--position`.
+///
+/// `active-window` falls back to screen centering when the platform can't
+/// report the focused window.
+fn place_window(
+    window: &slint::Window,
+    win_width: f32,
+    win_height: f32,
+    position: placement::Position,
+) {
+    if position == placement::Position::ActiveWindow {
+        let scale = window.scale_factor() as f64;
+        if let Some(frame) = placement::active_window_frame(scale) {
+            let (x, y) =
+                placement::center_over(frame, win_width as f64 * scale, win_height as f64 * scale);
+            window.set_position(slint::PhysicalPosition::new(x, y));
+            log::debug!("Focused window: {:?}, window pos: ({}, {})", frame, x, y);
+            return;
+        }
+        log::debug!("Focused window unavailable, centering on screen");
+    }
+
+    center_window(window, win_width, win_height);
+}
+
+/// Show the daemon's dialog (or raise it if already open) with the input focused
+fn show_dialog(dialog: &PromptDialog) {
+    if let Err(e) = dialog.show() {
+        log::error!("Failed to show dialog: {}", e);
+        return;
+    }
+    dialog.set_dialog_open(true);
+    dialog.invoke_focus_input();
+
+    #[cfg(not(target_os = "android"))]
+    {
+        use i_slint_backend_winit::WinitWindowAccessor;
+        dialog
+            .window()
+            .with_winit_window(|winit_win| winit_win.focus_window());
+    }
+}
+
+/// Clear the daemon's dialog after it hides, so the next show starts fresh
+fn reset_dialog(dialog: &PromptDialog) {
+    dialog.set_input_text("".into());
+    dialog.invoke_text_changed("".into());
+    dialog.set_error_text("".into());
+    dialog.set_sending(false);
+    dialog.set_closing(false);
+    dialog.set_start_close_timer(false);
+    dialog.set_dialog_open(false);
+}
+
+/// Move the window to where it last closed, if that still fits on a monitor.
+///
+/// Returns false when there is no saved position or it would be off-screen
+/// (e.g. its monitor was disconnected), leaving placement to the caller.
+fn restore_window(window: &slint::Window) -> bool {
+    let state = window_state::default_path()
+        .and_then(|path| window_state::WindowState::load(&path).ok().flatten());
+    let Some(state) = state else {
+        return false;
+    };
+
+    #[cfg(not(target_os = "android"))]
+    {
+        use i_slint_backend_winit::WinitWindowAccessor;
+        let position = window
+            .with_winit_window(|winit_win| {
+                let monitors: Vec<window_state::MonitorRect> = winit_win
+                    .available_monitors()
+                    .map(|monitor| {
+                        let (pos, size) = (monitor.position(), monitor.size());
+                        window_state::MonitorRect {
+                            x: pos.x,
+                            y: pos.y,
+                            width: size.width,
+                            height: size.height,
+                        }
+                    })
+                    .collect();
+                window_state::restore_position(&state, &monitors)
+            })
+            .flatten();
+        if let Some((x, y)) = position {
+            window.set_position(slint::PhysicalPosition::new(x, y));
+            log::debug!("Restored window pos: ({}, {})", x, y);
+            return true;
+        }
+    }
+
+    log::debug!("Saved window frame {:?} is off-screen, centering", state);
+    false
+}
+
+/// Remember where the window is so the next launch can reopen it there
+fn save_window_state(window: &slint::Window) {
+    let Some(path) = window_state::default_path() else {
+        return;
+    };
+    let (position, size) = (window.position(), window.size());
+    let state = window_state::WindowState {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+    };
+    if let Err(e) = state.save(&path) {
+        log::info!("{:#}", e);
+    }
+}
+
+/// Center a window of the given logical size on the primary monitor
+fn center_window(window: &slint::Window, win_width: f32, win_height: f32) {
     let scale = window.scale_factor();
-    let win_width = 680.0_f32;
-    let win_height = 240.0_f32;
 
     // Try to get screen size via winit backend
     #[cfg(not(target_os = "android"))]
     {
         use i_slint_backend_winit::WinitWindowAccessor;
         window.with_winit_window(|winit_win| {
-            if let Some(monitor) = winit_win
+            let screen = winit_win
                 .current_monitor()
                 .or_else(|| winit_win.primary_monitor())
-            {
-                let screen_size = monitor.size();
-                let screen_w = screen_size.width as f32;
-                let screen_h = screen_size.height as f32;
+                .map(|monitor| {
+                    let size = monitor.size();
+                    (size.width as f32, size.height as f32)
+                });
+            let (x, y) = centered_position(screen, win_width, win_height, scale);
+            window.set_position(slint::PhysicalPosition::new(x, y));
+
+            match screen {
+                Some((screen_w, screen_h)) => log::debug!(
+                    "Screen: {}x{}, scale: {}, window pos: ({}, {})",
+                    screen_w,
+                    screen_h,
+                    scale,
+                    x,
+                    y
+                ),
+                None => log::debug!("No monitor info available, window pos: ({}, {})", x, y),
+            }
+        });
+    }
+}
+
+/// Physical position centering a window horizontally and slightly above center
+/// on a `screen` of the given physical size; `FALLBACK_WINDOW_POSITION` when
+/// the screen size is unknown, so the window still lands on-screen
+fn centered_position(
+    screen: Option<(f32, f32)>,
+    win_width: f32,
+    win_height: f32,
+    scale: f32,
+) -> (i32, i32) {
+    let Some((screen_w, screen_h)) = screen else {
+        return FALLBACK_WINDOW_POSITION;
+    };
+    let x = (screen_w - win_width * scale) / 2.0;
+    let y = (screen_h - win_height * scale) / 3.0; // Slightly above center
+    (x as i32, y as i32)
+}
+
+/// Set the dialog's colors for `--theme`, asking the OS for `system`
+fn apply_theme(dialog: &PromptDialog, theme: theme::Theme) {
+    let system_dark = if theme == theme::Theme::System {
+        theme::system_is_dark()
+    } else {
+        None
+    };
+    let colors = theme.resolve(system_dark).colors();
+    dialog.set_theme_background(colors.background);
+    dialog.set_theme_foreground(colors.foreground);
+    dialog.set_theme_accent(colors.accent);
+    dialog.set_theme_argument(colors.argument);
+}
+
+/// Hand the keybinding registry's keys and help text to the dialog
+fn apply_keybindings(dialog: &PromptDialog) {
+    use keybindings::{key_text, Action};
+
+    dialog.set_submit_key(key_text(Action::Submit, false));
+    dialog.set_autocomplete_key(key_text(Action::AcceptAutocomplete, false));
+    dialog.set_dismiss_key(key_text(Action::Dismiss, false));
+    dialog.set_help_key(key_text(Action::ShowHelp, false));
+    dialog.set_help_empty_key(key_text(Action::ShowHelp, true));
+    dialog.set_history_previous_key(key_text(Action::HistoryPrevious, false));
+    dialog.set_history_next_key(key_text(Action::HistoryNext, false));
+    dialog.set_expand_key(key_text(Action::ExpandPreview, false));
+    dialog.set_undo_key(key_text(Action::Undo, false));
+    dialog.set_redo_key(key_text(Action::Redo, false));
+    dialog.set_help_text(keybindings::help_text(cfg!(target_os = "macos")).into());
+}
+
+/// Build the connection status label shown next to the status dot
+fn build_status_text(connected: bool, port: Option<u16>) -> String {
+    match (connected, port) {
+        (true, Some(port)) => format!("Connected :{}", port),
+        (true, None) => "Connected".to_string(),
+        (false, _) => "Disconnected".to_string(),
+    }
+}
+
+/// Parse --param key=value pairs into a HashMap
+fn parse_params(raw: &[String]) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    for param in raw {
+        if let Some((key, value)) = param.split_once('=') {
+            let key = key.trim().to_string();
+            let value = value.trim().to_string();
+            if !key.is_empty() {
+                map.insert(key, value);
+            }
+        }
+    }
+    map
+}
+
+/// Snapshot of the process environment, skipping non-UTF-8 entries
+fn env_vars() -> Vec<(String, String)> {
+    std::env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .collect()
+}
+
+/// Collect params from environment variables whose name starts with `prefix`.
+///
+/// The prefix is stripped and the remainder lowercased, so with prefix `PROMPT_`
+/// the variable `PROMPT_PATH` becomes the `@path` placeholder.
+fn collect_env_params<I>(vars: I, prefix: &str) -> HashMap<String, String>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut map = HashMap::new();
+    if prefix.is_empty() {
+        return map;
+    }
+    for (name, value) in vars {
+        if let Some(key) = name.strip_prefix(prefix) {
+            let key = key.trim().to_lowercase();
+            if !key.is_empty() {
+                map.insert(key, value);
+            }
+        }
+    }
+    map
+}
+
+/// Every param source merged, later ones winning: the project's
+/// `.prompt-dialog.toml`, env params (if enabled), `--params-file`, then
+/// explicit `--param` pairs
+fn load_params<I>(cli: &Cli, cwd: &Path, vars: I) -> Result<HashMap<String, String>>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let project_params = match project_config::find(cwd) {
+        Some(path) if !cli.no_project_config => {
+            log::info!("Loading project params from {}", path.display());
+            project_config::load(&path)?
+        }
+        _ => HashMap::new(),
+    };
+    let env_params = match &cli.param_env_prefix {
+        Some(prefix) => collect_env_params(vars, prefix),
+        None => HashMap::new(),
+    };
+    let file_params = match &cli.params_file {
+        Some(path) => params_file::load(path)?,
+        None => HashMap::new(),
+    };
+    Ok(merge_params(
+        merge_params(merge_params(project_params, env_params), file_params),
+        parse_params(&cli.params),
+    ))
+}
+
+/// Merge two param maps; entries in `overrides` win on conflicts
+fn merge_params(
+    mut base: HashMap<String, String>,
+    overrides: HashMap<String, String>,
+) -> HashMap<String, String> {
+    base.extend(overrides);
+    base
+}
+
+/// Expand a window title template.
+///
+/// Supports the usual placeholders plus `@cwd`, which resolves to the server's
+/// working directory unless a `cwd` param overrides it.
+fn expand_title(
+    template: &str,
+    params: &HashMap<String, String>,
+    cwd: Option<&Path>,
+    prefix: char,
+) -> String {
+    let mut params = params.clone();
+    if let Some(cwd) = cwd {
+        params
+            .entry("cwd".to_string())
+            .or_insert_with(|| cwd.display().to_string());
+    }
+    let ctx = ExpandContext {
+        prefix,
+        ..ExpandContext::new(&params)
+    };
+    expand_placeholders_with(template, &ctx)
+}
+
+/// The prompt with its placeholders expanded, for Ctrl+E; `None` if nothing
+/// would change.
+///
+/// Post-processing (`--envelope`, `--auto-fence`) is left for the submit, so
+/// it isn't applied twice, and so is `@cursor`, which only means something
+/// in the text that is sent.
+fn preview_expansion(text: &str, ctx: &ExpandContext) -> Option<String> {
+    let expanded = expand_placeholders_with(text, ctx);
+    (expanded != text).then_some(expanded)
+}
+
+/// Write an informational line to `out` unless `--quiet` is set
+fn log_to(out: &mut impl Write, quiet: bool, message: impl std::fmt::Display) {
+    if !quiet {
+        let _ = writeln!(out, "{}", message);
+    }
+}
+
+/// Write an informational line to stderr unless `--quiet` is set
+fn log_info(quiet: bool, message: impl std::fmt::Display) {
+    log_to(&mut std::io::stderr().lock(), quiet, message);
+}
+
+/// History for Up/Down recall; empty if there is none or it can't be read
+fn load_recall() -> history::Recall {
+    history::default_path()
+        .and_then(|path| history::History::load(&path).ok())
+        .map(|h| history::Recall::new(&h))
+        .unwrap_or_default()
+}
+
+/// Append a submitted (pre-expansion) prompt to the history file
+fn record_history(text: &str) {
+    let Some(path) = history::default_path() else {
+        return;
+    };
+    if let Err(e) = history::record(&path, text) {
+        log::info!("Failed to record history: {:#}", e);
+    }
+}
+
+/// Count a successful send in the local stats file
+fn record_stats(text: &str, expanded: &str) {
+    let Some(path) = stats::default_path() else {
+        return;
+    };
+    if let Err(e) = stats::record(&path, text, expanded) {
+        log::info!("Failed to record stats: {:#}", e);
+    }
+}
+
+/// Print recent history entries with their indices, most recent first
+fn list_history(limit: usize, out: &mut impl Write) -> Result<()> {
+    let path = history::default_path().context("No config directory for history")?;
+    let history = history::History::load(&path)?;
+    for (index, entry) in history.recent().take(limit) {
+        // Keep one line per entry; multi-line prompts are shown on a single line
+        writeln!(out, "{:>3}  {}", index, entry.replace('\n', " "))
+            .context("Failed to write history")?;
+    }
+    Ok(())
+}
+
+/// The state kinds picked by `reset` flags
+fn reset_selection(
+    all: bool,
+    history: bool,
+    stats: bool,
+    cache: bool,
+    window: bool,
+) -> Vec<reset::State> {
+    if all {
+        return reset::State::ALL.to_vec();
+    }
+    [
+        (history, reset::State::History),
+        (stats, reset::State::Stats),
+        (cache, reset::State::Cache),
+        (window, reset::State::Window),
+    ]
+    .into_iter()
+    .filter_map(|(selected, state)| selected.then_some(state))
+    .collect()
+}
+
+/// Delete the selected state files, listing each removed file
+fn reset_state(states: &[reset::State], quiet: bool, out: &mut impl Write) -> Result<()> {
+    let dir = reset::default_dir().context("No config directory for state")?;
+    let removed = reset::reset(&dir, states)?;
+    if removed.is_empty() {
+        log_to(out, quiet, "Nothing to reset");
+    }
+    for path in removed {
+        log_to(out, quiet, format_args!("Removed {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Read the dialog's initial prompt from `reader` (stdin), dropping trailing
+/// newlines; `None` if there's nothing to pre-fill
+fn read_prefill(mut reader: impl Read) -> Result<Option<String>> {
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .context("Failed to read prompt from stdin")?;
+    let text = text.trim_end_matches(['\n', '\r']);
+    Ok((!text.trim().is_empty()).then(|| text.to_string()))
+}
+
+/// Scan for OpenCode servers regardless of cwd and print them to `out`
+fn list_servers(cli: &Cli, json: bool, out: &mut impl Write) -> Result<()> {
+    let (rt, _) = build_runtime()?;
+    let servers = rt.block_on(with_timeout(
+        server::list_servers(scan_limits(cli), tls_options(cli)),
+        Duration::from_secs(cli.discovery_timeout),
+        "Server discovery",
+    ))?;
+    write_servers(&servers, json, out)
+}
+
+/// Probe `port` on `--host` without scanning or a window; quiet on success
+/// unless `--debug`, when the server's cwd is printed to `out`
+fn ping(cli: &Cli, port: u16, timeout: u64, out: &mut impl Write) -> Result<()> {
+    let (rt, _) = build_runtime()?;
+    let timeout = (timeout > 0).then(|| Duration::from_secs(timeout));
+    let server = rt
+        .block_on(server::ping_server(
+            &cli.host,
+            port,
+            &cli.base_path,
+            tls_options(cli),
+            timeout,
+        ))
+        .with_context(|| format!("No OpenCode server answering on {}:{}", cli.host, port))?;
+    if cli.debug {
+        writeln!(out, "{}", server.cwd.display()).context("Failed to write server cwd")?;
+    }
+    Ok(())
+}
+
+/// Print `servers` as an aligned table, or as a JSON array with `json`
+fn write_servers(servers: &[server::Server], json: bool, out: &mut impl Write) -> Result<()> {
+    if json {
+        let entries: Vec<serde_json::Value> = servers
+            .iter()
+            .map(|s| serde_json::json!({ "pid": s.pid, "port": s.port, "cwd": s.cwd }))
+            .collect();
+        let data = serde_json::to_string_pretty(&entries).context("Failed to serialize servers")?;
+        return writeln!(out, "{}", data).context("Failed to write servers");
+    }
+
+    writeln!(out, "{:<8} {:<6} CWD", "PID", "PORT").context("Failed to write servers")?;
+    for s in servers {
+        writeln!(out, "{:<8} {:<6} {}", s.pid, s.port, s.cwd.display())
+            .context("Failed to write servers")?;
+    }
+    Ok(())
+}
+
+/// Look up history entry `index`, then expand and send it without the GUI
+fn resend_history(
+    cli: &Cli,
+    params: &HashMap<String, String>,
+    cwd: &Path,
+    index: usize,
+) -> Result<()> {
+    let path = history::default_path().context("No config directory for history")?;
+    let history = history::History::load(&path)?;
+    let text = history.get(index)?.to_string();
+    send_headless(cli, params, cwd, &text)
+}
+
+/// `expand` / `--print-expanded`: write `text` expanded as it would be sent (built-ins resolved
+/// against `cwd`, `@cursor` removed). With `--strict`, unknown tokens left
+/// after expansion are an error and nothing is written.
+fn expand_command(
+    cli: &Cli,
+    params: &HashMap<String, String>,
+    cwd: &Path,
+    text: &str,
+    out: &mut impl Write,
+) -> Result<()> {
+    let ctx = ExpandContext::from_cli(cli, params).with_cwd(cwd);
+    let expanded = prepare_prompt(text, &ctx, &PostProcess::from_cli(cli)).text;
+    if cli.strict {
+        check_unknown_tokens(&expanded, &known_placeholders(cli, params), cli.prefix)
+            .map_err(anyhow::Error::msg)?;
+    }
+    let (expanded, _) = take_cursor(&expanded, cli.prefix);
+    writeln!(out, "{}", expanded).context("Failed to write expanded prompt")
+}
+
+/// Report undeclared placeholders in a template file; any finding is an error
+fn validate_template(file: &Path, out: &mut impl Write) -> Result<()> {
+    let text = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read template {}", file.display()))?;
+    let parsed =
+        template::parse(&text).with_context(|| format!("Invalid template {}", file.display()))?;
+
+    let missing = template::undeclared(&parsed, BUILTIN_PLACEHOLDERS);
+    for r in &missing {
+        writeln!(
+            out,
+            "{}:{}: undeclared placeholder @{}",
+            file.display(),
+            r.line,
+            r.name
+        )
+        .context("Failed to write report")?;
+    }
+
+    if missing.is_empty() {
+        writeln!(out, "{}: ok", file.display()).context("Failed to write report")?;
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} undeclared placeholder(s) in {}",
+            missing.len(),
+            file.display()
+        ))
+    }
+}
+
+/// Discover the server, expand `text`, and send it without creating a window
+fn send_headless(
+    cli: &Cli,
+    params: &HashMap<String, String>,
+    cwd: &Path,
+    text: &str,
+) -> Result<()> {
+    // The same checks and redaction as a send, against the cwd it would use
+    let redactor = build_redactor(cli)?;
+    let finalize = |cwd: &Path| {
+        let ctx = ExpandContext::from_cli(cli, params).with_cwd(cwd);
+        finalize_prompt(text, &ctx, cli, redactor.as_ref(), None).map_err(anyhow::Error::msg)
+    };
+    if cli.dry_run {
+        let expanded = finalize(cwd)?;
+        return write_dry_run(&expanded, &mut std::io::stdout().lock());
+    }
+
+    let (rt, _) = build_runtime()?;
+    let server = rt.block_on(with_timeout(
+        discover_and_connect(cwd, cli),
+        Duration::from_secs(cli.discovery_timeout),
+        "Server discovery",
+    ))?;
+
+    let expanded = finalize(&server.cwd)?;
+    let client = server_client(cli, &server);
+    let session_id = rt
+        .block_on(client.send_prompt(&expanded, send_mode(cli)))
+        .context("Send failed")?;
+
+    let after = AfterSend::from_cli(cli).with_report(cli, &server);
+    after.log_session(session_id.as_deref());
+    after.run(text, &expanded);
+    Ok(())
+}
 
-                let x = (screen_w - win_width * scale) / 2.0;
-                let y = (screen_h - win_height * scale) / 3.0; // Slightly above center
+/// Character and word counts shown under the input
+fn text_counts(text: &str) -> (usize, usize) {
+    (text.chars().count(), text.split_whitespace().count())
+}
 
-                window.set_position(slint::PhysicalPosition::new(x as i32, y as i32));
+/// Which kind of tokio runtime was built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuntimeFlavor {
+    MultiThread,
+    CurrentThread,
+}
 
-                if debug {
-                    eprintln!(
-                        "Screen: {}x{}, scale: {}, window pos: ({}, {})",
-                        screen_w, screen_h, scale, x as i32, y as i32
-                    );
-                }
-            }
-        });
+/// Build the tokio runtime, falling back to a current-thread runtime when a
+/// multi-threaded one can't be created (e.g. thread limits in constrained envs)
+fn build_runtime() -> Result<(tokio::runtime::Runtime, RuntimeFlavor)> {
+    let (rt, flavor) = build_runtime_with(
+        || {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+        },
+        || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+        },
+    )?;
+    if flavor == RuntimeFlavor::CurrentThread {
+        log::info!("Multi-threaded runtime unavailable, using current-thread runtime");
     }
+    Ok((rt, flavor))
 }
 
-/// Parse --param key=value pairs into a HashMap
-fn parse_params(raw: &[String]) -> HashMap<String, String> {
-    let mut map = HashMap::new();
-    for param in raw {
-        if let Some((key, value)) = param.split_once('=') {
-            let key = key.trim().to_string();
-            let value = value.trim().to_string();
-            if !key.is_empty() {
-                map.insert(key, value);
-            }
-        }
+/// Try `multi_thread` first, then `current_thread`, reporting both errors if neither works
+fn build_runtime_with<T>(
+    multi_thread: impl FnOnce() -> std::io::Result<T>,
+    current_thread: impl FnOnce() -> std::io::Result<T>,
+) -> Result<(T, RuntimeFlavor)> {
+    match multi_thread() {
+        Ok(rt) => Ok((rt, RuntimeFlavor::MultiThread)),
+        Err(multi_err) => match current_thread() {
+            Ok(rt) => Ok((rt, RuntimeFlavor::CurrentThread)),
+            Err(current_err) => Err(anyhow!(
+                "Failed to create async runtime (multi-thread: {}; current-thread: {})",
+                multi_err,
+                current_err
+            )),
+        },
     }
-    map
 }
 
-/// Expand @placeholder tokens in text with param values and built-in special tokens.
-///
-/// Built-in tokens (always available):
-///   - `@clipboard` — current system clipboard text content
+/// Keep the runtime running while the Slint event loop owns the main thread.
 ///
-/// User params from `--param key=value` are expanded as `@key`.
-/// Matches the longest key first to avoid partial replacements.
-fn expand_placeholders(text: &str, params: &HashMap<String, String>) -> String {
-    let mut result = text.to_string();
-
-    // Expand built-in special tokens first
-    result = expand_builtins(&result);
+/// A multi-threaded runtime runs on its own workers and is simply returned to be
+/// kept alive. A current-thread runtime only makes progress while something
+/// blocks on it, so it is moved to a helper thread that does exactly that.
+fn drive_in_background(
+    rt: tokio::runtime::Runtime,
+    flavor: RuntimeFlavor,
+) -> Option<tokio::runtime::Runtime> {
+    match flavor {
+        RuntimeFlavor::MultiThread => Some(rt),
+        RuntimeFlavor::CurrentThread => {
+            std::thread::spawn(move || rt.block_on(std::future::pending::<()>()));
+            None
+        }
+    }
+}
 
-    // Expand user params
-    if !params.is_empty() {
-        let mut keys: Vec<&String> = params.keys().collect();
-        keys.sort_by_key(|k| std::cmp::Reverse(k.len()));
+/// Await `fut`, failing with a "timed out" error if it doesn't finish within `timeout`
+async fn with_timeout<T, F>(fut: F, timeout: Duration, what: &str) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow!(
+            "{} timed out after {}s",
+            what,
+            timeout.as_secs_f32()
+        )),
+    }
+}
 
-        for key in keys {
-            let placeholder = format!("@{}", key);
-            if let Some(value) = params.get(key) {
-                result = result.replace(&placeholder, value);
-            }
-        }
+/// Discover the servers to offer: every match with `--pick-server`, else the
+/// single best one. Startup and the Retry button both go through here.
+async fn discover_servers(cwd: &Path, cli: &Cli) -> Result<Vec<server::Server>> {
+    if cli.pick_server {
+        discover_all_and_connect(cwd, cli).await
+    } else {
+        discover_and_connect(cwd, cli)
+            .await
+            .map(|server| vec![server])
     }
+}
 
-    result
+/// Discover every local server matching `cwd` for `--pick-server`.
+///
+/// Explicit ports and sockets, remote hosts and mDNS name a single server, so those
+/// fall back to regular discovery.
+async fn discover_all_and_connect(cwd: &Path, cli: &Cli) -> Result<Vec<server::Server>> {
+    if cli.port.is_some() || cli.socket.is_some() || cli.mdns || !server::is_local_host(&cli.host) {
+        return discover_and_connect(cwd, cli)
+            .await
+            .map(|server| vec![server]);
+    }
+    server::discover_all_servers(cwd, scan_limits(cli), tls_options(cli), cwd_match(cli)).await
 }
 
-/// Expand built-in special tokens like @clipboard
-fn expand_builtins(text: &str) -> String {
-    let mut result = text.to_string();
+/// How a server's cwd must match ours, from `--exact-cwd`
+fn cwd_match(cli: &Cli) -> server::CwdMatch {
+    if cli.exact_cwd {
+        server::CwdMatch::Exact
+    } else {
+        server::CwdMatch::Prefix
+    }
+}
 
-    if result.contains("@clipboard") {
-        let clipboard_text = read_clipboard().unwrap_or_default();
-        result = result.replace("@clipboard", &clipboard_text);
+/// Process scan bounds from `--scan-max-candidates` / `--scan-max-processes`
+fn scan_limits(cli: &Cli) -> server::ScanLimits {
+    server::ScanLimits {
+        max_candidates: cli.scan_max_candidates,
+        max_processes: cli.scan_max_processes,
     }
+}
 
-    result
+/// Fill the server picker, which only shows with more than one server
+fn show_server_choices(dialog: &PromptDialog, servers: &[server::Server]) {
+    let labels: Vec<slint::SharedString> = servers
+        .iter()
+        .map(|s| server_choice_label(s).into())
+        .collect();
+    dialog.set_server_choices(Rc::new(slint::VecModel::from(labels)).into());
 }
 
-/// Read text content from the system clipboard
-fn read_clipboard() -> Option<String> {
-    arboard::Clipboard::new()
-        .ok()
-        .and_then(|mut cb| cb.get_text().ok())
-        .filter(|s| !s.is_empty())
+/// Picker entry for a server: port and working directory
+fn server_choice_label(server: &server::Server) -> String {
+    format!(":{}  {}", server.port, server.cwd.display())
 }
 
-/// Build a highlight overlay text where only @placeholder tokens are visible
-/// and all other characters are replaced with spaces (preserving positions).
-///
-/// This works because the overlay Text uses the same font/size/wrap as the input,
-/// so characters at the same positions line up exactly.
-fn build_highlight_text(text: &str, placeholders: &[String]) -> String {
-    let mut mask = vec![false; text.len()];
-
-    // Mark character positions that are part of @placeholder tokens
-    for name in placeholders {
-        let token = format!("@{}", name);
-        let mut search_from = 0;
-        while let Some(pos) = text[search_from..].find(&token) {
-            let abs_pos = search_from + pos;
-            let end = abs_pos + token.len();
-            // Check that the token ends at a word boundary
-            let at_end = end >= text.len()
-                || !text.as_bytes()[end].is_ascii_alphanumeric() && text.as_bytes()[end] != b'_';
-            if at_end {
-                for item in mask.iter_mut().take(end).skip(abs_pos) {
-                    *item = true;
-                }
-            }
-            search_from = abs_pos + 1;
-        }
+/// Discover and connect to an OpenCode server
+async fn discover_and_connect(cwd: &Path, cli: &Cli) -> Result<server::Server> {
+    log::info!("Discovering OpenCode server (cwd: {})...", cwd.display());
+
+    if let Some(socket) = &cli.socket {
+        return server::discover_socket_server(socket, &cli.base_path).await;
     }
 
-    // Build overlay: keep @token chars, replace everything else with spaces
-    text.char_indices()
-        .map(|(i, c)| {
-            if i < mask.len() && mask[i] {
-                c
-            } else if c == '\n' {
-                '\n' // Preserve newlines for wrap alignment
-            } else {
-                ' '
-            }
-        })
-        .collect()
-}
+    if cli.mdns && cli.port.is_none() {
+        return server::discover_mdns_server(cwd, tls_options(cli), cwd_match(cli)).await;
+    }
 
-/// Find autocomplete suggestion for the current @partial token being typed.
-///
-/// Looks for an `@` followed by partial text at the end of the input (or before
-/// trailing whitespace), and returns the best matching placeholder name.
-fn find_autocomplete(text: &str, placeholders: &[String]) -> (String, bool) {
-    // Find the last '@' that starts an incomplete token
-    if let Some(at_pos) = text.rfind('@') {
-        let after_at = &text[at_pos + 1..];
+    let limits = scan_limits(cli);
+    if cli.port.is_some() || !server::is_local_host(&cli.host) {
+        return server::discover_server(
+            cwd,
+            &cli.host,
+            cli.port,
+            &cli.base_path,
+            limits,
+            tls_options(cli),
+            cwd_match(cli),
+        )
+        .await;
+    }
 
-        // The partial must be at the end (no spaces after it)
-        if after_at.contains(' ') || after_at.contains('\n') {
-            return (String::new(), false);
+    // Try the port this cwd last connected to before scanning processes
+    let cache_path = port_cache::default_path();
+    let cached = cache_path
+        .as_deref()
+        .and_then(|path| port_cache::lookup(path, cwd));
+    // Then instance files and port files, and only then the (slower) process scan
+    let instances: Vec<server::instances::Instance> = match &cli.instance_dir {
+        Some(dir) => vec![dir.clone()],
+        None => server::instances::default_dirs(),
+    }
+    .iter()
+    .flat_map(|dir| server::instances::load_instances(dir))
+    .collect();
+    let port_files = match &cli.port_file {
+        Some(path) => vec![path.clone()],
+        None => server::default_port_files(cwd),
+    };
+    let tls = tls_options(cli);
+    let cwd_match = cwd_match(cli);
+    let discover = async {
+        if let Some(server) = server::discover_from_instances(cwd, &instances, tls, cwd_match).await
+        {
+            return Ok(server);
         }
+        if let Some(server) =
+            server::discover_from_port_files(cwd, &port_files, tls, cwd_match).await
+        {
+            return Ok(server);
+        }
+        server::discover_server(cwd, &cli.host, None, "", limits, tls, cwd_match).await
+    };
+    let server = server::discover_with_cached_port(cwd, cached, tls, cwd_match, discover).await?;
 
-        let partial = after_at.to_lowercase();
-
-        // Don't suggest if the token already exactly matches a placeholder
-        if placeholders.iter().any(|p| p == &partial) {
-            return (String::new(), false);
+    if let Some(path) = cache_path {
+        if let Err(e) = port_cache::record(&path, cwd, server.port) {
+            log::info!("Failed to update port cache: {:#}", e);
         }
+    }
+    Ok(server)
+}
 
-        // Find matching placeholders (prefix match)
-        if !partial.is_empty() {
-            let matches: Vec<&String> = placeholders
-                .iter()
-                .filter(|p| p.to_lowercase().starts_with(&partial))
-                .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::ClipboardProvider;
 
-            if let Some(best) = matches.first() {
-                return (format!("@{}", best), true);
-            }
-        } else {
-            // Just typed '@', show first placeholder
-            if let Some(first) = placeholders.first() {
-                return (format!("@{}", first), true);
-            }
+    /// Clipboard provider returning fixed content
+    struct FakeClipboard(Option<String>);
+
+    impl ClipboardProvider for FakeClipboard {
+        fn read_text(&self) -> Option<String> {
+            self.0.clone()
         }
     }
 
-    (String::new(), false)
-}
+    /// Expansion context with no params and a fake clipboard
+    fn ctx_with_clipboard<'a>(
+        params: &'a HashMap<String, String>,
+        clipboard: &'a FakeClipboard,
+    ) -> ExpandContext<'a> {
+        ExpandContext {
+            clipboard,
+            ..ExpandContext::new(params)
+        }
+    }
 
-/// Apply the autocomplete: replace the current @partial token with the full suggestion.
-fn apply_autocomplete(text: &str, placeholders: &[String]) -> String {
-    if let Some(at_pos) = text.rfind('@') {
-        let after_at = &text[at_pos + 1..];
+    #[test]
+    fn test_centered_position() {
+        assert_eq!(
+            centered_position(Some((1920.0, 1080.0)), 680.0, 240.0, 1.0),
+            (620, 280)
+        );
+        assert_eq!(
+            centered_position(Some((3840.0, 2160.0)), 680.0, 240.0, 2.0),
+            (1240, 560)
+        );
+    }
 
-        if after_at.contains(' ') || after_at.contains('\n') {
-            return text.to_string();
-        }
+    #[test]
+    fn test_centered_position_without_monitor() {
+        assert_eq!(
+            centered_position(None, 680.0, 240.0, 2.0),
+            FALLBACK_WINDOW_POSITION
+        );
+    }
 
-        let partial = after_at.to_lowercase();
-        let matches: Vec<&String> = if partial.is_empty() {
-            placeholders.iter().collect()
-        } else {
-            placeholders
-                .iter()
-                .filter(|p| p.to_lowercase().starts_with(&partial))
-                .collect()
-        };
+    #[test]
+    fn test_compute_window_width_short_hint() {
+        assert_eq!(
+            compute_window_width("@clipboard", None),
+            DEFAULT_WINDOW_WIDTH
+        );
+    }
 
-        if let Some(best) = matches.first() {
-            let mut result = text[..at_pos].to_string();
-            result.push_str(&format!("@{} ", best));
-            return result;
-        }
+    #[test]
+    fn test_compute_window_width_long_hint() {
+        let hint = "@clipboard @".repeat(10);
+        let width = compute_window_width(&hint, None);
+        let expected = hint.len() as f32 * HINT_CHAR_WIDTH + HINT_RESERVED_WIDTH;
+        assert!(width > DEFAULT_WINDOW_WIDTH);
+        assert_eq!(width, expected);
     }
 
-    text.to_string()
-}
+    #[test]
+    fn test_compute_window_width_capped() {
+        let hint = "x".repeat(10_000);
+        assert_eq!(compute_window_width(&hint, None), MAX_WINDOW_WIDTH);
+    }
 
-/// Discover and connect to an OpenCode server
-async fn discover_and_connect(
-    cwd: &std::path::Path,
-    port: Option<u16>,
-    debug: bool,
-) -> Result<server::Server> {
-    if debug {
-        eprintln!("Discovering OpenCode server (cwd: {})...", cwd.display());
+    #[test]
+    fn test_compute_window_width_min_override() {
+        assert_eq!(compute_window_width("@clipboard", Some(900.0)), 900.0);
+        assert_eq!(
+            compute_window_width("@clipboard", Some(100.0)),
+            DEFAULT_WINDOW_WIDTH
+        );
     }
 
-    server::discover_server(cwd, port).await
-}
+    #[test]
+    fn test_build_status_text() {
+        assert_eq!(build_status_text(true, Some(4096)), "Connected :4096");
+        assert_eq!(build_status_text(true, None), "Connected");
+        assert_eq!(build_status_text(false, None), "Disconnected");
+        assert_eq!(build_status_text(false, Some(4096)), "Disconnected");
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_build_runtime_prefers_multi_thread() {
+        let (rt, flavor) = build_runtime_with(|| Ok("multi"), || Ok("current")).unwrap();
+        assert_eq!(rt, "multi");
+        assert_eq!(flavor, RuntimeFlavor::MultiThread);
+    }
+
+    #[test]
+    fn test_build_runtime_falls_back_to_current_thread() {
+        let (rt, flavor) = build_runtime_with(
+            || Err(std::io::Error::other("thread limit reached")),
+            || Ok("current"),
+        )
+        .unwrap();
+        assert_eq!(rt, "current");
+        assert_eq!(flavor, RuntimeFlavor::CurrentThread);
+    }
+
+    #[test]
+    fn test_build_runtime_both_fail() {
+        let err = build_runtime_with::<()>(
+            || Err(std::io::Error::other("thread limit reached")),
+            || Err(std::io::Error::other("no reactor")),
+        )
+        .unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("thread limit reached"));
+        assert!(msg.contains("no reactor"));
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_expires() {
+        let slow = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok::<_, anyhow::Error>(42)
+        };
+        let err = with_timeout(slow, Duration::from_millis(20), "Server discovery")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Server discovery timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_completes() {
+        let fast = async { Ok::<_, anyhow::Error>(42) };
+        let value = with_timeout(fast, Duration::from_secs(5), "Server discovery")
+            .await
+            .unwrap();
+        assert_eq!(value, 42);
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_passes_through_errors() {
+        let failing = async { Err::<u16, _>(anyhow!("No OpenCode processes found")) };
+        let err = with_timeout(failing, Duration::from_secs(5), "Server discovery")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "No OpenCode processes found");
+    }
 
     #[test]
     fn test_parse_params() {
@@ -471,125 +2541,799 @@ mod tests {
     }
 
     #[test]
-    fn test_expand_placeholders() {
+    fn test_collect_env_params() {
+        let vars = vec![
+            ("PROMPT_PATH".to_string(), "/src/main.rs".to_string()),
+            ("PROMPT_Lang".to_string(), "rust".to_string()),
+            ("HOME".to_string(), "/home/me".to_string()),
+            ("PROMPT_".to_string(), "ignored".to_string()),
+        ];
+        let params = collect_env_params(vars, "PROMPT_");
+        assert_eq!(params.len(), 2);
+        assert_eq!(params.get("path").unwrap(), "/src/main.rs");
+        assert_eq!(params.get("lang").unwrap(), "rust");
+    }
+
+    #[test]
+    fn test_project_params_merge_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().join("src");
+        std::fs::create_dir(&cwd).unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::write(
+            dir.path().join(project_config::PROJECT_FILE),
+            "[params]\nlang = \"rust\"\nowner = \"project\"\nbranch = \"main\"\nfile = \"x\"\n",
+        )
+        .unwrap();
+        let params_file = dir.path().join("params.json");
+        std::fs::write(&params_file, r#"{"branch": "file", "file": "file"}"#).unwrap();
+        let vars = || vec![("PD_OWNER".to_string(), "env".to_string())];
+
+        let cli = Cli::try_parse_from([
+            "prompt-dialog",
+            "--param-env-prefix",
+            "PD_",
+            "--params-file",
+            params_file.to_str().unwrap(),
+            "--param",
+            "file=cli",
+        ])
+        .unwrap();
+        let params = load_params(&cli, &cwd, vars()).unwrap();
+        assert_eq!(params["lang"], "rust");
+        assert_eq!(params["owner"], "env");
+        assert_eq!(params["branch"], "file");
+        assert_eq!(params["file"], "cli");
+
+        let cli = Cli::try_parse_from(["prompt-dialog", "--no-project-config"]).unwrap();
+        assert!(load_params(&cli, &cwd, vars()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_collect_env_params_empty_prefix() {
+        let vars = vec![("PATH".to_string(), "/usr/bin".to_string())];
+        assert!(collect_env_params(vars, "").is_empty());
+    }
+
+    #[test]
+    fn test_log_gate_quiet_suppresses_output() {
+        let mut out = Vec::new();
+        log_to(&mut out, true, "Failed to record history");
+        assert!(out.is_empty());
+
+        log_to(&mut out, false, "Failed to record history");
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Failed to record history\n"
+        );
+    }
+
+    #[test]
+    fn test_send_report() {
+        let server = server::Server {
+            pid: 4242,
+            host: "127.0.0.1".to_string(),
+            port: 4096,
+            tls: server::TlsOptions::default(),
+            cwd: PathBuf::from("/work/alpha"),
+            socket: None,
+        };
+        let mut out = Vec::new();
+        write_report(
+            &send_report("Fix @path", "Fix src/main.rs", &server),
+            &mut out,
+        )
+        .unwrap();
+        let line = String::from_utf8(out).unwrap();
+        assert!(line.ends_with('\n'));
+        assert_eq!(line.lines().count(), 1);
+
+        let report: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            report,
+            serde_json::json!({
+                "ok": true,
+                "prompt": "Fix @path",
+                "expanded": "Fix src/main.rs",
+                "port": 4096,
+                "pid": 4242,
+                "cwd": "/work/alpha",
+            })
+        );
+
+        // Over a Unix socket there is no port to report
+        let server = server::Server {
+            socket: Some(PathBuf::from("/tmp/opencode.sock")),
+            ..server
+        };
+        assert!(send_report("x", "x", &server)["port"].is_null());
+    }
+
+    #[test]
+    fn test_failure_report() {
+        assert_eq!(
+            failure_report("Send failed: connection refused"),
+            serde_json::json!({ "ok": false, "error": "Send failed: connection refused" })
+        );
+        assert_eq!(dismissed_error(""), "Dismissed without sending");
+        assert_eq!(dismissed_error("Send failed: 500"), "Send failed: 500");
+    }
+
+    #[test]
+    fn test_report_json_only_for_real_sends() {
+        assert!(Cli::try_parse_from(["prompt-dialog", "--report-json", "--dry-run"]).is_err());
+        assert!(Cli::try_parse_from(["prompt-dialog", "--report-json", "--daemon"]).is_err());
+
+        let server = server::Server {
+            pid: 1,
+            host: "127.0.0.1".to_string(),
+            port: 4096,
+            tls: server::TlsOptions::default(),
+            cwd: PathBuf::from("/"),
+            socket: None,
+        };
+        let cli = Cli::try_parse_from(["prompt-dialog"]).unwrap();
+        assert!(AfterSend::from_cli(&cli)
+            .with_report(&cli, &server)
+            .report
+            .is_none());
+        let cli = Cli::try_parse_from(["prompt-dialog", "--report-json"]).unwrap();
+        assert!(AfterSend::from_cli(&cli)
+            .with_report(&cli, &server)
+            .report
+            .is_some());
+    }
+
+    /// Parse `args` and merge `config` under them
+    fn parse_with_config(args: &[&str], config: &config::Config) -> Result<Cli> {
+        let matches = Cli::command().try_get_matches_from(args).unwrap();
+        Cli::from_arg_matches(&matches)
+            .unwrap()
+            .with_config(config, &matches)
+    }
+
+    #[test]
+    fn test_config_defaults_lose_to_flags() {
+        let config = config::Config {
+            host: Some("10.0.0.2".to_string()),
+            port: Some(4096),
+            timeout: Some(30),
+            theme: Some(theme::Theme::Light),
+            prefix: Some('$'),
+            retries: Some(7),
+        };
+
+        // Nothing given: every value comes from the file
+        let cli = parse_with_config(&["prompt-dialog"], &config).unwrap();
+        assert_eq!(cli.host, "10.0.0.2");
+        assert_eq!(cli.port, Some(4096));
+        assert_eq!(cli.timeout, 30);
+        assert_eq!(cli.theme, theme::Theme::Light);
+        assert_eq!(cli.prefix, '$');
+        assert_eq!(cli.retries, 7);
+
+        // Explicit flags win, even when they repeat the built-in default
+        let cli = parse_with_config(
+            &[
+                "prompt-dialog",
+                "--timeout",
+                "5",
+                "--theme",
+                "dark",
+                "--port",
+                "8080",
+            ],
+            &config,
+        )
+        .unwrap();
+        assert_eq!(cli.timeout, 5);
+        assert_eq!(cli.theme, theme::Theme::Dark);
+        assert_eq!(cli.port, Some(8080));
+        assert_eq!(cli.retries, 7);
+
+        // A socket leaves no room for the file's port
+        let cli =
+            parse_with_config(&["prompt-dialog", "--socket", "/tmp/oc.sock"], &config).unwrap();
+        assert_eq!(cli.port, None);
+
+        // An empty file changes nothing
+        let cli = parse_with_config(&["prompt-dialog"], &config::Config::default()).unwrap();
+        assert_eq!(cli.host, "localhost");
+        assert_eq!(cli.timeout, 5);
+
+        let bad = config::Config {
+            prefix: Some(' '),
+            ..Default::default()
+        };
+        assert!(parse_with_config(&["prompt-dialog"], &bad).is_err());
+    }
+
+    #[test]
+    fn test_quiet_overrides_debug() {
+        let cli = Cli::try_parse_from(["prompt-dialog", "--debug", "--quiet"])
+            .unwrap()
+            .silenced();
+        assert!(cli.quiet);
+        assert!(!cli.debug);
+        assert!(AfterSend::from_cli(&cli).quiet);
+
+        let cli = Cli::try_parse_from(["prompt-dialog", "--debug"])
+            .unwrap()
+            .silenced();
+        assert!(cli.debug);
+    }
+
+    #[test]
+    fn test_truncate_requires_max_length() {
+        assert!(Cli::try_parse_from(["prompt-dialog", "--truncate"]).is_err());
+        let cli =
+            Cli::try_parse_from(["prompt-dialog", "--max-length", "10", "--truncate"]).unwrap();
+        assert_eq!(cli.max_length, Some(10));
+        assert!(cli.truncate);
+    }
+
+    #[test]
+    fn test_ignore_submit_while_sending_or_closing() {
+        assert!(!ignore_submit(false, false));
+        assert!(ignore_submit(true, false));
+        assert!(ignore_submit(false, true));
+        assert!(ignore_submit(true, true));
+    }
+
+    #[test]
+    fn test_compute_window_height() {
+        let width = DEFAULT_WINDOW_WIDTH;
+        assert_eq!(compute_window_height("", width), DEFAULT_WINDOW_HEIGHT);
+        let lines = |n: usize| vec!["x"; n].join("\n");
+        assert_eq!(
+            compute_window_height(&lines(AUTOSIZE_BASE_LINES), width),
+            DEFAULT_WINDOW_HEIGHT
+        );
+        assert_eq!(
+            compute_window_height(&lines(AUTOSIZE_BASE_LINES + 3), width),
+            DEFAULT_WINDOW_HEIGHT + 3.0 * INPUT_LINE_HEIGHT
+        );
+        // A 40-line paste is capped
+        assert_eq!(compute_window_height(&lines(40), width), MAX_WINDOW_HEIGHT);
+
+        // A long line counts as the lines it wraps into
+        let columns = ((width - INPUT_RESERVED_WIDTH) / INPUT_CHAR_WIDTH) as usize;
+        let wrapped = "y".repeat(columns * (AUTOSIZE_BASE_LINES + 1));
+        assert_eq!(
+            compute_window_height(&wrapped, width),
+            DEFAULT_WINDOW_HEIGHT + INPUT_LINE_HEIGHT
+        );
+    }
+
+    #[test]
+    fn test_log_level() {
+        use log::LevelFilter;
+        let level = |args: &[&str]| {
+            let argv = std::iter::once("prompt-dialog").chain(args.iter().copied());
+            Cli::try_parse_from(argv).unwrap().silenced().log_level()
+        };
+        assert_eq!(level(&[]), LevelFilter::Warn);
+        assert_eq!(level(&["-v"]), LevelFilter::Info);
+        assert_eq!(level(&["-vv"]), LevelFilter::Debug);
+        assert_eq!(level(&["--debug"]), LevelFilter::Debug);
+        assert_eq!(level(&["--debug", "-v"]), LevelFilter::Debug);
+        assert_eq!(level(&["-vvv"]), LevelFilter::Trace);
+        assert_eq!(level(&["-vvv", "--quiet"]), LevelFilter::Off);
+    }
+
+    #[test]
+    fn test_merge_params_cli_wins() {
+        let env = collect_env_params(
+            vec![
+                ("PROMPT_PATH".to_string(), "from-env".to_string()),
+                ("PROMPT_LANG".to_string(), "rust".to_string()),
+            ],
+            "PROMPT_",
+        );
+        let cli = parse_params(&["path=from-cli".to_string()]);
+        let params = merge_params(env, cli);
+        assert_eq!(params.get("path").unwrap(), "from-cli");
+        assert_eq!(params.get("lang").unwrap(), "rust");
+    }
+
+    #[test]
+    fn test_preview_expansion() {
+        let mut params = HashMap::new();
+        params.insert("path".to_string(), "src/main.rs".to_string());
+        let clipboard = FakeClipboard(Some("copied".to_string()));
+        let ctx = ctx_with_clipboard(&params, &clipboard);
+
+        assert_eq!(
+            preview_expansion("Fix @path: @clipboard@cursor", &ctx).as_deref(),
+            Some("Fix src/main.rs: copied@cursor")
+        );
+        // Nothing to expand, nothing to replace
+        assert_eq!(preview_expansion("Fix it @cursor", &ctx), None);
+    }
+
+    #[test]
+    fn test_expand_title_with_cwd() {
         let mut params = HashMap::new();
-        params.insert("path".to_string(), "/src/main.rs".to_string());
-        params.insert("selection".to_string(), "fn main()".to_string());
+        params.insert("lang".to_string(), "rust".to_string());
 
-        let text = "Fix the bug in @path near @selection";
-        let result = expand_placeholders(text, &params);
-        assert_eq!(result, "Fix the bug in /src/main.rs near fn main()");
+        let title = expand_title(
+            "Prompt: @cwd (@lang)",
+            &params,
+            Some(Path::new("/work/app")),
+            '@',
+        );
+        assert_eq!(title, "Prompt: /work/app (rust)");
     }
 
     #[test]
-    fn test_expand_placeholders_no_match() {
-        let params = HashMap::new();
-        let text = "No placeholders here";
-        let result = expand_placeholders(text, &params);
-        assert_eq!(result, "No placeholders here");
+    fn test_expand_title_without_cwd() {
+        let title = expand_title("Prompt: @cwd", &HashMap::new(), None, '@');
+        assert_eq!(title, "Prompt: @cwd");
     }
 
     #[test]
-    fn test_expand_placeholders_multiple_occurrences() {
+    fn test_expand_title_param_overrides_cwd() {
         let mut params = HashMap::new();
-        params.insert("file".to_string(), "test.rs".to_string());
+        params.insert("cwd".to_string(), "custom".to_string());
+
+        let title = expand_title("@cwd", &params, Some(Path::new("/work/app")), '@');
+        assert_eq!(title, "custom");
+    }
+
+    #[test]
+    fn test_print_expanded() {
+        let cli = Cli::try_parse_from([
+            "prompt-dialog",
+            "--print-expanded",
+            "Fix @path",
+            "--param",
+            "path=/src/main.rs",
+        ])
+        .unwrap();
+        let params = parse_params(&cli.params);
+        let text = cli.print_expanded.as_deref().unwrap();
+
+        let mut out = Vec::new();
+        expand_command(&cli, &params, Path::new("."), text, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Fix /src/main.rs\n");
+    }
 
-        let text = "Compare @file with @file";
-        let result = expand_placeholders(text, &params);
-        assert_eq!(result, "Compare test.rs with test.rs");
+    #[test]
+    fn test_parse_envelope_tag() {
+        assert_eq!(parse_envelope_tag("task"), Ok("task".to_string()));
+        assert!(parse_envelope_tag("user-request_2").is_ok());
+        assert!(parse_envelope_tag("").is_err());
+        assert!(parse_envelope_tag("2task").is_err());
+        assert!(parse_envelope_tag("a b").is_err());
+        assert!(parse_envelope_tag("a>b").is_err());
     }
 
     #[test]
-    fn test_expand_longest_key_first() {
+    fn test_dry_run_prints_expanded_prompt() {
         let mut params = HashMap::new();
-        params.insert("path".to_string(), "short".to_string());
-        params.insert("pathname".to_string(), "long".to_string());
+        params.insert("path".to_string(), "src/lib.rs".to_string());
+        let post = PostProcess {
+            envelope: Some("task".to_string()),
+            ..Default::default()
+        };
+
+        let expanded = prepare_prompt("Fix @path", &ExpandContext::new(&params), &post).text;
+        let mut out = Vec::new();
+        write_dry_run(&expanded, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "<task>\nFix src/lib.rs\n</task>\n"
+        );
+    }
+
+    #[test]
+    fn test_print_expanded_resolves_builtins() {
+        let cli = Cli::try_parse_from(["prompt-dialog"]).unwrap();
+        let mut out = Vec::new();
+        expand_command(
+            &cli,
+            &HashMap::new(),
+            Path::new("."),
+            "Paste: @clipboard",
+            &mut out,
+        )
+        .unwrap();
+        let printed = String::from_utf8(out).unwrap();
+        assert!(printed.starts_with("Paste: "));
+        assert!(!printed.contains("@clipboard"));
+    }
+
+    #[test]
+    fn test_validate_template_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let clean = dir.path().join("clean.md");
+        std::fs::write(
+            &clean,
+            "---\nparams: path\n---\nReview @path with @git-root",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        validate_template(&clean, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with(": ok\n"));
+
+        let broken = dir.path().join("broken.md");
+        std::fs::write(&broken, "---\nparams: path\n---\nReview @path\n@selection").unwrap();
+        let mut out = Vec::new();
+        assert!(validate_template(&broken, &mut out).is_err());
+        let report = String::from_utf8(out).unwrap();
+        assert!(report.ends_with("broken.md:5: undeclared placeholder @selection\n"));
+    }
+
+    #[test]
+    fn test_text_counts() {
+        assert_eq!(text_counts(""), (0, 0));
+        assert_eq!(text_counts("fix  the\nbug "), (13, 3));
+        // Characters, not bytes
+        assert_eq!(text_counts("héllo wörld"), (11, 2));
+    }
+
+    #[test]
+    fn test_parse_attachment_validates_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.rs");
+        std::fs::write(&file, "fn a() {}").unwrap();
+
+        let cli = Cli::try_parse_from([
+            "prompt-dialog",
+            "--attach",
+            file.to_str().unwrap(),
+            "--attach",
+            file.to_str().unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(cli.attach, vec![file.canonicalize().unwrap(); 2]);
+
+        let missing = dir.path().join("missing.rs");
+        assert!(
+            Cli::try_parse_from(["prompt-dialog", "--attach", missing.to_str().unwrap()]).is_err()
+        );
+    }
 
-        let text = "Use @pathname and @path";
-        let result = expand_placeholders(text, &params);
-        assert_eq!(result, "Use long and short");
+    #[test]
+    fn test_reset_selection() {
+        use reset::State;
+
+        assert_eq!(
+            reset_selection(true, false, false, false, false),
+            State::ALL
+        );
+        assert_eq!(
+            reset_selection(false, true, false, true, false),
+            vec![State::History, State::Cache]
+        );
+        assert_eq!(
+            reset_selection(false, false, false, false, true),
+            vec![State::Window]
+        );
+
+        let cli = Cli::try_parse_from(["prompt-dialog", "reset", "--stats"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Reset { stats: true, .. })
+        ));
+        // Resetting nothing is a usage error
+        assert!(Cli::try_parse_from(["prompt-dialog", "reset"]).is_err());
     }
 
     #[test]
-    fn test_build_highlight_text() {
-        let placeholders = vec!["path".to_string(), "clipboard".to_string()];
-        let text = "Fix @path and @clipboard now";
-        let result = build_highlight_text(text, &placeholders);
-        // @path and @clipboard should be visible, rest spaces
-        assert_eq!(result, "    @path     @clipboard    ");
+    fn test_send_mode() {
+        let mode = |args: &[&str]| {
+            let cli = Cli::try_parse_from([&["prompt-dialog"], args].concat()).unwrap();
+            send_mode(&cli)
+        };
+        assert_eq!(mode(&[]), server::SendMode::AppendAndSubmit);
+        assert_eq!(mode(&["--submit-only"]), server::SendMode::SubmitOnly);
+        assert_eq!(mode(&["--mode", "session"]), server::SendMode::NewSession);
+        assert_eq!(mode(&["--append-only"]), server::SendMode::AppendOnly);
+        assert!(Cli::try_parse_from(["prompt-dialog", "--append-only", "--submit-only"]).is_err());
+        assert!(
+            Cli::try_parse_from(["prompt-dialog", "--mode", "session", "--submit-only"]).is_err()
+        );
     }
 
     #[test]
-    fn test_build_highlight_preserves_newlines() {
-        let placeholders = vec!["file".to_string()];
-        let text = "hello\n@file";
-        let result = build_highlight_text(text, &placeholders);
-        assert_eq!(result, "     \n@file");
+    fn test_parse_command_name() {
+        assert_eq!(
+            parse_command_name("prompt.submit").unwrap(),
+            "prompt.submit"
+        );
+        assert_eq!(parse_command_name("my_macro-2").unwrap(), "my_macro-2");
+        assert!(parse_command_name("").is_err());
+        assert_eq!(
+            parse_command_name("rm -rf").unwrap_err(),
+            "' ' is not allowed in a command name"
+        );
+
+        let cli = Cli::try_parse_from(["prompt-dialog"]).unwrap();
+        assert_eq!(cli.submit_command, "prompt.submit");
+        assert!(Cli::try_parse_from(["prompt-dialog", "--command", "a/b"]).is_err());
     }
 
     #[test]
-    fn test_find_autocomplete_partial() {
-        let placeholders = vec!["clipboard".to_string(), "path".to_string()];
-        let (suggestion, visible) = find_autocomplete("hello @cl", &placeholders);
-        assert!(visible);
-        assert_eq!(suggestion, "@clipboard");
+    fn test_daemon_flags() {
+        let cli = Cli::try_parse_from(["prompt-dialog", "--daemon"]).unwrap();
+        assert!(cli.daemon);
+        assert_eq!(cli.hotkey, hotkey::DEFAULT_HOTKEY);
+        let cli =
+            Cli::try_parse_from(["prompt-dialog", "--daemon", "--hotkey", "super+KeyP"]).unwrap();
+        assert_eq!(cli.hotkey, "super+KeyP");
+        assert!(Cli::try_parse_from(["prompt-dialog", "--daemon", "--builder"]).is_err());
     }
 
     #[test]
-    fn test_find_autocomplete_at_only() {
-        let placeholders = vec!["clipboard".to_string(), "path".to_string()];
-        let (suggestion, visible) = find_autocomplete("hello @", &placeholders);
-        assert!(visible);
-        assert_eq!(suggestion, "@clipboard");
+    fn test_connect_timeout() {
+        let cli = Cli::try_parse_from(["prompt-dialog", "--timeout", "60"]).unwrap();
+        assert_eq!(request_timeout(&cli), Some(Duration::from_secs(60)));
+        assert_eq!(connect_timeout(&cli), Some(Duration::from_secs(5)));
+        let cli = Cli::try_parse_from(["prompt-dialog", "--connect-timeout", "0"]).unwrap();
+        assert_eq!(connect_timeout(&cli), None);
+        assert_eq!(request_timeout(&cli), Some(Duration::from_secs(5)));
     }
 
     #[test]
-    fn test_find_autocomplete_exact_match_no_suggest() {
-        let placeholders = vec!["clipboard".to_string()];
-        let (_suggestion, visible) = find_autocomplete("hello @clipboard", &placeholders);
-        assert!(!visible);
+    fn test_send_error_text() {
+        let err = anyhow::Error::new(std::io::Error::other("refused"))
+            .context(server::RequestError::Connect("localhost:4096".to_string()))
+            .context("Failed to append prompt")
+            .context("Failed to append prompt text");
+        assert_eq!(
+            send_error_text(&err),
+            "Send failed: could not connect to OpenCode server at localhost:4096"
+        );
+        let err = anyhow::Error::new(std::io::Error::other("slow"))
+            .context(server::RequestError::Timeout(Duration::from_secs(5)))
+            .context("Failed to submit prompt");
+        assert_eq!(
+            send_error_text(&err),
+            "Send failed: request timed out after 5s"
+        );
+        let err = anyhow::anyhow!("Server returned 500").context("Failed to submit prompt");
+        assert_eq!(
+            send_error_text(&err),
+            "Send failed: Failed to submit prompt"
+        );
     }
 
     #[test]
-    fn test_find_autocomplete_no_at() {
-        let placeholders = vec!["clipboard".to_string()];
-        let (_suggestion, visible) = find_autocomplete("hello world", &placeholders);
-        assert!(!visible);
+    fn test_undo_depth() {
+        let cli = Cli::try_parse_from(["prompt-dialog"]).unwrap();
+        assert_eq!(cli.undo_depth, undo::DEFAULT_DEPTH);
+        let cli = Cli::try_parse_from(["prompt-dialog", "--undo-depth", "200"]).unwrap();
+        assert_eq!(cli.undo_depth, 200);
+        assert!(Cli::try_parse_from(["prompt-dialog", "--undo-depth", "0"]).is_err());
     }
 
     #[test]
-    fn test_apply_autocomplete() {
-        let placeholders = vec!["clipboard".to_string(), "path".to_string()];
-        let result = apply_autocomplete("Fix @cl", &placeholders);
-        assert_eq!(result, "Fix @clipboard ");
+    fn test_request_timeout() {
+        let cli = Cli::try_parse_from(["prompt-dialog"]).unwrap();
+        assert_eq!(request_timeout(&cli), Some(Duration::from_secs(5)));
+        let cli = Cli::try_parse_from(["prompt-dialog", "--timeout", "60"]).unwrap();
+        assert_eq!(request_timeout(&cli), Some(Duration::from_secs(60)));
+        let cli = Cli::try_parse_from(["prompt-dialog", "--timeout", "0"]).unwrap();
+        assert_eq!(request_timeout(&cli), None);
     }
 
     #[test]
-    fn test_apply_autocomplete_at_only() {
-        let placeholders = vec!["clipboard".to_string()];
-        let result = apply_autocomplete("Fix @", &placeholders);
-        assert_eq!(result, "Fix @clipboard ");
+    fn test_ping() {
+        let server = server::mock::MockServer::with_body(r#"{"directory":"/work/alpha"}"#);
+        let port = server.port();
+        let cli = |args: &[&str]| {
+            let argv = ["prompt-dialog", "--host", "127.0.0.1"]
+                .into_iter()
+                .chain(args.iter().copied());
+            Cli::try_parse_from(argv).unwrap()
+        };
+
+        let mut out = Vec::new();
+        ping(&cli(&[]), port, 2, &mut out).unwrap();
+        assert!(out.is_empty());
+
+        ping(&cli(&["--debug"]), port, 2, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "/work/alpha\n");
+
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let err = ping(&cli(&[]), closed, 2, &mut Vec::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains(&format!("127.0.0.1:{}", closed)));
     }
 
     #[test]
-    fn test_expand_clipboard_token() {
-        // @clipboard expands to whatever is on the system clipboard.
-        // We can't control clipboard in CI, so just verify it doesn't panic
-        // and the token is consumed (replaced with something).
-        let params = HashMap::new();
-        let text = "Paste: @clipboard";
-        let result = expand_placeholders(text, &params);
-        assert!(!result.contains("@clipboard"));
+    fn test_ping_parses_port_and_timeout() {
+        let cli = Cli::try_parse_from(["prompt-dialog", "ping", "--port", "8080"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Ping {
+                port: 8080,
+                timeout: 2
+            })
+        ));
+        assert!(Cli::try_parse_from(["prompt-dialog", "ping"]).is_err());
     }
 
     #[test]
-    fn test_expand_clipboard_with_params() {
-        let mut params = HashMap::new();
-        params.insert("file".to_string(), "main.rs".to_string());
+    fn test_expand_command() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.md"), "remember").unwrap();
+        let run = |args: &[&str]| {
+            let cli = Cli::try_parse_from(args).unwrap();
+            let params = parse_params(&cli.params);
+            let Some(Command::Expand { text }) = &cli.command else {
+                panic!("not an expand command");
+            };
+            let mut out = Vec::new();
+            expand_command(&cli, &params, dir.path(), text, &mut out)
+                .map(|()| String::from_utf8(out).unwrap())
+        };
+
+        assert_eq!(
+            run(&["prompt-dialog", "expand", "Fix @path", "--param", "path=x"]).unwrap(),
+            "Fix x\n"
+        );
+        assert_eq!(
+            run(&["prompt-dialog", "expand", "@file:notes.md@cursor @nope"]).unwrap(),
+            "remember @nope\n"
+        );
+        let err = run(&[
+            "prompt-dialog",
+            "expand",
+            "@file:notes.md @nope",
+            "--strict",
+        ])
+        .unwrap_err();
+        assert!(format!("{}", err).contains("@nope"));
+    }
 
-        let text = "Fix @file using @clipboard";
-        let result = expand_placeholders(text, &params);
-        assert!(!result.contains("@clipboard"));
-        assert!(result.contains("main.rs"));
+    #[test]
+    fn test_write_servers() {
+        let servers = vec![
+            server::Server {
+                pid: 4242,
+                host: "localhost".to_string(),
+                port: 4096,
+                tls: server::TlsOptions::default(),
+                cwd: PathBuf::from("/work/alpha"),
+                socket: None,
+            },
+            server::Server {
+                pid: 7,
+                host: "localhost".to_string(),
+                port: 8080,
+                tls: server::TlsOptions::default(),
+                cwd: PathBuf::from("/work/beta"),
+                socket: None,
+            },
+        ];
+
+        let mut out = Vec::new();
+        write_servers(&servers, false, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "PID      PORT   CWD\n\
+             4242     4096   /work/alpha\n\
+             7        8080   /work/beta\n"
+        );
+
+        let mut out = Vec::new();
+        write_servers(&servers, true, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json[0]["pid"], 4242);
+        assert_eq!(json[1]["port"], 8080);
+        assert_eq!(json[1]["cwd"], "/work/beta");
+    }
+
+    #[test]
+    fn test_base_path_requires_port() {
+        assert!(Cli::try_parse_from(["prompt-dialog", "--base-path", "/opencode/"]).is_err());
+        let cli = Cli::try_parse_from([
+            "prompt-dialog",
+            "--port",
+            "443",
+            "--base-path",
+            "/opencode/",
+        ])
+        .unwrap();
+        assert_eq!(cli.base_path, "/opencode/");
+    }
+
+    #[test]
+    fn test_socket_flag() {
+        let cli = Cli::try_parse_from([
+            "prompt-dialog",
+            "--socket",
+            "/run/opencode.sock",
+            "--base-path",
+            "/opencode/",
+        ])
+        .unwrap();
+        assert_eq!(cli.socket, Some(PathBuf::from("/run/opencode.sock")));
+
+        for other in [["--port", "4096"], ["--mdns", "--debug"]] {
+            let argv = ["prompt-dialog", "--socket", "/run/opencode.sock"]
+                .into_iter()
+                .chain(other);
+            assert!(Cli::try_parse_from(argv).is_err());
+        }
+    }
+
+    #[test]
+    fn test_socket_server_has_no_tcp_port() {
+        let mut server = server::Server {
+            pid: 0,
+            host: "localhost".to_string(),
+            port: 0,
+            tls: server::TlsOptions::default(),
+            cwd: PathBuf::from("/work/alpha"),
+            socket: Some(PathBuf::from("/run/opencode.sock")),
+        };
+        assert_eq!(build_status_text(true, server.tcp_port()), "Connected");
+        server.socket = None;
+        server.port = 4096;
+        assert_eq!(
+            build_status_text(true, server.tcp_port()),
+            "Connected :4096"
+        );
+    }
+
+    #[test]
+    fn test_read_prefill() {
+        let diff = "diff --git a/x b/x\n+fix @path\n\n";
+        assert_eq!(
+            read_prefill(diff.as_bytes()).unwrap().as_deref(),
+            Some("diff --git a/x b/x\n+fix @path")
+        );
+        assert_eq!(
+            read_prefill("  indented\r\n".as_bytes())
+                .unwrap()
+                .as_deref(),
+            Some("  indented")
+        );
+        assert_eq!(read_prefill("".as_bytes()).unwrap(), None);
+        assert_eq!(read_prefill(" \n\n".as_bytes()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_prompt_argument() {
+        let cli = Cli::try_parse_from(["prompt-dialog", "--submit-on-start", "Fix @path"]).unwrap();
+        assert!(cli.submit_on_start);
+        assert_eq!(cli.prompt.as_deref(), Some("Fix @path"));
+        assert!(cli.command.is_none());
+
+        // Subcommand names still select the subcommand
+        let cli = Cli::try_parse_from(["prompt-dialog", "history"]).unwrap();
+        assert!(cli.prompt.is_none());
+        assert!(matches!(cli.command, Some(Command::History { .. })));
+
+        assert!(Cli::try_parse_from(["prompt-dialog", "--submit-on-start"]).is_err());
+    }
+
+    #[test]
+    fn test_server_choice_label() {
+        let server = server::Server {
+            pid: 7,
+            host: "localhost".to_string(),
+            port: 4096,
+            tls: server::TlsOptions::default(),
+            cwd: PathBuf::from("/work/alpha"),
+            socket: None,
+        };
+        assert_eq!(server_choice_label(&server), ":4096  /work/alpha");
+    }
+
+    #[test]
+    fn test_parse_prefix() {
+        assert_eq!(parse_prefix("$"), Ok('$'));
+        assert!(parse_prefix("§").unwrap_err().contains("multi-byte"));
+        assert!(parse_prefix("").is_err());
+        assert!(parse_prefix("$$").is_err());
+        assert!(parse_prefix("a").is_err());
+        assert!(Cli::try_parse_from(["prompt-dialog", "--prefix", "€"]).is_err());
     }
 }
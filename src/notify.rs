@@ -0,0 +1,144 @@
+//! `--notify`: desktop notifications for send outcomes
+//!
+//! A failed send is easy to miss once the window is gone (daemon mode), so by
+//! default failures raise an OS notification with the error. `always` also
+//! confirms successful sends; `never` keeps quiet. Notifications are shown
+//! off the UI thread and a failure to show one is only logged.
+
+use anyhow::{Context, Result};
+
+/// `--notify`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum NotifyMode {
+    /// Only when a send fails
+    #[default]
+    OnError,
+    /// After every send, failed or not
+    Always,
+    /// Never notify
+    Never,
+}
+
+/// How long a success confirmation stays up
+const SUCCESS_TIMEOUT_MS: u32 = 3000;
+
+/// One notification to show
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub summary: &'static str,
+    pub body: String,
+    /// A brief confirmation rather than an error
+    pub success: bool,
+}
+
+impl NotifyMode {
+    /// The notification for a send `result` (the error text on failure), if
+    /// this mode shows one
+    pub fn notification(self, result: &Result<(), String>) -> Option<Notification> {
+        match (self, result) {
+            (NotifyMode::Never, _) | (NotifyMode::OnError, Ok(())) => None,
+            (_, Ok(())) => Some(Notification {
+                summary: "Prompt sent",
+                body: "Sent to OpenCode".to_string(),
+                success: true,
+            }),
+            (_, Err(error)) => Some(Notification {
+                summary: "Prompt not sent",
+                body: error.clone(),
+                success: false,
+            }),
+        }
+    }
+}
+
+/// Shows notifications; injectable so the wiring can be tested
+pub trait Notifier {
+    fn show(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Native notifications through notify-rust
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn show(&self, notification: &Notification) -> Result<()> {
+        let mut native = notify_rust::Notification::new();
+        native
+            .appname("prompt-dialog")
+            .summary(notification.summary)
+            .body(&notification.body);
+        if notification.success {
+            native.timeout(notify_rust::Timeout::Milliseconds(SUCCESS_TIMEOUT_MS));
+        }
+        native
+            .show()
+            .map(drop)
+            .context("Failed to show notification")
+    }
+}
+
+/// Show the notification `mode` calls for after a send, logging (not
+/// returning) a failure to show it
+pub fn notify_outcome(notifier: &dyn Notifier, mode: NotifyMode, result: &Result<(), String>) {
+    if let Some(notification) = mode.notification(result) {
+        if let Err(e) = notifier.show(&notification) {
+            log::warn!("{:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::cell::RefCell;
+
+    /// Records notifications instead of showing them
+    #[derive(Default)]
+    struct RecordingNotifier {
+        shown: RefCell<Vec<Notification>>,
+        fail: bool,
+    }
+
+    impl Notifier for RecordingNotifier {
+        fn show(&self, notification: &Notification) -> Result<()> {
+            self.shown.borrow_mut().push(notification.clone());
+            if self.fail {
+                Err(anyhow!("no notification daemon"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_modes() {
+        let failed = Err("Send failed: request timed out after 5s".to_string());
+        let cases = [
+            (NotifyMode::OnError, Ok(()), None),
+            (NotifyMode::OnError, failed.clone(), Some(false)),
+            (NotifyMode::Always, Ok(()), Some(true)),
+            (NotifyMode::Always, failed.clone(), Some(false)),
+            (NotifyMode::Never, Ok(()), None),
+            (NotifyMode::Never, failed.clone(), None),
+        ];
+        for (mode, result, success) in cases {
+            let notification = mode.notification(&result);
+            assert_eq!(notification.as_ref().map(|n| n.success), success);
+        }
+        let notification = NotifyMode::OnError.notification(&failed).unwrap();
+        assert_eq!(notification.body, "Send failed: request timed out after 5s");
+    }
+
+    #[test]
+    fn test_notify_outcome_ignores_show_failures() {
+        let notifier = RecordingNotifier {
+            fail: true,
+            ..Default::default()
+        };
+        notify_outcome(&notifier, NotifyMode::OnError, &Err("boom".to_string()));
+        notify_outcome(&notifier, NotifyMode::OnError, &Ok(()));
+        let shown = notifier.shown.borrow();
+        assert_eq!(shown.len(), 1);
+        assert_eq!(shown[0].summary, "Prompt not sent");
+    }
+}
@@ -0,0 +1,162 @@
+//! `--params-file`: params from a JSON or TOML file
+//!
+//! The file holds a flat table of names to values. Strings are used as-is,
+//! numbers and booleans in their textual form, and arrays of those are joined
+//! with commas (so `@files[2]` indexing works on them).
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Load params from `path`, picking the format by extension (`.json` / `.toml`)
+pub fn load(path: &Path) -> Result<HashMap<String, String>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read params file {}", path.display()))?;
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    let params = match ext.as_deref() {
+        Some("json") => parse_json(&data),
+        Some("toml") => parse_toml(&data),
+        _ => Err(anyhow!("expected a .json or .toml extension")),
+    };
+    params.with_context(|| format!("Invalid params file {}", path.display()))
+}
+
+/// Parse a JSON object of params
+pub fn parse_json(data: &str) -> Result<HashMap<String, String>> {
+    let value: serde_json::Value = serde_json::from_str(data).context("Failed to parse JSON")?;
+    let serde_json::Value::Object(map) = value else {
+        bail!("top level must be an object");
+    };
+    map.into_iter()
+        .map(|(key, value)| {
+            let text = json_text(&value).ok_or_else(|| unsupported(&key))?;
+            Ok((key, text))
+        })
+        .collect()
+}
+
+/// Parse a TOML table of params
+pub fn parse_toml(data: &str) -> Result<HashMap<String, String>> {
+    let table: toml::Table = toml::from_str(data).context("Failed to parse TOML")?;
+    from_toml_table(table)
+}
+
+/// Params from an already parsed TOML table, with the same coercions
+pub fn from_toml_table(table: toml::Table) -> Result<HashMap<String, String>> {
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let text = toml_text(&value).ok_or_else(|| unsupported(&key))?;
+            Ok((key, text))
+        })
+        .collect()
+}
+
+fn unsupported(key: &str) -> anyhow::Error {
+    anyhow!(
+        "param {:?} must be a string, number, boolean or array of those",
+        key
+    )
+}
+
+/// Textual form of a JSON param value; `None` for nested objects
+fn json_text(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Null => Some(String::new()),
+        serde_json::Value::Bool(_) | serde_json::Value::Number(_) => Some(value.to_string()),
+        serde_json::Value::Array(items) => join(items.iter().map(|item| match item {
+            serde_json::Value::Array(_) => None,
+            _ => json_text(item),
+        })),
+        serde_json::Value::Object(_) => None,
+    }
+}
+
+/// Textual form of a TOML param value; `None` for nested tables
+fn toml_text(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Datetime(dt) => Some(dt.to_string()),
+        toml::Value::Array(items) => join(items.iter().map(|item| match item {
+            toml::Value::Array(_) => None,
+            _ => toml_text(item),
+        })),
+        toml::Value::Table(_) => None,
+    }
+}
+
+/// Comma-join array items, failing if any can't be stringified
+fn join(items: impl Iterator<Item = Option<String>>) -> Option<String> {
+    items
+        .collect::<Option<Vec<_>>>()
+        .map(|items| items.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json_coerces_values() {
+        let params = parse_json(
+            r#"{"path": "src/main.rs", "count": 3, "ratio": 0.5, "strict": true,
+                "files": ["a.rs", "b.rs"], "empty": null}"#,
+        )
+        .unwrap();
+        assert_eq!(params["path"], "src/main.rs");
+        assert_eq!(params["count"], "3");
+        assert_eq!(params["ratio"], "0.5");
+        assert_eq!(params["strict"], "true");
+        assert_eq!(params["files"], "a.rs,b.rs");
+        assert_eq!(params["empty"], "");
+    }
+
+    #[test]
+    fn test_parse_toml_coerces_values() {
+        let params = parse_toml(
+            "path = \"src/main.rs\"\ncount = 3\nstrict = false\nfiles = [\"a.rs\", 2]\n",
+        )
+        .unwrap();
+        assert_eq!(params["path"], "src/main.rs");
+        assert_eq!(params["count"], "3");
+        assert_eq!(params["strict"], "false");
+        assert_eq!(params["files"], "a.rs,2");
+    }
+
+    #[test]
+    fn test_nested_values_are_rejected() {
+        let err = parse_json(r#"{"meta": {"a": 1}}"#).unwrap_err();
+        assert!(err.to_string().contains("\"meta\""), "{}", err);
+        assert!(parse_json(r#"["path"]"#).is_err());
+        assert!(parse_toml("[section]\nkey = 1\n").is_err());
+    }
+
+    #[test]
+    fn test_load_by_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = dir.path().join("params.json");
+        std::fs::write(&json, r#"{"lang": "rust"}"#).unwrap();
+        assert_eq!(load(&json).unwrap()["lang"], "rust");
+
+        let toml = dir.path().join("params.TOML");
+        std::fs::write(&toml, "lang = \"rust\"\n").unwrap();
+        assert_eq!(load(&toml).unwrap()["lang"], "rust");
+
+        let broken = dir.path().join("broken.json");
+        std::fs::write(&broken, "{\"lang\": ").unwrap();
+        let err = format!("{:#}", load(&broken).unwrap_err());
+        assert!(err.contains("Invalid params file"), "{}", err);
+
+        let other = dir.path().join("params.yaml");
+        std::fs::write(&other, "lang: rust\n").unwrap();
+        assert!(load(&other).is_err());
+    }
+}
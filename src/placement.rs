@@ -0,0 +1,83 @@
+//! Window placement relative to the focused application window
+//!
+//! Queries the platform for the currently focused window's frame so the dialog
+//! can open on top of whatever the user is working in.
+
+/// Where the dialog opens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Position {
+    /// Centered (slightly above the middle) on the current monitor
+    #[default]
+    Center,
+    /// Centered over the focused application window, falling back to `center`
+    ActiveWindow,
+}
+
+/// A screen rectangle in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Top-left corner for a `width`×`height` window centered over `frame`.
+///
+/// Like screen centering, the window sits slightly above the middle (a third
+/// of the free vertical space goes above it).
+pub fn center_over(frame: Rect, width: f64, height: f64) -> (i32, i32) {
+    let x = frame.x + (frame.width - width) / 2.0;
+    let y = frame.y + (frame.height - height) / 3.0;
+    (x.round() as i32, y.round() as i32)
+}
+
+/// Frame of the currently focused application window, if the platform reports one.
+///
+/// `scale` converts the logical points macOS reports into physical pixels.
+pub fn active_window_frame(scale: f64) -> Option<Rect> {
+    let window = active_win_pos_rs::get_active_window().ok()?;
+    let pos = window.position;
+    if pos.width <= 0.0 || pos.height <= 0.0 {
+        return None;
+    }
+
+    let factor = if cfg!(target_os = "macos") {
+        scale
+    } else {
+        1.0
+    };
+    Some(Rect {
+        x: pos.x * factor,
+        y: pos.y * factor,
+        width: pos.width * factor,
+        height: pos.height * factor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_center_over_focused_window() {
+        let frame = Rect {
+            x: 100.0,
+            y: 50.0,
+            width: 1200.0,
+            height: 900.0,
+        };
+        assert_eq!(center_over(frame, 680.0, 240.0), (360, 270));
+    }
+
+    #[test]
+    fn test_center_over_smaller_window_overhangs() {
+        let frame = Rect {
+            x: 500.0,
+            y: 400.0,
+            width: 400.0,
+            height: 120.0,
+        };
+        assert_eq!(center_over(frame, 680.0, 240.0), (360, 360));
+    }
+}
@@ -0,0 +1,104 @@
+//! Last-used server port per working directory
+//!
+//! Remembers which port each project's cwd last connected to, so discovery can
+//! try it before scanning processes. Stored as JSON under the OS config dir.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// File name of the port cache inside the app config dir
+pub const PORT_CACHE_FILE: &str = "ports.json";
+
+/// Default cache location: `<config dir>/prompt-dialog/ports.json`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("prompt-dialog").join(PORT_CACHE_FILE))
+}
+
+/// Mapping from working directory to the port it last connected to
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PortCache {
+    ports: BTreeMap<PathBuf, u16>,
+}
+
+impl PortCache {
+    /// Load the cache from `path`; a missing file yields an empty cache
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read port cache {}", path.display()))?;
+        let ports = serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse port cache {}", path.display()))?;
+        Ok(Self { ports })
+    }
+
+    /// Write the cache to `path`, creating parent directories as needed
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let data =
+            serde_json::to_string_pretty(&self.ports).context("Failed to serialize port cache")?;
+        std::fs::write(path, data)
+            .with_context(|| format!("Failed to write port cache {}", path.display()))
+    }
+
+    /// Port `cwd` last connected to
+    pub fn get(&self, cwd: &Path) -> Option<u16> {
+        self.ports.get(cwd).copied()
+    }
+
+    /// Remember that `cwd` connected to `port`
+    pub fn insert(&mut self, cwd: &Path, port: u16) {
+        self.ports.insert(cwd.to_path_buf(), port);
+    }
+}
+
+/// Port `cwd` last connected to, according to the cache file at `path`
+pub fn lookup(path: &Path, cwd: &Path) -> Option<u16> {
+    PortCache::load(path).ok()?.get(cwd)
+}
+
+/// Record in the cache file at `path` that `cwd` connected to `port`
+pub fn record(path: &Path, cwd: &Path, port: u16) -> Result<()> {
+    let mut cache = PortCache::load(path)?;
+    if cache.get(cwd) == Some(port) {
+        return Ok(());
+    }
+    cache.insert(cwd, port);
+    cache.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(PORT_CACHE_FILE);
+
+        record(&path, Path::new("/work/alpha"), 4096).unwrap();
+        record(&path, Path::new("/work/beta"), 8080).unwrap();
+        record(&path, Path::new("/work/alpha"), 4097).unwrap();
+
+        assert_eq!(lookup(&path, Path::new("/work/alpha")), Some(4097));
+        assert_eq!(lookup(&path, Path::new("/work/beta")), Some(8080));
+        assert_eq!(lookup(&path, Path::new("/work/gamma")), None);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["/work/beta"], 8080);
+    }
+
+    #[test]
+    fn test_lookup_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PORT_CACHE_FILE);
+        assert_eq!(lookup(&path, Path::new("/work/alpha")), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! `.prompt-dialog.toml`: per-project default params
+//!
+//! The file is found by walking up from the working directory, the way rustfmt
+//! finds its config; the walk stops at the repository root (the directory
+//! holding `.git`) or the filesystem root. Its `[params]` table takes the same
+//! values as `--params-file` and sits below every other param source.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::params_file;
+
+/// Name of the project config file
+pub const PROJECT_FILE: &str = ".prompt-dialog.toml";
+
+/// The nearest project file at or above `start`, not looking past the
+/// repository root
+pub fn find(start: &Path) -> Option<PathBuf> {
+    for dir in start.ancestors() {
+        let path = dir.join(PROJECT_FILE);
+        if path.is_file() {
+            return Some(path);
+        }
+        if dir.join(".git").exists() {
+            return None;
+        }
+    }
+    None
+}
+
+/// Project file layout; other tables are ignored
+#[derive(Debug, Deserialize)]
+struct ProjectFile {
+    #[serde(default)]
+    params: toml::Table,
+}
+
+/// Params from a project file's `[params]` table
+pub fn parse(data: &str) -> Result<HashMap<String, String>> {
+    let file: ProjectFile = toml::from_str(data).context("Failed to parse TOML")?;
+    params_file::from_toml_table(file.params)
+}
+
+/// Load the params of the project file at `path`
+pub fn load(path: &Path) -> Result<HashMap<String, String>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read project config {}", path.display()))?;
+    parse(&data).with_context(|| format!("Invalid project config {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_walks_up_to_repo_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let nested = repo.join("crates").join("core");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir(repo.join(".git")).unwrap();
+
+        // Nothing in the repository: the file above it is out of reach
+        std::fs::write(dir.path().join(PROJECT_FILE), "").unwrap();
+        assert_eq!(find(&nested), None);
+
+        // The repository root's file is found from deep inside
+        std::fs::write(repo.join(PROJECT_FILE), "").unwrap();
+        assert_eq!(find(&nested), Some(repo.join(PROJECT_FILE)));
+
+        // The nearest file wins
+        std::fs::write(nested.join(PROJECT_FILE), "").unwrap();
+        assert_eq!(find(&nested), Some(nested.join(PROJECT_FILE)));
+    }
+
+    #[test]
+    fn test_find_outside_a_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(dir.path().join(PROJECT_FILE), "").unwrap();
+        assert_eq!(find(&nested), Some(dir.path().join(PROJECT_FILE)));
+    }
+
+    #[test]
+    fn test_parse_params_table() {
+        let params =
+            parse("[params]\nlang = \"rust\"\nfiles = [\"a.rs\", \"b.rs\"]\n\n[other]\nkey = 1\n")
+                .unwrap();
+        assert_eq!(params.len(), 2);
+        assert_eq!(params["lang"], "rust");
+        assert_eq!(params["files"], "a.rs,b.rs");
+
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("[params]\nmeta = { a = 1 }\n").is_err());
+        assert!(parse("params = 3\n").is_err());
+    }
+}
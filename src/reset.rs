@@ -0,0 +1,104 @@
+//! `reset` subcommand: delete persisted state
+//!
+//! Every state file lives in `<config dir>/prompt-dialog/`; this removes the
+//! selected ones and leaves everything else in that directory alone.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// A kind of persisted state that can be reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// Submitted prompts (`history`, Up/Down recall)
+    History,
+    /// `--stats` usage counters
+    Stats,
+    /// Last-used port per working directory
+    Cache,
+    /// Last window position
+    Window,
+}
+
+impl State {
+    /// Every kind of state, for `reset --all`
+    pub const ALL: [State; 4] = [State::History, State::Stats, State::Cache, State::Window];
+
+    /// File name inside the state directory
+    pub fn file_name(self) -> &'static str {
+        match self {
+            State::History => crate::history::HISTORY_FILE,
+            State::Stats => crate::stats::STATS_FILE,
+            State::Cache => crate::port_cache::PORT_CACHE_FILE,
+            State::Window => crate::window_state::WINDOW_STATE_FILE,
+        }
+    }
+}
+
+/// Default state directory: `<config dir>/prompt-dialog`
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("prompt-dialog"))
+}
+
+/// Delete the files for `states` in `dir`, returning the ones that existed
+pub fn reset(dir: &Path, states: &[State]) -> Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    for state in states {
+        let path = dir.join(state.file_name());
+        if !path.exists() {
+            continue;
+        }
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+        removed.push(path);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A state dir with every state file plus an unrelated one
+    fn populated() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for state in State::ALL {
+            std::fs::write(dir.path().join(state.file_name()), "{}").unwrap();
+        }
+        std::fs::write(dir.path().join("notes.txt"), "keep").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_reset_selected_only() {
+        let dir = populated();
+        let removed = reset(dir.path(), &[State::History]).unwrap();
+
+        assert_eq!(removed, vec![dir.path().join(State::History.file_name())]);
+        assert!(!dir.path().join(State::History.file_name()).exists());
+        assert!(dir.path().join(State::Stats.file_name()).exists());
+        assert!(dir.path().join(State::Cache.file_name()).exists());
+    }
+
+    #[test]
+    fn test_reset_all_keeps_unrelated_files() {
+        let dir = populated();
+        let removed = reset(dir.path(), &State::ALL).unwrap();
+
+        assert_eq!(removed.len(), 4);
+        let left: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(left, vec!["notes.txt"]);
+    }
+
+    #[test]
+    fn test_reset_missing_files_is_ok() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(reset(dir.path(), &State::ALL).unwrap().is_empty());
+        assert!(reset(&dir.path().join("missing"), &[State::Stats])
+            .unwrap()
+            .is_empty());
+    }
+}
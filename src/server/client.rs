@@ -2,16 +2,86 @@
 //!
 //! Communicates with the OpenCode server via HTTP/JSON.
 
-use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 
+use super::discovery::canonical;
+
 /// HTTP client for OpenCode server
 #[derive(Debug, Clone)]
 pub struct Client {
-    port: u16,
+    endpoint: Endpoint,
+    tls: TlsOptions,
+    /// Per-request timeout; `None` waits indefinitely
+    timeout: Option<Duration>,
+    /// Timeout for establishing the connection alone; `None` waits indefinitely
+    connect_timeout: Option<Duration>,
     http: reqwest::Client,
+    /// When set, `send_prompt` re-checks the server cwd against this first
+    expected_cwd: Option<PathBuf>,
+    /// Files referenced (`@<path>`) in the TUI prompt before each sent prompt
+    attachments: Vec<PathBuf>,
+    /// Extra attempts for each TUI event after a transient failure
+    retries: u32,
+    /// Path prefix of every endpoint, e.g. `/opencode` behind a proxy; empty for none
+    base_path: String,
+    /// TUI command executed after the text is appended
+    submit_command: String,
 }
 
+/// Per-request timeout used unless `--timeout` says otherwise
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connect timeout used unless `--connect-timeout` says otherwise
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Retries for sends that fail to connect unless `--retries` says otherwise
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// TUI command that submits the prompt unless `--command` says otherwise
+pub const DEFAULT_SUBMIT_COMMAND: &str = "prompt.submit";
+
+/// Delay before the first retry; doubles with every further one
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// HTTPS settings, from `--tls` / `--insecure`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TlsOptions {
+    /// Talk `https://` instead of `http://`
+    pub enabled: bool,
+    /// Accept invalid (e.g. self-signed) certificates
+    pub insecure: bool,
+}
+
+/// Why a request never got an answer, kept apart so the dialog can tell an
+/// unreachable server from a slow one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    /// The connection couldn't be established (refused, unreachable, or the
+    /// connect timeout expired)
+    Connect(String),
+    /// Connected, but the whole request didn't finish within the timeout
+    Timeout(Duration),
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Connect(address) => {
+                write!(f, "could not connect to OpenCode server at {}", address)
+            }
+            RequestError::Timeout(timeout) => {
+                write!(f, "request timed out after {}s", timeout.as_secs_f32())
+            }
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
 /// Response from /path endpoint
 #[derive(Debug, Deserialize)]
 pub struct PathResponse {
@@ -19,6 +89,33 @@ pub struct PathResponse {
     pub worktree: Option<String>,
 }
 
+impl PathResponse {
+    /// The server's working directory, preferring `directory` over `worktree`
+    pub fn cwd(self) -> Option<PathBuf> {
+        self.directory.or(self.worktree).map(PathBuf::from)
+    }
+}
+
+/// How `send_prompt` delivers a prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SendMode {
+    /// Append the text to the TUI prompt, then submit it
+    #[default]
+    AppendAndSubmit,
+    /// Submit whatever is already in the TUI prompt; the text is not sent
+    SubmitOnly,
+    /// Append the text to the TUI prompt without submitting, to keep editing there
+    AppendOnly,
+    /// Create a new session and post the text there, bypassing the TUI prompt
+    NewSession,
+}
+
+/// Response from POST /session
+#[derive(Debug, Deserialize)]
+struct SessionResponse {
+    id: String,
+}
+
 /// TUI publish request body
 #[derive(Debug, Serialize)]
 struct TuiPublishRequest {
@@ -27,31 +124,139 @@ struct TuiPublishRequest {
     properties: serde_json::Value,
 }
 
+/// Where the OpenCode API listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// TCP host and port
+    Tcp { host: String, port: u16 },
+    /// Unix domain socket (`--socket`)
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// Base URL of every request (IPv6 literals are bracketed). Over a socket
+    /// the URL only supplies the path and a `localhost` Host header.
+    fn base_url(&self, tls: TlsOptions) -> String {
+        let scheme = if tls.enabled { "https" } else { "http" };
+        match self {
+            Endpoint::Tcp { host, port } if host.contains(':') && !host.starts_with('[') => {
+                format!("{}://[{}]:{}", scheme, host, port)
+            }
+            Endpoint::Tcp { host, port } => format!("{}://{}:{}", scheme, host, port),
+            Endpoint::Unix(_) => "http://localhost".to_string(),
+        }
+    }
+
+    /// Where the server is, for error messages: `host:port` or the socket path
+    fn address(&self) -> String {
+        match self {
+            Endpoint::Tcp { host, port } => format!("{}:{}", host, port),
+            Endpoint::Unix(path) => path.display().to_string(),
+        }
+    }
+}
+
 impl Client {
-    /// Create a new client for the given port
-    pub fn new(port: u16) -> Self {
-        let http = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(5))
-            .build()
-            .expect("Failed to create HTTP client");
+    /// Create a new client for the given port on localhost.
+    ///
+    /// Requests give up after `timeout`; `None` disables the timeout.
+    pub fn new(port: u16, timeout: Option<Duration>) -> Self {
+        Self::with_host("localhost", port, timeout)
+    }
+
+    /// Create a new client for the given host and port
+    pub fn with_host(host: &str, port: u16, timeout: Option<Duration>) -> Self {
+        Self::with_tls(host, port, TlsOptions::default(), timeout)
+    }
+
+    /// Create a new client for the given host and port, using HTTPS if `tls` says so
+    pub fn with_tls(host: &str, port: u16, tls: TlsOptions, timeout: Option<Duration>) -> Self {
+        let endpoint = Endpoint::Tcp {
+            host: host.to_string(),
+            port,
+        };
+        Self::with_endpoint(endpoint, tls, timeout)
+    }
+
+    /// Create a new client talking plain HTTP over the Unix socket at `path`
+    pub fn with_socket(path: &Path, timeout: Option<Duration>) -> Self {
+        let endpoint = Endpoint::Unix(path.to_path_buf());
+        Self::with_endpoint(endpoint, TlsOptions::default(), timeout)
+    }
 
-        Self { port, http }
+    fn with_endpoint(endpoint: Endpoint, tls: TlsOptions, timeout: Option<Duration>) -> Self {
+        let connect_timeout = Some(DEFAULT_CONNECT_TIMEOUT);
+        Self {
+            http: build_http(&endpoint, tls, timeout, connect_timeout),
+            endpoint,
+            tls,
+            timeout,
+            connect_timeout,
+            expected_cwd: None,
+            attachments: Vec::new(),
+            retries: DEFAULT_RETRIES,
+            base_path: String::new(),
+            submit_command: DEFAULT_SUBMIT_COMMAND.to_string(),
+        }
+    }
+
+    /// Give up connecting after `timeout` (`None` waits indefinitely),
+    /// separately from the overall per-request timeout
+    pub fn with_connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self.http = build_http(&self.endpoint, self.tls, self.timeout, timeout);
+        self
+    }
+
+    /// Turn a failed request into a `RequestError` when it never got an
+    /// answer, so connect failures and timeouts read differently
+    fn request_error(&self, error: reqwest::Error) -> anyhow::Error {
+        if error.is_connect() {
+            anyhow::Error::new(error).context(RequestError::Connect(self.endpoint.address()))
+        } else if let (true, Some(timeout)) = (error.is_timeout(), self.timeout) {
+            anyhow::Error::new(error).context(RequestError::Timeout(timeout))
+        } else {
+            error.into()
+        }
+    }
+
+    /// Abort sends if the server's cwd no longer matches `cwd` (the discovered one)
+    pub fn with_expected_cwd(mut self, cwd: &Path) -> Self {
+        self.expected_cwd = Some(cwd.to_path_buf());
+        self
     }
 
     /// Base URL for the server
     fn base_url(&self) -> String {
-        format!("http://localhost:{}", self.port)
+        self.endpoint.base_url(self.tls)
+    }
+
+    /// Serve every endpoint under `base_path` (`opencode`, `/opencode/` and
+    /// `/opencode` are equivalent; empty or `/` means no prefix)
+    pub fn with_base_path(mut self, base_path: &str) -> Self {
+        let trimmed = base_path.trim_matches('/');
+        self.base_path = if trimmed.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", trimmed)
+        };
+        self
+    }
+
+    /// Full URL of `endpoint` (which starts with `/`), including the base path
+    fn url(&self, endpoint: &str) -> String {
+        format!("{}{}{}", self.base_url(), self.base_path, endpoint)
     }
 
     /// GET /path - Get server working directory
     pub async fn get_path(&self) -> Result<PathResponse> {
-        let url = format!("{}/path", self.base_url());
+        let url = self.url("/path");
         let response = self
             .http
             .get(&url)
             .send()
             .await
-            .context("Failed to connect to OpenCode server")?;
+            .map_err(|e| self.request_error(e))?;
 
         response
             .json()
@@ -59,69 +264,707 @@ impl Client {
             .context("Failed to parse path response")
     }
 
+    /// POST /tui/publish, retrying failed connections with exponential backoff.
+    ///
+    /// Fails on a non-2xx answer, with the status and the start of the body.
+    /// Only the event type is logged, since the properties hold prompt text.
+    async fn publish(&self, request: &TuiPublishRequest) -> Result<()> {
+        let url = self.url("/tui/publish");
+        let mut attempt = 0;
+        loop {
+            log::debug!("POST {} ({})", url, request.event_type);
+            let outcome = self.http.post(&url).json(request).send().await;
+            if attempt < self.retries && is_transient(&outcome) {
+                let delay = backoff_delay(attempt);
+                log::info!("{} failed, retrying in {:?}", request.event_type, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+            let response = outcome.map_err(|e| self.request_error(e))?;
+            return check_status(response).await.map(drop);
+        }
+    }
+
     /// POST /tui/publish - Append text to the TUI prompt
     async fn tui_append_prompt(&self, text: &str) -> Result<()> {
-        let url = format!("{}/tui/publish", self.base_url());
         let request = TuiPublishRequest {
             event_type: "tui.prompt.append".to_string(),
             properties: serde_json::json!({ "text": text }),
         };
 
-        self.http
-            .post(&url)
-            .json(&request)
-            .send()
+        self.publish(&request)
             .await
-            .context("Failed to append prompt")?;
-
-        Ok(())
+            .context("Failed to append prompt")
     }
 
     /// POST /tui/publish - Execute a TUI command
     async fn tui_execute_command(&self, command: &str) -> Result<()> {
-        let url = format!("{}/tui/publish", self.base_url());
         let request = TuiPublishRequest {
             event_type: "tui.command.execute".to_string(),
             properties: serde_json::json!({ "command": command }),
         };
 
-        self.http
+        self.publish(&request)
+            .await
+            .context("Failed to execute command")
+    }
+
+    /// POST /session - Create a new session, returning its id
+    pub async fn create_session(&self) -> Result<String> {
+        let response = self
+            .http
+            .post(self.url("/session"))
+            .json(&serde_json::json!({}))
+            .send()
+            .await
+            .map_err(|e| self.request_error(e))?;
+        let session: SessionResponse = check_status(response)
+            .await?
+            .json()
+            .await
+            .context("Failed to parse session response")?;
+        Ok(session.id)
+    }
+
+    /// POST /session/{id}/prompt_async - Post a user message to a session.
+    ///
+    /// The server answers as soon as the message is queued, without waiting
+    /// for the model's reply.
+    pub async fn send_to_session(&self, session_id: &str, text: &str) -> Result<()> {
+        let body = serde_json::json!({ "parts": [{ "type": "text", "text": text }] });
+        let url = self.url(&format!("/session/{}/prompt_async", session_id));
+        log::debug!("POST {}", url);
+        let response = self
+            .http
             .post(&url)
-            .json(&request)
+            .json(&body)
             .send()
             .await
-            .context("Failed to execute command")?;
+            .map_err(|e| self.request_error(e))?;
+        check_status(response).await.map(drop)
+    }
+
+    /// Retry each TUI event up to `retries` more times after a transient failure
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Execute `command` instead of `prompt.submit` after appending
+    pub fn with_submit_command(mut self, command: &str) -> Self {
+        self.submit_command = command.to_string();
+        self
+    }
+
+    /// Attach `paths` to every prompt, one append event per file
+    pub fn with_attachments(mut self, paths: &[PathBuf]) -> Self {
+        self.attachments = paths.to_vec();
+        self
+    }
 
+    /// Fail if the server's cwd differs from the expected one, e.g. because the
+    /// user switched projects in OpenCode after discovery. Both are compared
+    /// with symlinks resolved, as discovery does.
+    async fn verify_cwd(&self, expected: &Path) -> Result<()> {
+        let current = self
+            .get_path()
+            .await?
+            .cwd()
+            .ok_or_else(|| anyhow!("Server did not return a working directory"))?;
+        if canonical(&current) != canonical(expected) {
+            return Err(anyhow!(
+                "Server cwd changed since discovery ({} -> {}), not sending",
+                expected.display(),
+                current.display()
+            ));
+        }
         Ok(())
     }
 
-    /// Send a prompt to OpenCode: append text then submit
-    pub async fn send_prompt(&self, text: &str) -> Result<()> {
-        self.tui_append_prompt(text)
-            .await
-            .context("Failed to append prompt text")?;
+    /// Send a prompt to OpenCode: append any attachments, then the text (unless
+    /// `mode` is submit-only), then submit (unless `mode` is append-only).
+    ///
+    /// In `NewSession` mode the attachments and text go to a fresh session
+    /// instead, and its id is returned.
+    pub async fn send_prompt(&self, text: &str, mode: SendMode) -> Result<Option<String>> {
+        if let Some(ref expected) = self.expected_cwd {
+            self.verify_cwd(expected).await?;
+        }
+
+        if mode == SendMode::NewSession {
+            let session_id = self
+                .create_session()
+                .await
+                .context("Failed to create session")?;
+            let references: String = self
+                .attachments
+                .iter()
+                .map(|path| format!("@{} ", path.display()))
+                .collect();
+            self.send_to_session(&session_id, &format!("{}{}", references, text))
+                .await
+                .with_context(|| format!("Failed to send prompt to session {}", session_id))?;
+            return Ok(Some(session_id));
+        }
+
+        for path in &self.attachments {
+            self.tui_append_prompt(&format!("@{} ", path.display()))
+                .await
+                .with_context(|| format!("Failed to attach {}", path.display()))?;
+        }
+
+        if mode != SendMode::SubmitOnly {
+            self.tui_append_prompt(text)
+                .await
+                .context("Failed to append prompt text")?;
+        }
+        if mode == SendMode::AppendOnly {
+            return Ok(None);
+        }
 
-        self.tui_execute_command("prompt.submit")
+        self.tui_execute_command(&self.submit_command)
             .await
             .context("Failed to submit prompt")?;
 
-        Ok(())
+        Ok(None)
     }
 }
 
+/// HTTP client with the given timeouts, TLS settings and (for a socket
+/// endpoint) Unix socket transport
+fn build_http(
+    endpoint: &Endpoint,
+    tls: TlsOptions,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+) -> reqwest::Client {
+    let mut builder =
+        reqwest::Client::builder().danger_accept_invalid_certs(tls.enabled && tls.insecure);
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(timeout) = connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    #[cfg(unix)]
+    if let Endpoint::Unix(path) = endpoint {
+        builder = builder.unix_socket(path.as_path());
+    }
+    // `run` rejects `--socket` off Unix, so there is no socket to route to
+    #[cfg(not(unix))]
+    let _ = endpoint;
+    builder.build().expect("Failed to create HTTP client")
+}
+
+/// Whether a request outcome is worth retrying.
+///
+/// Only connection failures qualify, where the request never reached the
+/// server. Events like `tui.prompt.append` aren't idempotent: after a 5xx or a
+/// timeout the server may already have applied it, so resending could
+/// duplicate the prompt.
+fn is_transient(outcome: &reqwest::Result<reqwest::Response>) -> bool {
+    matches!(outcome, Err(error) if error.is_connect())
+}
+
+/// Longest part of an error response body quoted in the error message
+const ERROR_BODY_SNIPPET: usize = 200;
+
+/// Turn a non-2xx response into an error carrying its status and body snippet
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let body = body.trim();
+    let snippet: String = body.chars().take(ERROR_BODY_SNIPPET).collect();
+    let ellipsis = if snippet.len() < body.len() {
+        "…"
+    } else {
+        ""
+    };
+    Err(anyhow!(
+        "Server returned {}: {}{}",
+        status,
+        snippet,
+        ellipsis
+    ))
+}
+
+/// Delay before retry number `attempt` (0-based): 200ms, 400ms, 800ms, ...
+fn backoff_delay(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::mock::MockServer;
 
     #[test]
     fn test_base_url() {
-        let client = Client::new(12345);
+        let client = Client::new(12345, Some(DEFAULT_TIMEOUT));
         assert_eq!(client.base_url(), "http://localhost:12345");
     }
 
     #[test]
     fn test_base_url_default_port() {
-        let client = Client::new(4096);
+        let client = Client::new(4096, Some(DEFAULT_TIMEOUT));
         assert_eq!(client.base_url(), "http://localhost:4096");
     }
+
+    #[test]
+    fn test_timeout_is_configurable() {
+        let client = Client::new(4096, Some(Duration::from_secs(30)));
+        assert_eq!(client.timeout, Some(Duration::from_secs(30)));
+
+        let client = Client::with_host("localhost", 4096, None);
+        assert_eq!(client.timeout, None);
+    }
+
+    #[test]
+    fn test_connect_timeout_is_separate() {
+        let client = Client::new(4096, Some(Duration::from_secs(30)));
+        assert_eq!(client.connect_timeout, Some(DEFAULT_CONNECT_TIMEOUT));
+
+        let client = client.with_connect_timeout(Some(Duration::from_secs(2)));
+        assert_eq!(client.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(client.connect_timeout, Some(Duration::from_secs(2)));
+
+        let client = client.with_connect_timeout(None);
+        assert_eq!(client.connect_timeout, None);
+    }
+
+    #[tokio::test]
+    async fn test_connect_failure_is_a_request_error() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let client = Client::with_host("127.0.0.1", port, Some(DEFAULT_TIMEOUT));
+        let err = client.get_path().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RequestError>(),
+            Some(&RequestError::Connect(format!("127.0.0.1:{}", port)))
+        );
+    }
+
+    #[test]
+    fn test_base_url_with_host() {
+        let client = Client::with_host("192.168.1.20", 4096, Some(DEFAULT_TIMEOUT));
+        assert_eq!(client.base_url(), "http://192.168.1.20:4096");
+    }
+
+    #[test]
+    fn test_socket_url() {
+        let client = Client::with_socket(Path::new("/run/opencode.sock"), Some(DEFAULT_TIMEOUT));
+        assert_eq!(
+            client.endpoint,
+            Endpoint::Unix(PathBuf::from("/run/opencode.sock"))
+        );
+        assert_eq!(client.url("/path"), "http://localhost/path");
+        let client = client.with_base_path("/opencode/");
+        assert_eq!(
+            client.url("/tui/publish"),
+            "http://localhost/opencode/tui/publish"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_get_path_over_socket() {
+        use std::io::{Read, Write};
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("opencode.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            let body = r#"{"directory":"/work/alpha"}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        let client = Client::with_socket(&socket, Some(DEFAULT_TIMEOUT));
+        let path = client.get_path().await.unwrap();
+        assert_eq!(path.cwd(), Some(PathBuf::from("/work/alpha")));
+    }
+
+    #[test]
+    fn test_url_with_base_path() {
+        let client = Client::with_host("proxy.lan", 443, Some(DEFAULT_TIMEOUT));
+        assert_eq!(client.url("/path"), "http://proxy.lan:443/path");
+        assert_eq!(
+            client.url("/tui/publish"),
+            "http://proxy.lan:443/tui/publish"
+        );
+
+        for base_path in ["/opencode/", "opencode", "/opencode", "opencode//"] {
+            let client = client.clone().with_base_path(base_path);
+            assert_eq!(client.url("/path"), "http://proxy.lan:443/opencode/path");
+            assert_eq!(
+                client.url("/tui/publish"),
+                "http://proxy.lan:443/opencode/tui/publish"
+            );
+        }
+
+        let client = client.with_base_path("/team/opencode/").with_base_path("/");
+        assert_eq!(client.url("/path"), "http://proxy.lan:443/path");
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_with_base_path() {
+        let server = MockServer::with_body("true");
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT))
+            .with_base_path("/opencode/");
+        client
+            .send_prompt("Fix it", SendMode::AppendAndSubmit)
+            .await
+            .unwrap();
+
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            vec!["/opencode/tui/publish", "/opencode/tui/publish"]
+        );
+    }
+
+    #[test]
+    fn test_base_url_tls() {
+        let tls = TlsOptions {
+            enabled: true,
+            insecure: true,
+        };
+        let client = Client::with_tls("opencode.example.com", 443, tls, Some(DEFAULT_TIMEOUT));
+        assert_eq!(client.base_url(), "https://opencode.example.com:443");
+
+        let client = Client::with_tls(
+            "localhost",
+            4096,
+            TlsOptions::default(),
+            Some(DEFAULT_TIMEOUT),
+        );
+        assert_eq!(client.base_url(), "http://localhost:4096");
+    }
+
+    #[test]
+    fn test_base_url_with_hostname_and_ipv6() {
+        let client = Client::with_host("opencode.lan", 8080, Some(DEFAULT_TIMEOUT));
+        assert_eq!(client.base_url(), "http://opencode.lan:8080");
+
+        let client = Client::with_host("::1", 4096, Some(DEFAULT_TIMEOUT));
+        assert_eq!(client.base_url(), "http://[::1]:4096");
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay(0), Duration::from_millis(200));
+        assert_eq!(backoff_delay(1), Duration::from_millis(400));
+        assert_eq!(backoff_delay(3), Duration::from_millis(1600));
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors() {
+        // A port nothing listens on refuses the connection
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let http = reqwest::Client::new();
+        let refused = http.get(format!("http://127.0.0.1:{}/", port)).send().await;
+        assert!(is_transient(&refused));
+
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/ok" => (200, "{}".to_string()),
+            "/missing" => (404, "{}".to_string()),
+            _ => (503, "{}".to_string()),
+        });
+        let get = |path: &str| http.get(format!("http://127.0.0.1:{}{}", server.port(), path));
+        assert!(!is_transient(&get("/ok").send().await));
+        assert!(!is_transient(&get("/missing").send().await));
+        assert!(!is_transient(&get("/busy").send().await));
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_server_error() {
+        let server = MockServer::start(|_| (500, "{\"error\": \"boom\"}".to_string()));
+        let client =
+            Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT)).with_retries(0);
+
+        let err = client
+            .send_prompt("hello", SendMode::AppendAndSubmit)
+            .await
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("Failed to append prompt text"));
+        assert!(message.contains("500 Internal Server Error"));
+        assert!(message.contains("boom"));
+        assert_eq!(published_types(&server), vec!["tui.prompt.append"]);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let server = MockServer::start(|_| (400, "bad event".to_string()));
+        let client =
+            Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT)).with_retries(3);
+
+        let err = client
+            .send_prompt("hello", SendMode::SubmitOnly)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("400 Bad Request: bad event"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_not_retried() {
+        let server = MockServer::start(|_| (503, "{}".to_string()));
+        let client =
+            Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT)).with_retries(1);
+
+        assert!(client
+            .send_prompt("hello", SendMode::AppendOnly)
+            .await
+            .is_err());
+        assert_eq!(published_types(&server), vec!["tui.prompt.append"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_gives_up_after_retries() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let client = Client::with_host("127.0.0.1", port, Some(DEFAULT_TIMEOUT)).with_retries(1);
+
+        let started = std::time::Instant::now();
+        let err = client
+            .send_prompt("hello", SendMode::AppendAndSubmit)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to append prompt text"));
+        assert!(started.elapsed() >= backoff_delay(0));
+    }
+
+    /// Published event types, in order
+    fn published_types(server: &MockServer) -> Vec<String> {
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.method == "POST" && r.path == "/tui/publish")
+            .map(|r| r.json()["type"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_new_session() {
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/session" => (200, r#"{"id": "ses_123", "title": "New"}"#.to_string()),
+            _ => (204, String::new()),
+        });
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT))
+            .with_attachments(&[PathBuf::from("/src/lib.rs")]);
+
+        let session = client
+            .send_prompt("Fix it", SendMode::NewSession)
+            .await
+            .unwrap();
+        assert_eq!(session.as_deref(), Some("ses_123"));
+
+        let requests = server.requests();
+        let paths: Vec<&str> = requests.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, vec!["/session", "/session/ses_123/prompt_async"]);
+        assert_eq!(
+            requests[1].json()["parts"][0],
+            serde_json::json!({ "type": "text", "text": "@/src/lib.rs Fix it" })
+        );
+        assert!(published_types(&server).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_session_error() {
+        let server = MockServer::start(|_| (500, "no sessions today".to_string()));
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT));
+
+        let err = client
+            .send_prompt("Fix it", SendMode::NewSession)
+            .await
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("Failed to create session"));
+        assert!(message.contains("no sessions today"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_appends_then_submits() {
+        let server = MockServer::with_body("true");
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT));
+        client
+            .send_prompt("Fix it", SendMode::AppendAndSubmit)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            published_types(&server),
+            vec!["tui.prompt.append", "tui.command.execute"]
+        );
+        assert_eq!(server.requests()[0].json()["properties"]["text"], "Fix it");
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_submit_only() {
+        let server = MockServer::with_body("true");
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT));
+        client
+            .send_prompt("ignored", SendMode::SubmitOnly)
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].json()["type"], "tui.command.execute");
+        assert_eq!(requests[0].json()["properties"]["command"], "prompt.submit");
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_custom_command() {
+        let server = MockServer::with_body("true");
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT))
+            .with_submit_command("session.new");
+        client
+            .send_prompt("Fix it", SendMode::AppendAndSubmit)
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[1].json()["type"], "tui.command.execute");
+        assert_eq!(requests[1].json()["properties"]["command"], "session.new");
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_append_only() {
+        let server = MockServer::with_body("true");
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT));
+        client
+            .send_prompt("Draft", SendMode::AppendOnly)
+            .await
+            .unwrap();
+
+        assert_eq!(published_types(&server), vec!["tui.prompt.append"]);
+        assert_eq!(server.requests()[0].json()["properties"]["text"], "Draft");
+    }
+
+    #[tokio::test]
+    async fn test_send_with_attachments_in_order() {
+        let server = MockServer::with_body("true");
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT))
+            .with_attachments(&[
+                PathBuf::from("/work/src/a.rs"),
+                PathBuf::from("/work/src/b.rs"),
+            ]);
+        client
+            .send_prompt("Compare these", SendMode::AppendAndSubmit)
+            .await
+            .unwrap();
+
+        let texts: Vec<serde_json::Value> = server
+            .requests()
+            .iter()
+            .map(|r| r.json()["properties"]["text"].clone())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                serde_json::json!("@/work/src/a.rs "),
+                serde_json::json!("@/work/src/b.rs "),
+                serde_json::json!("Compare these"),
+                serde_json::Value::Null,
+            ]
+        );
+        assert_eq!(
+            published_types(&server),
+            vec![
+                "tui.prompt.append",
+                "tui.prompt.append",
+                "tui.prompt.append",
+                "tui.command.execute"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_aborts_when_cwd_changed() {
+        let path_calls = std::sync::atomic::AtomicUsize::new(0);
+        let server = MockServer::start(move |req| {
+            if req.path != "/path" {
+                return (200, "true".to_string());
+            }
+            // First call is discovery, later ones see the switched project
+            let dir = match path_calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => "/work/alpha",
+                _ => "/work/beta",
+            };
+            (200, format!(r#"{{"directory":"{}"}}"#, dir))
+        });
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT));
+        let discovered = client.get_path().await.unwrap().cwd().unwrap();
+
+        let client = client.with_expected_cwd(&discovered);
+        let err = client
+            .send_prompt("Fix it", SendMode::AppendAndSubmit)
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("cwd changed"), "{}", err);
+        assert!(published_types(&server).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_send_with_unchanged_cwd() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/path" => (200, r#"{"directory":"/work/alpha"}"#.to_string()),
+            _ => (200, "true".to_string()),
+        });
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT))
+            .with_expected_cwd(Path::new("/work/alpha"));
+        client
+            .send_prompt("Fix it", SendMode::AppendAndSubmit)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            published_types(&server),
+            vec!["tui.prompt.append", "tui.command.execute"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_with_same_cwd_spelled_differently() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let body = serde_json::json!({ "directory": dir.path() }).to_string();
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/path" => (200, body.clone()),
+            _ => (200, "true".to_string()),
+        });
+        let client = Client::with_host("127.0.0.1", server.port(), Some(DEFAULT_TIMEOUT))
+            .with_expected_cwd(&dir.path().join("sub").join(".."));
+        client
+            .send_prompt("Fix it", SendMode::AppendOnly)
+            .await
+            .unwrap();
+
+        assert_eq!(published_types(&server), vec!["tui.prompt.append"]);
+    }
 }
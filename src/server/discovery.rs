@@ -2,41 +2,97 @@
 //!
 //! Finds running OpenCode servers by scanning processes and validating via HTTP.
 
+use std::future::Future;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 use sysinfo::System;
 
+use super::client::{Client, TlsOptions, DEFAULT_TIMEOUT};
+use super::instances::Instance;
+
 /// A discovered OpenCode server
 #[derive(Debug, Clone)]
 pub struct Server {
-    /// Process ID
+    /// Process ID (0 when not discovered via process scan)
     pub pid: u32,
+    /// Host the server is reachable on
+    pub host: String,
     /// HTTP server port
     pub port: u16,
+    /// Whether the server was reached over HTTPS
+    pub tls: TlsOptions,
     /// Working directory of the server
     pub cwd: PathBuf,
+    /// Unix socket the server was reached over (`--socket`); `host` and `port`
+    /// are then unused
+    pub socket: Option<PathBuf>,
+}
+
+impl Server {
+    /// TCP port for display, `None` over a Unix socket
+    pub fn tcp_port(&self) -> Option<u16> {
+        self.socket.is_none().then_some(self.port)
+    }
+}
+
+/// Bounds on the process scan, for machines running thousands of processes
+///
+/// `None` means unbounded, which keeps the scan exhaustive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScanLimits {
+    /// Stop scanning once this many OpenCode candidates were found
+    pub max_candidates: Option<usize>,
+    /// Examine at most this many processes, newest first
+    pub max_processes: Option<usize>,
 }
 
-/// Find OpenCode processes with --port flag
-fn find_opencode_processes() -> Vec<(u32, String)> {
+/// Find OpenCode processes with --port flag, newest first
+fn find_opencode_processes(limits: ScanLimits) -> Vec<(u32, String)> {
     let system = System::new_all();
-    let mut processes = Vec::new();
-
-    for (pid, process) in system.processes() {
-        let cmd_str: String = process
-            .cmd()
-            .iter()
-            .map(|s| s.to_string_lossy().to_string())
-            .collect::<Vec<_>>()
-            .join(" ");
-
-        if cmd_str.contains("opencode") && cmd_str.contains("--port") {
-            processes.push((pid.as_u32(), cmd_str));
-        }
+    let processes = system
+        .processes()
+        .iter()
+        .map(|(pid, process)| (pid.as_u32(), process.start_time(), process))
+        .collect();
+
+    select_opencode_processes(
+        processes,
+        |process| {
+            process
+                .cmd()
+                .iter()
+                .map(|s| s.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        limits,
+    )
+}
+
+/// Pick OpenCode candidates from `(pid, start_time, process)` entries.
+///
+/// Processes are ordered by start time (most recent first) so the caps keep
+/// the likeliest servers. Command lines are only built for examined processes.
+fn select_opencode_processes<P>(
+    mut processes: Vec<(u32, u64, P)>,
+    cmdline: impl Fn(&P) -> String,
+    limits: ScanLimits,
+) -> Vec<(u32, String)> {
+    processes.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+    if let Some(max) = limits.max_processes {
+        processes.truncate(max);
     }
 
-    processes
+    let matches = processes.iter().filter_map(|(pid, _, process)| {
+        let cmd_str = cmdline(process);
+        (cmd_str.contains("opencode") && cmd_str.contains("--port")).then_some((*pid, cmd_str))
+    });
+    match limits.max_candidates {
+        Some(max) => matches.take(max).collect(),
+        None => matches.collect(),
+    }
 }
 
 /// Extract port number from command line arguments
@@ -58,75 +114,518 @@ fn extract_port_from_cmdline(cmdline: &str) -> Option<u16> {
     None
 }
 
+/// How a server's cwd has to relate to ours to count as a match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CwdMatch {
+    /// Either directory contains the other
+    #[default]
+    Prefix,
+    /// The very same directory (`--exact-cwd`), so a parent directory's server
+    /// never stands in for a subdirectory's own instance
+    Exact,
+}
+
+impl CwdMatch {
+    /// Whether a server running in `server_cwd` serves `cwd`
+    pub fn matches(self, cwd: &Path, server_cwd: &Path) -> bool {
+        match self {
+            CwdMatch::Prefix => cwd_matches(cwd, server_cwd),
+            CwdMatch::Exact => canonical(cwd) == canonical(server_cwd),
+        }
+    }
+}
+
+/// How long to listen for mDNS advertisements
+const MDNS_BROWSE_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// Validate a port is an OpenCode server and get its working directory
-async fn validate_server(port: u16) -> Result<Server> {
-    let client = super::client::Client::new(port);
+async fn validate_server(port: u16, tls: TlsOptions) -> Result<Server> {
+    validate_server_at("localhost", port, "", tls).await
+}
+
+/// Validate a host/port (serving the API under `base_path`) is an OpenCode
+/// server and get its working directory
+async fn validate_server_at(
+    host: &str,
+    port: u16,
+    base_path: &str,
+    tls: TlsOptions,
+) -> Result<Server> {
+    ping_server(host, port, base_path, tls, Some(DEFAULT_TIMEOUT)).await
+}
+
+/// Like `validate_server_at`, giving up after `timeout` (`None` waits
+/// indefinitely); for the `ping` readiness check
+pub async fn ping_server(
+    host: &str,
+    port: u16,
+    base_path: &str,
+    tls: TlsOptions,
+    timeout: Option<Duration>,
+) -> Result<Server> {
+    let client = Client::with_tls(host, port, tls, timeout).with_base_path(base_path);
     let path_response = client
         .get_path()
         .await
         .context("Failed to connect to OpenCode server")?;
 
     let cwd = path_response
-        .directory
-        .or(path_response.worktree)
+        .cwd()
         .ok_or_else(|| anyhow!("Server did not return a working directory"))?;
 
     Ok(Server {
         pid: 0,
+        host: host.to_string(),
         port,
-        cwd: PathBuf::from(cwd),
+        tls,
+        cwd,
+        socket: None,
+    })
+}
+
+/// Validate the server behind the Unix socket `path` (serving the API under
+/// `base_path`); no scanning is involved
+pub async fn discover_socket_server(path: &Path, base_path: &str) -> Result<Server> {
+    let client = Client::with_socket(path, Some(DEFAULT_TIMEOUT)).with_base_path(base_path);
+    let path_response = client
+        .get_path()
+        .await
+        .with_context(|| format!("Failed to connect to OpenCode server at {}", path.display()))?;
+
+    let cwd = path_response
+        .cwd()
+        .ok_or_else(|| anyhow!("Server did not return a working directory"))?;
+
+    Ok(Server {
+        pid: 0,
+        host: "localhost".to_string(),
+        port: 0,
+        tls: TlsOptions::default(),
+        cwd,
+        socket: Some(path.to_path_buf()),
     })
 }
 
+/// Whether `host` names this machine, where process scanning can see servers
+pub fn is_local_host(host: &str) -> bool {
+    matches!(
+        host.trim_start_matches('[').trim_end_matches(']'),
+        "localhost" | "127.0.0.1" | "::1"
+    )
+}
+
 /// Discover an OpenCode server for the given working directory
 ///
-/// If `port` is specified, validates and uses that port directly.
-/// Otherwise, scans for OpenCode processes and finds one matching the cwd.
-pub async fn discover_server(cwd: &Path, port: Option<u16>) -> Result<Server> {
+/// If `port` is specified, validates and uses `host:port` directly, with the
+/// API under `base_path` (e.g. behind a reverse proxy). Otherwise, scans for OpenCode processes (within `limits`) and finds one
+/// matching the cwd; that only works when `host` is this machine. Every
+/// candidate is validated with the scheme `tls` selects.
+///
+/// With `CwdMatch::Exact` every candidate is checked, and the server in the
+/// deepest directory containing `cwd` must be in `cwd` itself.
+pub async fn discover_server(
+    cwd: &Path,
+    host: &str,
+    port: Option<u16>,
+    base_path: &str,
+    limits: ScanLimits,
+    tls: TlsOptions,
+    cwd_match: CwdMatch,
+) -> Result<Server> {
     // If port is specified, use it directly
     if let Some(p) = port {
-        return validate_server(p)
+        return validate_server_at(host, p, base_path, tls)
             .await
-            .context(format!("No OpenCode server responding on port {}", p));
+            .context(format!("No OpenCode server responding on {}:{}", host, p));
+    }
+
+    if !is_local_host(host) {
+        return Err(anyhow!(
+            "Can't scan for OpenCode processes on remote host {}; pass --port too",
+            host
+        ));
+    }
+
+    let processes = scan_processes(limits).await?;
+    let first_only = cwd_match == CwdMatch::Prefix;
+    let (mut servers, last_error) = matching_servers(Some(cwd), processes, tls, first_only).await;
+    if servers.is_empty() {
+        return Err(last_error.unwrap_or_else(|| {
+            anyhow!("No OpenCode server found for directory: {}", cwd.display())
+        }));
+    }
+    if first_only {
+        return Ok(servers.remove(0));
+    }
+
+    match deepest_match(cwd, servers) {
+        Some(server) if cwd_match.matches(cwd, &server.cwd) => Ok(server),
+        Some(server) => Err(anyhow!(
+            "No OpenCode server running in {} (closest is in {}; drop --exact-cwd to use it)",
+            cwd.display(),
+            server.cwd.display()
+        )),
+        None => Err(anyhow!("No OpenCode server running in {}", cwd.display())),
+    }
+}
+
+/// The server whose cwd is the deepest directory containing `cwd` (or `cwd`
+/// itself), preferring the earliest (newest process) among equals
+fn deepest_match(cwd: &Path, servers: Vec<Server>) -> Option<Server> {
+    let cwd = canonical(cwd);
+    servers
+        .into_iter()
+        .filter(|server| cwd.starts_with(canonical(&server.cwd)))
+        .min_by_key(|server| std::cmp::Reverse(canonical(&server.cwd).components().count()))
+}
+
+/// Every local OpenCode server whose cwd matches, newest process first
+/// (one entry per port), for letting the user pick between them
+pub async fn discover_all_servers(
+    cwd: &Path,
+    limits: ScanLimits,
+    tls: TlsOptions,
+    cwd_match: CwdMatch,
+) -> Result<Vec<Server>> {
+    let processes = scan_processes(limits).await?;
+    let (mut servers, last_error) = matching_servers(Some(cwd), processes, tls, false).await;
+    servers.retain(|server| cwd_match.matches(cwd, &server.cwd));
+    if servers.is_empty() {
+        return Err(last_error.unwrap_or_else(|| {
+            anyhow!("No OpenCode server found for directory: {}", cwd.display())
+        }));
     }
+    Ok(servers)
+}
 
-    // Find all OpenCode processes
-    let processes = find_opencode_processes();
+/// Every local OpenCode server that answers, whatever its cwd, newest process
+/// first (one entry per port), for `list-servers`
+pub async fn list_servers(limits: ScanLimits, tls: TlsOptions) -> Result<Vec<Server>> {
+    let processes = scan_processes(limits).await?;
+    let (servers, last_error) = matching_servers(None, processes, tls, false).await;
+    match last_error {
+        Some(e) if servers.is_empty() => Err(e),
+        _ => Ok(servers),
+    }
+}
+
+/// Find OpenCode processes, failing if there are none
+async fn scan_processes(limits: ScanLimits) -> Result<Vec<(u32, String)>> {
+    // Scan on a blocking thread so a stalled scan can't block a caller's timeout
+    let processes = tokio::task::spawn_blocking(move || find_opencode_processes(limits))
+        .await
+        .context("Process scan task failed")?;
     if processes.is_empty() {
         return Err(anyhow!(
             "No OpenCode processes found. Start OpenCode with: opencode --port 8080"
         ));
     }
+    Ok(processes)
+}
 
-    // Try each process to find one matching our cwd
-    let mut last_error = None;
+/// The port of each `(pid, cmdline)` process as `(pid, port)`, once per port.
+///
+/// A wrapper and its node child both match "opencode" with the same `--port`;
+/// the first pid seen (the newest process) is kept.
+fn unique_ports(processes: &[(u32, String)]) -> Vec<(u32, u16)> {
+    let mut ports: Vec<(u32, u16)> = Vec::new();
     for (pid, cmdline) in processes {
-        if let Some(port) = extract_port_from_cmdline(&cmdline) {
-            match validate_server(port).await {
-                Ok(mut server) => {
-                    server.pid = pid;
-
-                    let server_cwd = server.cwd.canonicalize().unwrap_or(server.cwd.clone());
-                    let our_cwd = cwd.canonicalize().unwrap_or(cwd.to_path_buf());
+        let Some(port) = extract_port_from_cmdline(cmdline) else {
+            continue;
+        };
+        if !ports.iter().any(|&(_, seen)| seen == port) {
+            ports.push((*pid, port));
+        }
+    }
+    ports
+}
 
-                    if our_cwd.starts_with(&server_cwd) || server_cwd.starts_with(&our_cwd) {
-                        return Ok(server);
+/// Validate each `(pid, cmdline)` process and keep the servers matching `cwd`
+/// (all of them if `None`), stopping at the first one if `first_only`. Each
+/// port is probed once. Also returns the last validation error, to explain an
+/// empty result.
+async fn matching_servers(
+    cwd: Option<&Path>,
+    processes: Vec<(u32, String)>,
+    tls: TlsOptions,
+    first_only: bool,
+) -> (Vec<Server>, Option<anyhow::Error>) {
+    let mut servers: Vec<Server> = Vec::new();
+    let mut last_error = None;
+    for (pid, port) in unique_ports(&processes) {
+        match validate_server(port, tls).await {
+            Ok(mut server) => {
+                server.pid = pid;
+                log::debug!(
+                    "Process {} serves port {} in {}",
+                    pid,
+                    port,
+                    server.cwd.display()
+                );
+                if cwd.map_or(true, |cwd| cwd_matches(cwd, &server.cwd)) {
+                    servers.push(server);
+                    if first_only {
+                        break;
                     }
                 }
-                Err(e) => {
-                    last_error = Some(e);
-                }
+            }
+            Err(e) => {
+                log::debug!("Process {} on port {} is not usable: {:#}", pid, port, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    (servers, last_error)
+}
+
+/// Whether a server running in `server_cwd` serves `cwd` (one contains the other)
+fn cwd_matches(cwd: &Path, server_cwd: &Path) -> bool {
+    let server_cwd = canonical(server_cwd);
+    let our_cwd = canonical(cwd);
+    our_cwd.starts_with(&server_cwd) || server_cwd.starts_with(&our_cwd)
+}
+
+/// `path` with symlinks resolved, or as given if it can't be (e.g. a remote path)
+pub fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or(path.to_path_buf())
+}
+
+/// Try `cached`, the port `cwd` last connected to, before running `discover`.
+///
+/// The cached port is used only if a server still answers there and its cwd
+/// matches ours; a stale entry falls back to `discover`.
+pub async fn discover_with_cached_port(
+    cwd: &Path,
+    cached: Option<u16>,
+    tls: TlsOptions,
+    cwd_match: CwdMatch,
+    discover: impl Future<Output = Result<Server>>,
+) -> Result<Server> {
+    if let Some(port) = cached {
+        if let Ok(server) = validate_server(port, tls).await {
+            if cwd_match.matches(cwd, &server.cwd) {
+                return Ok(server);
             }
         }
     }
+    discover.await
+}
+
+/// Port files tried when `--port-file` isn't given: `<home>/.opencode/port`,
+/// then `<runtime dir>/opencode-<cwd hash>.port` (see `cwd_hash`)
+pub fn port_file_paths(
+    cwd: &Path,
+    home: Option<&Path>,
+    runtime_dir: Option<&Path>,
+) -> Vec<PathBuf> {
+    let shared = home.map(|home| home.join(".opencode").join("port"));
+    let per_cwd = runtime_dir.map(|dir| dir.join(format!("opencode-{}.port", cwd_hash(cwd))));
+    shared.into_iter().chain(per_cwd).collect()
+}
 
-    Err(last_error
-        .unwrap_or_else(|| anyhow!("No OpenCode server found for directory: {}", cwd.display())))
+/// `port_file_paths` for this user's home and runtime dirs
+pub fn default_port_files(cwd: &Path) -> Vec<PathBuf> {
+    port_file_paths(
+        cwd,
+        dirs::home_dir().as_deref(),
+        dirs::runtime_dir().as_deref(),
+    )
+}
+
+/// Stable name for `cwd` in per-directory port files: the 64-bit FNV-1a hash
+/// of the path's bytes, as 16 lowercase hex digits
+pub fn cwd_hash(cwd: &Path) -> String {
+    let hash = cwd
+        .to_string_lossy()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// Read a port file: the port number as text, surrounding whitespace ignored
+pub fn read_port_file(path: &Path) -> Option<u16> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Find a server for `cwd` via port files, tried in order.
+///
+/// A missing or unreadable file, a port nothing answers on, and a server for
+/// another cwd are all skipped, so discovery can fall through to scanning.
+pub async fn discover_from_port_files(
+    cwd: &Path,
+    paths: &[PathBuf],
+    tls: TlsOptions,
+    cwd_match: CwdMatch,
+) -> Option<Server> {
+    for port in paths.iter().filter_map(|path| read_port_file(path)) {
+        if let Ok(server) = validate_server(port, tls).await {
+            if cwd_match.matches(cwd, &server.cwd) {
+                return Some(server);
+            }
+        }
+    }
+    None
+}
+
+/// Find a server for `cwd` among instances read from instance files.
+///
+/// Instances recording a different cwd are skipped without a request; the
+/// rest are validated and must report a matching cwd.
+pub async fn discover_from_instances(
+    cwd: &Path,
+    instances: &[Instance],
+    tls: TlsOptions,
+    cwd_match: CwdMatch,
+) -> Option<Server> {
+    for instance in instances {
+        if instance
+            .cwd
+            .as_deref()
+            .is_some_and(|recorded| !cwd_match.matches(cwd, recorded))
+        {
+            continue;
+        }
+        if let Ok(mut server) = validate_server(instance.port, tls).await {
+            if cwd_match.matches(cwd, &server.cwd) {
+                server.pid = instance.pid.unwrap_or(0);
+                return Some(server);
+            }
+        }
+    }
+    None
+}
+
+/// Discover an OpenCode server advertised via mDNS on the local network
+///
+/// Every advertised endpoint is validated via `GET /path`. A server whose cwd
+/// matches ours (per `cwd_match`) is preferred. With `CwdMatch::Prefix` the
+/// first valid server is the fallback, since remote working directories can't
+/// be compared with the local one; `CwdMatch::Exact` has no fallback.
+pub async fn discover_mdns_server(
+    cwd: &Path,
+    tls: TlsOptions,
+    cwd_match: CwdMatch,
+) -> Result<Server> {
+    let records = tokio::task::spawn_blocking(|| super::mdns::browse(MDNS_BROWSE_TIMEOUT))
+        .await
+        .context("mDNS browse task failed")??;
+
+    let candidates = super::mdns::candidates_from_records(&records);
+    for c in &candidates {
+        log::debug!("mDNS candidate: {}:{}", c.host, c.port);
+    }
+    if candidates.is_empty() {
+        return Err(anyhow!("No OpenCode servers advertised via mDNS"));
+    }
+
+    let mut valid = Vec::new();
+    let mut last_error = None;
+    for candidate in candidates {
+        match validate_server_at(&candidate.host, candidate.port, "", tls).await {
+            Ok(server) if cwd_match.matches(cwd, &server.cwd) => return Ok(server),
+            Ok(server) => valid.push(server),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    if let Some(server) = mdns_fallback(valid, cwd_match) {
+        return Ok(server);
+    }
+    Err(match (last_error, cwd_match) {
+        (_, CwdMatch::Exact) => anyhow!(
+            "No mDNS-advertised OpenCode server runs in {}",
+            cwd.display()
+        ),
+        (Some(e), _) => e,
+        (None, _) => anyhow!("No mDNS-advertised OpenCode server responded"),
+    })
+}
+
+/// The mDNS server to use when none matched our cwd: the first valid one,
+/// unless only an exact cwd match will do
+fn mdns_fallback(valid: Vec<Server>, cwd_match: CwdMatch) -> Option<Server> {
+    match cwd_match {
+        CwdMatch::Prefix => valid.into_iter().next(),
+        CwdMatch::Exact => None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::mock::MockServer;
+
+    #[test]
+    fn test_cwd_hash() {
+        assert_eq!(cwd_hash(Path::new("/work/alpha")), "2467f728cadd4dd8");
+        assert_ne!(
+            cwd_hash(Path::new("/work/alpha")),
+            cwd_hash(Path::new("/work/beta"))
+        );
+    }
+
+    #[test]
+    fn test_port_file_paths() {
+        let paths = port_file_paths(
+            Path::new("/work/alpha"),
+            Some(Path::new("/home/me")),
+            Some(Path::new("/run/user/1000")),
+        );
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("/home/me/.opencode/port"),
+                PathBuf::from("/run/user/1000/opencode-2467f728cadd4dd8.port"),
+            ]
+        );
+        assert!(port_file_paths(Path::new("/work/alpha"), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_read_port_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("port");
+        assert_eq!(read_port_file(&path), None);
+
+        std::fs::write(&path, "4096\n").unwrap();
+        assert_eq!(read_port_file(&path), Some(4096));
+        std::fs::write(&path, "not a port").unwrap();
+        assert_eq!(read_port_file(&path), None);
+        std::fs::write(&path, "70000").unwrap();
+        assert_eq!(read_port_file(&path), None);
+    }
+
+    #[tokio::test]
+    async fn test_discover_from_port_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = MockServer::with_body(r#"{"directory":"/work/beta"}"#);
+        let ours = MockServer::with_body(r#"{"directory":"/work/alpha"}"#);
+        let files = ["missing", "other", "ours"].map(|name| dir.path().join(name));
+        std::fs::write(&files[1], other.port().to_string()).unwrap();
+        std::fs::write(&files[2], ours.port().to_string()).unwrap();
+
+        let found = discover_from_port_files(
+            Path::new("/work/alpha"),
+            &files,
+            TlsOptions::default(),
+            CwdMatch::Prefix,
+        )
+        .await
+        .unwrap();
+        assert_eq!(found.port, ours.port());
+
+        // Nothing for this cwd: fall through
+        assert!(discover_from_port_files(
+            Path::new("/work/gamma"),
+            &files,
+            TlsOptions::default(),
+            CwdMatch::Prefix
+        )
+        .await
+        .is_none());
+    }
 
     #[test]
     fn test_extract_port_space_separated() {
@@ -166,4 +665,371 @@ mod tests {
     fn test_extract_port_invalid_value() {
         assert_eq!(extract_port_from_cmdline("opencode --port abc"), None);
     }
+
+    fn sample_processes() -> Vec<(u32, u64, &'static str)> {
+        vec![
+            (10, 100, "opencode --port 1000"),
+            (11, 300, "bash"),
+            (12, 200, "opencode --port 2000"),
+            (13, 400, "opencode --port 3000"),
+            (14, 50, "opencode"),
+        ]
+    }
+
+    fn select(limits: ScanLimits) -> Vec<u32> {
+        select_opencode_processes(sample_processes(), |cmd| cmd.to_string(), limits)
+            .into_iter()
+            .map(|(pid, _)| pid)
+            .collect()
+    }
+
+    #[test]
+    fn test_select_unbounded_newest_first() {
+        assert_eq!(select(ScanLimits::default()), vec![13, 12, 10]);
+    }
+
+    #[test]
+    fn test_select_stops_after_max_candidates() {
+        let limits = ScanLimits {
+            max_candidates: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(select(limits), vec![13, 12]);
+    }
+
+    #[test]
+    fn test_select_examines_at_most_max_processes() {
+        // The three newest processes are 13, 11 (not opencode) and 12
+        let limits = ScanLimits {
+            max_processes: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(select(limits), vec![13, 12]);
+    }
+
+    #[test]
+    fn test_select_builds_cmdlines_lazily() {
+        let examined = std::cell::Cell::new(0);
+        let limits = ScanLimits {
+            max_candidates: Some(1),
+            ..Default::default()
+        };
+        let found = select_opencode_processes(
+            sample_processes(),
+            |cmd| {
+                examined.set(examined.get() + 1);
+                cmd.to_string()
+            },
+            limits,
+        );
+        assert_eq!(found, vec![(13, "opencode --port 3000".to_string())]);
+        assert_eq!(examined.get(), 1);
+    }
+
+    /// What the fallback scan "finds" in the cached-port tests
+    async fn scanned() -> Result<Server> {
+        Ok(scanned_server("/work/alpha"))
+    }
+
+    fn scanned_server(cwd: &str) -> Server {
+        Server {
+            pid: 42,
+            host: "localhost".to_string(),
+            port: 1,
+            tls: TlsOptions::default(),
+            cwd: PathBuf::from(cwd),
+            socket: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_port_tried_first() {
+        let server = MockServer::with_body(r#"{"directory":"/work/alpha"}"#);
+        let found = discover_with_cached_port(
+            Path::new("/work/alpha"),
+            Some(server.port()),
+            TlsOptions::default(),
+            CwdMatch::Prefix,
+            scanned(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(found.port, server.port());
+        assert_eq!(server.requests()[0].path, "/path");
+    }
+
+    #[tokio::test]
+    async fn test_cached_port_other_project_falls_back() {
+        let server = MockServer::with_body(r#"{"directory":"/work/beta"}"#);
+        let found = discover_with_cached_port(
+            Path::new("/work/alpha"),
+            Some(server.port()),
+            TlsOptions::default(),
+            CwdMatch::Prefix,
+            scanned(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(found.pid, 42);
+    }
+
+    #[test]
+    fn test_is_local_host() {
+        assert!(is_local_host("localhost"));
+        assert!(is_local_host("127.0.0.1"));
+        assert!(is_local_host("[::1]"));
+        assert!(!is_local_host("192.168.1.20"));
+        assert!(!is_local_host("opencode.internal"));
+    }
+
+    #[tokio::test]
+    async fn test_remote_host_requires_port() {
+        let err = discover_server(
+            Path::new("/work/alpha"),
+            "192.168.1.20",
+            None,
+            "",
+            ScanLimits::default(),
+            TlsOptions::default(),
+            CwdMatch::Prefix,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("--port"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_explicit_port_uses_host() {
+        let server = MockServer::with_body(r#"{"directory":"/srv/app"}"#);
+        let found = discover_server(
+            Path::new("/work/alpha"),
+            "127.0.0.1",
+            Some(server.port()),
+            "",
+            ScanLimits::default(),
+            TlsOptions::default(),
+            CwdMatch::Prefix,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(found.host, "127.0.0.1");
+        assert_eq!(found.cwd, PathBuf::from("/srv/app"));
+    }
+
+    #[tokio::test]
+    async fn test_explicit_port_with_base_path() {
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/opencode/path" => (200, r#"{"directory":"/srv/app"}"#.to_string()),
+            _ => (404, "{}".to_string()),
+        });
+        let found = discover_server(
+            Path::new("/work/alpha"),
+            "127.0.0.1",
+            Some(server.port()),
+            "/opencode/",
+            ScanLimits::default(),
+            TlsOptions::default(),
+            CwdMatch::Prefix,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(found.cwd, PathBuf::from("/srv/app"));
+    }
+
+    #[test]
+    fn test_unique_ports_keeps_first_pid() {
+        let processes = vec![
+            (9, "opencode --port 4096".to_string()),
+            (8, "node /usr/lib/opencode --port=4096".to_string()),
+            (7, "opencode".to_string()),
+            (6, "opencode --port 8080".to_string()),
+        ];
+        assert_eq!(unique_ports(&processes), vec![(9, 4096), (6, 8080)]);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_processes_probe_port_once() {
+        // A server elsewhere isn't kept, so the old skip didn't stop re-probing it
+        let server = MockServer::with_body(r#"{"directory":"/work/beta"}"#);
+        let processes = vec![
+            (2, format!("opencode --port {}", server.port())),
+            (1, format!("node opencode --port {}", server.port())),
+        ];
+
+        let cwd = Some(Path::new("/work/alpha"));
+        let (found, _) = matching_servers(cwd, processes, TlsOptions::default(), false).await;
+        assert!(found.is_empty());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_matching_servers_collects_all() {
+        let alpha = MockServer::with_body(r#"{"directory":"/work/alpha"}"#);
+        let nested = MockServer::with_body(r#"{"directory":"/work/alpha/sub"}"#);
+        let beta = MockServer::with_body(r#"{"directory":"/work/beta"}"#);
+        let processes = vec![
+            (3, format!("opencode --port {}", alpha.port())),
+            (2, format!("opencode --port {}", beta.port())),
+            (1, format!("opencode --port={}", nested.port())),
+            // A wrapper process for the same server
+            (4, format!("node opencode --port {}", alpha.port())),
+        ];
+
+        let cwd = Some(Path::new("/work/alpha"));
+        let (all, _) = matching_servers(cwd, processes.clone(), TlsOptions::default(), false).await;
+        let found: Vec<(u32, u16)> = all.iter().map(|s| (s.pid, s.port)).collect();
+        assert_eq!(found, vec![(3, alpha.port()), (1, nested.port())]);
+
+        let (first, _) =
+            matching_servers(cwd, processes.clone(), TlsOptions::default(), true).await;
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].port, alpha.port());
+
+        // Without a cwd (list-servers) every answering server is kept
+        let (any, _) = matching_servers(None, processes, TlsOptions::default(), false).await;
+        let found: Vec<(u32, u16)> = any.iter().map(|s| (s.pid, s.port)).collect();
+        assert_eq!(
+            found,
+            vec![(3, alpha.port()), (2, beta.port()), (1, nested.port())]
+        );
+    }
+
+    fn server_in(cwd: &str, port: u16) -> Server {
+        Server {
+            pid: 0,
+            host: "localhost".to_string(),
+            port,
+            tls: TlsOptions::default(),
+            cwd: PathBuf::from(cwd),
+            socket: None,
+        }
+    }
+
+    #[test]
+    fn test_deepest_match() {
+        let servers = || {
+            vec![
+                server_in("/work", 1),
+                server_in("/work/app/sub", 2),
+                server_in("/work/app", 3),
+                server_in("/work/app", 4),
+                server_in("/elsewhere", 5),
+            ]
+        };
+        let port = |cwd: &str| deepest_match(Path::new(cwd), servers()).map(|s| s.port);
+
+        assert_eq!(port("/work/app"), Some(3));
+        assert_eq!(port("/work/app/sub/deeper"), Some(2));
+        assert_eq!(port("/work/app/sub"), Some(2));
+        // A server below our cwd doesn't contain it, so the root one wins
+        assert_eq!(port("/work/other"), Some(1));
+        assert_eq!(port("/nowhere"), None);
+    }
+
+    #[test]
+    fn test_cwd_match_modes() {
+        let (cwd, parent) = (Path::new("/work/app"), Path::new("/work"));
+        assert!(CwdMatch::Prefix.matches(cwd, parent));
+        assert!(!CwdMatch::Exact.matches(cwd, parent));
+        assert!(CwdMatch::Exact.matches(cwd, Path::new("/work/app")));
+    }
+
+    #[test]
+    fn test_mdns_fallback_only_for_prefix_match() {
+        let remote = || vec![scanned_server("/srv/remote")];
+        assert_eq!(
+            mdns_fallback(remote(), CwdMatch::Prefix).map(|s| s.cwd),
+            Some(PathBuf::from("/srv/remote"))
+        );
+        assert!(mdns_fallback(remote(), CwdMatch::Exact).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_exact_cwd_skips_parent_instance() {
+        let parent = MockServer::with_body(r#"{"directory":"/work"}"#);
+        let ours = MockServer::with_body(r#"{"directory":"/work/app"}"#);
+        let instances = [parent.port(), ours.port()].map(|port| Instance {
+            port,
+            cwd: None,
+            pid: None,
+        });
+        let cwd = Path::new("/work/app");
+
+        let found =
+            discover_from_instances(cwd, &instances, TlsOptions::default(), CwdMatch::Prefix)
+                .await
+                .unwrap();
+        assert_eq!(found.port, parent.port());
+        let found =
+            discover_from_instances(cwd, &instances, TlsOptions::default(), CwdMatch::Exact)
+                .await
+                .unwrap();
+        assert_eq!(found.port, ours.port());
+    }
+
+    #[tokio::test]
+    async fn test_instances_pick_matching_cwd() {
+        let other = MockServer::with_body(r#"{"directory":"/work/beta"}"#);
+        let ours = MockServer::with_body(r#"{"directory":"/work/alpha"}"#);
+        let instances = [
+            Instance {
+                port: other.port(),
+                cwd: None,
+                pid: None,
+            },
+            Instance {
+                port: 1,
+                cwd: Some(PathBuf::from("/work/gamma")),
+                pid: None,
+            },
+            Instance {
+                port: ours.port(),
+                cwd: Some(PathBuf::from("/work/alpha")),
+                pid: Some(77),
+            },
+        ];
+
+        let found = discover_from_instances(
+            Path::new("/work/alpha"),
+            &instances,
+            TlsOptions::default(),
+            CwdMatch::Prefix,
+        )
+        .await
+        .unwrap();
+        assert_eq!(found.port, ours.port());
+        assert_eq!(found.pid, 77);
+        // Checked, but serves another project
+        assert_eq!(other.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_instances_none_match() {
+        let found = discover_from_instances(
+            Path::new("/work/alpha"),
+            &[],
+            TlsOptions::default(),
+            CwdMatch::Prefix,
+        )
+        .await;
+        assert!(found.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_no_cached_port_scans() {
+        let found = discover_with_cached_port(
+            Path::new("/work/alpha"),
+            None,
+            TlsOptions::default(),
+            CwdMatch::Prefix,
+            scanned(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(found.pid, 42);
+    }
 }
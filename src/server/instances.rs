@@ -0,0 +1,136 @@
+//! Instance-file discovery for OpenCode servers
+//!
+//! Reads running-instance info (port, cwd) that OpenCode may leave as JSON files
+//! in a well-known directory, so a server can be found without scanning every
+//! process on the machine.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// A running OpenCode instance as described by an instance file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Instance {
+    /// HTTP server port
+    pub port: u16,
+    /// Working directory of the instance, if recorded
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// Process ID, if recorded
+    #[serde(default)]
+    pub pid: Option<u32>,
+}
+
+/// Directories searched for instance files when `--instance-dir` isn't given:
+/// `$XDG_RUNTIME_DIR/opencode`, then `<config dir>/opencode`
+pub fn default_dirs() -> Vec<PathBuf> {
+    [dirs::runtime_dir(), dirs::config_dir()]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("opencode"))
+        .collect()
+}
+
+/// Parse one instance file; a file may hold one instance or an array of them
+pub fn parse_instances(data: &str) -> Option<Vec<Instance>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum InstanceFile {
+        One(Instance),
+        Many(Vec<Instance>),
+    }
+
+    match serde_json::from_str(data).ok()? {
+        InstanceFile::One(instance) => Some(vec![instance]),
+        InstanceFile::Many(instances) => Some(instances),
+    }
+}
+
+/// Load every instance from the `*.json` files in `dir`, in file name order.
+///
+/// Missing directories yield nothing; unreadable or corrupt files are skipped
+/// (and logged under `-v`), since a stale file mustn't break discovery.
+pub fn load_instances(dir: &Path) -> Vec<Instance> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+
+    let mut instances = Vec::new();
+    for file in files {
+        let parsed = std::fs::read_to_string(&file)
+            .ok()
+            .and_then(|data| parse_instances(&data));
+        match parsed {
+            Some(mut found) => instances.append(&mut found),
+            None => log::info!("Skipping unreadable instance file {}", file.display()),
+        }
+    }
+    instances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_instance() {
+        let instances =
+            parse_instances(r#"{"port": 4096, "cwd": "/work/alpha", "pid": 123}"#).unwrap();
+        assert_eq!(
+            instances,
+            vec![Instance {
+                port: 4096,
+                cwd: Some(PathBuf::from("/work/alpha")),
+                pid: Some(123),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_multiple_instances() {
+        let instances =
+            parse_instances(r#"[{"port": 4096, "cwd": "/work/alpha"}, {"port": 8080}]"#).unwrap();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[1].port, 8080);
+        assert_eq!(instances[1].cwd, None);
+    }
+
+    #[test]
+    fn test_parse_corrupt_instance() {
+        assert_eq!(parse_instances("{\"port\": 40"), None);
+        assert_eq!(parse_instances(r#"{"cwd": "/work/alpha"}"#), None);
+        assert_eq!(parse_instances(r#"{"port": 99999}"#), None);
+    }
+
+    #[test]
+    fn test_load_instances_skips_corrupt_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("a.json"),
+            r#"{"port": 4096, "cwd": "/work/alpha"}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("b.json"), "not json").unwrap();
+        std::fs::write(
+            dir.path().join("c.json"),
+            r#"[{"port": 5000}, {"port": 5001, "cwd": "/work/beta"}]"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("notes.txt"), r#"{"port": 1}"#).unwrap();
+
+        let ports: Vec<u16> = load_instances(dir.path()).iter().map(|i| i.port).collect();
+        assert_eq!(ports, vec![4096, 5000, 5001]);
+    }
+
+    #[test]
+    fn test_load_instances_missing_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_instances(&dir.path().join("missing")).is_empty());
+    }
+}
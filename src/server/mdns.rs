@@ -0,0 +1,148 @@
+//! mDNS discovery for OpenCode servers on the local network
+//!
+//! Browses for OpenCode instances advertised via mDNS/Bonjour and turns the
+//! resolved records into host/port candidates for validation.
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+
+/// Service type OpenCode instances are expected to advertise
+pub const SERVICE_TYPE: &str = "_opencode._tcp.local.";
+
+/// A resolved mDNS service record
+#[derive(Debug, Clone)]
+pub struct MdnsRecord {
+    /// Advertised hostname (e.g. `devbox.local.`)
+    pub hostname: String,
+    /// Addresses the hostname resolved to
+    pub addresses: Vec<IpAddr>,
+    /// Advertised service port
+    pub port: u16,
+}
+
+/// A host/port pair to validate as an OpenCode server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Browse the network for OpenCode services until `timeout` elapses
+pub fn browse(timeout: Duration) -> Result<Vec<MdnsRecord>> {
+    let daemon = ServiceDaemon::new().context("Failed to start mDNS daemon")?;
+    let receiver = daemon
+        .browse(SERVICE_TYPE)
+        .context("Failed to browse for OpenCode services")?;
+
+    let deadline = Instant::now() + timeout;
+    let mut records = Vec::new();
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(remaining) {
+            Ok(ServiceEvent::ServiceResolved(info)) => records.push(MdnsRecord {
+                hostname: info.get_hostname().to_string(),
+                addresses: info.get_addresses().iter().copied().collect(),
+                port: info.get_port(),
+            }),
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+
+    let _ = daemon.shutdown();
+    Ok(records)
+}
+
+/// Turn resolved records into unique host/port candidates.
+///
+/// IPv4 addresses are preferred over IPv6, and the hostname is used when a
+/// record carries no addresses. Records advertising port 0 are skipped.
+pub fn candidates_from_records(records: &[MdnsRecord]) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = Vec::new();
+
+    for record in records {
+        if record.port == 0 {
+            continue;
+        }
+
+        let host = record
+            .addresses
+            .iter()
+            .find(|a| a.is_ipv4())
+            .or_else(|| record.addresses.first())
+            .map(|addr| match addr {
+                IpAddr::V4(v4) => v4.to_string(),
+                IpAddr::V6(v6) => format!("[{}]", v6),
+            })
+            .unwrap_or_else(|| record.hostname.trim_end_matches('.').to_string());
+
+        if host.is_empty() {
+            continue;
+        }
+
+        let candidate = Candidate {
+            host,
+            port: record.port,
+        };
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    }
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(hostname: &str, addresses: &[&str], port: u16) -> MdnsRecord {
+        MdnsRecord {
+            hostname: hostname.to_string(),
+            addresses: addresses.iter().map(|a| a.parse().unwrap()).collect(),
+            port,
+        }
+    }
+
+    #[test]
+    fn test_candidates_prefer_ipv4() {
+        let records = vec![record("devbox.local.", &["fe80::1", "192.168.1.20"], 4096)];
+        let candidates = candidates_from_records(&records);
+        assert_eq!(
+            candidates,
+            vec![Candidate {
+                host: "192.168.1.20".to_string(),
+                port: 4096
+            }]
+        );
+    }
+
+    #[test]
+    fn test_candidates_ipv6_bracketed() {
+        let records = vec![record("devbox.local.", &["fe80::1"], 4096)];
+        let candidates = candidates_from_records(&records);
+        assert_eq!(candidates[0].host, "[fe80::1]");
+    }
+
+    #[test]
+    fn test_candidates_fall_back_to_hostname() {
+        let records = vec![record("devbox.local.", &[], 8080)];
+        let candidates = candidates_from_records(&records);
+        assert_eq!(candidates[0].host, "devbox.local");
+        assert_eq!(candidates[0].port, 8080);
+    }
+
+    #[test]
+    fn test_candidates_skip_zero_port_and_dedupe() {
+        let records = vec![
+            record("a.local.", &["10.0.0.1"], 0),
+            record("b.local.", &["10.0.0.2"], 4096),
+            record("b.local.", &["10.0.0.2"], 4096),
+        ];
+        let candidates = candidates_from_records(&records);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].host, "10.0.0.2");
+    }
+}
@@ -2,6 +2,14 @@
 
 pub mod client;
 pub mod discovery;
+pub mod instances;
+pub mod mdns;
+#[cfg(test)]
+pub mod mock;
 
-pub use client::Client;
-pub use discovery::{discover_server, Server};
+pub use client::{Client, RequestError, SendMode, TlsOptions};
+pub use discovery::{
+    default_port_files, discover_all_servers, discover_from_instances, discover_from_port_files,
+    discover_mdns_server, discover_server, discover_socket_server, discover_with_cached_port,
+    is_local_host, list_servers, ping_server, CwdMatch, ScanLimits, Server,
+};
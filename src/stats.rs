@@ -0,0 +1,119 @@
+//! Local usage statistics for `--stats`
+//!
+//! Counts sends, placeholder usage and prompt length in a JSON file under the
+//! OS config dir. Nothing here ever touches the network.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// File name of the stats store inside the app config dir
+pub const STATS_FILE: &str = "stats.json";
+
+/// Default stats location: `<config dir>/prompt-dialog/stats.json`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("prompt-dialog").join(STATS_FILE))
+}
+
+/// Accumulated usage statistics
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Number of successful sends
+    pub sends: u64,
+    /// Sum of expanded prompt lengths in characters
+    pub total_chars: u64,
+    /// How often each `@placeholder` appeared in a sent prompt
+    pub placeholders: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    /// Load stats from `path`; a missing file yields empty stats
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read stats file {}", path.display()))?;
+        serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse stats file {}", path.display()))
+    }
+
+    /// Write stats to `path`, creating parent directories as needed
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let data = serde_json::to_string_pretty(self).context("Failed to serialize stats")?;
+        std::fs::write(path, data)
+            .with_context(|| format!("Failed to write stats file {}", path.display()))
+    }
+
+    /// Count one send of `text` (pre-expansion) that expanded to `expanded`
+    pub fn add_send(&mut self, text: &str, expanded: &str) {
+        self.sends += 1;
+        self.total_chars += expanded.chars().count() as u64;
+        for r in crate::template::scan_placeholders(text) {
+            *self.placeholders.entry(r.name).or_default() += 1;
+        }
+    }
+
+    /// Average expanded prompt length in characters (0 before the first send)
+    pub fn average_length(&self) -> f64 {
+        if self.sends == 0 {
+            0.0
+        } else {
+            self.total_chars as f64 / self.sends as f64
+        }
+    }
+}
+
+/// Add one send to the stats file at `path`
+pub fn record(path: &Path, text: &str, expanded: &str) -> Result<()> {
+    let mut stats = Stats::load(path)?;
+    stats.add_send(text, expanded);
+    stats.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_send_accumulates() {
+        let mut stats = Stats::default();
+        stats.add_send("Fix @path", "Fix src/a.rs");
+        stats.add_send("Explain @path with @clipboard", "Explain b.rs with xyz");
+
+        assert_eq!(stats.sends, 2);
+        assert_eq!(stats.total_chars, 12 + 21);
+        assert_eq!(stats.placeholders["path"], 2);
+        assert_eq!(stats.placeholders["clipboard"], 1);
+        assert_eq!(stats.average_length(), 16.5);
+    }
+
+    #[test]
+    fn test_average_length_empty() {
+        assert_eq!(Stats::default().average_length(), 0.0);
+    }
+
+    #[test]
+    fn test_record_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(STATS_FILE);
+
+        record(&path, "Fix @path", "Fix a.rs").unwrap();
+        record(&path, "Hi", "Hi").unwrap();
+
+        let stats = Stats::load(&path).unwrap();
+        assert_eq!(stats.sends, 2);
+        assert_eq!(stats.total_chars, 10);
+        assert_eq!(stats.placeholders.len(), 1);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["placeholders"]["path"], 1);
+    }
+}
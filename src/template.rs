@@ -0,0 +1,266 @@
+//! Prompt template files and static placeholder checks
+//!
+//! A template is prompt text with an optional front-matter block declaring the
+//! params it expects:
+//!
+//! ```text
+//! ---
+//! params: path, selection
+//! ---
+//! Review @path:
+//! @selection
+//! ```
+//!
+//! A `--template-dir` of such files feeds the dialog's template picker.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+/// Front-matter delimiter line
+const FRONT_MATTER_FENCE: &str = "---";
+
+/// A parsed template file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    /// Param names declared in the front-matter
+    pub declared: Vec<String>,
+    /// Template text after the front-matter
+    pub body: String,
+    /// Line number (1-based) of the first body line in the original file
+    pub body_line: usize,
+}
+
+/// A template file offered by the dialog's picker
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedTemplate {
+    /// File name without its extension
+    pub name: String,
+    /// Template text after the front-matter, placeholders intact
+    pub body: String,
+}
+
+/// Extensions of the files `load_dir` picks up
+const TEMPLATE_EXTENSIONS: [&str; 2] = ["txt", "md"];
+
+/// A `@name` token found in template text
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaceholderRef {
+    pub name: String,
+    /// 1-based line number
+    pub line: usize,
+}
+
+/// Parse a template, splitting off its front-matter block if present
+pub fn parse(text: &str) -> Result<Template> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim_end) != Some(FRONT_MATTER_FENCE) {
+        return Ok(Template {
+            declared: Vec::new(),
+            body: text.to_string(),
+            body_line: 1,
+        });
+    }
+
+    let mut declared = Vec::new();
+    for (i, line) in lines.by_ref().enumerate() {
+        let line = line.trim();
+        if line == FRONT_MATTER_FENCE {
+            // Fence lines plus the `i` front-matter lines precede the body
+            let body_line = i + 3;
+            let body = text
+                .lines()
+                .skip(body_line - 1)
+                .collect::<Vec<_>>()
+                .join("\n");
+            return Ok(Template {
+                declared,
+                body,
+                body_line,
+            });
+        }
+        if let Some(list) = line.strip_prefix("params:") {
+            declared.extend(
+                list.split(',')
+                    .map(|name| name.trim().trim_start_matches('@').to_string())
+                    .filter(|name| !name.is_empty()),
+            );
+        }
+    }
+
+    Err(anyhow!("Unterminated front-matter (missing closing `---`)"))
+}
+
+/// Load every `.txt` / `.md` template in `dir`, sorted by name
+pub fn load_dir(dir: &Path) -> Result<Vec<NamedTemplate>> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read template dir {}", dir.display()))?;
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| TEMPLATE_EXTENSIONS.contains(&ext))
+        })
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read template {}", path.display()))?;
+            let template =
+                parse(&text).with_context(|| format!("Invalid template {}", path.display()))?;
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            Ok(NamedTemplate {
+                name,
+                body: template.body,
+            })
+        })
+        .collect()
+}
+
+/// Find every `@name` token in `text`.
+///
+/// A token starts with `@` at the beginning of the text or after a character
+/// that can't be part of a word (so `user@example.com` isn't a token). Names
+/// are ASCII letters, digits, `_` and `-`; a `:argument` suffix is not part of
+/// the name.
+pub fn scan_placeholders(text: &str) -> Vec<PlaceholderRef> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    let mut refs = Vec::new();
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let mut prev: Option<char> = None;
+        for (i, c) in line.char_indices() {
+            if c == '@' && !prev.is_some_and(|p| p.is_alphanumeric() || p == '_') {
+                let name: String = line[i + 1..]
+                    .chars()
+                    .take_while(|&c| is_name_char(c))
+                    .collect();
+                let name = name.trim_end_matches('-');
+                if !name.is_empty() {
+                    refs.push(PlaceholderRef {
+                        name: name.to_string(),
+                        line: line_idx + 1,
+                    });
+                }
+            }
+            prev = Some(c);
+        }
+    }
+
+    refs
+}
+
+/// References in the template body that are neither declared nor built in
+pub fn undeclared(template: &Template, builtins: &[&str]) -> Vec<PlaceholderRef> {
+    scan_placeholders(&template.body)
+        .into_iter()
+        .filter(|r| !builtins.contains(&r.name.as_str()) && !template.declared.contains(&r.name))
+        .map(|r| PlaceholderRef {
+            line: r.line + template.body_line - 1,
+            ..r
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILTINS: &[&str] = &["clipboard"];
+
+    #[test]
+    fn test_parse_front_matter() {
+        let template = parse("---\nparams: path, @selection\n---\nReview @path").unwrap();
+        assert_eq!(template.declared, vec!["path", "selection"]);
+        assert_eq!(template.body, "Review @path");
+        assert_eq!(template.body_line, 4);
+    }
+
+    #[test]
+    fn test_parse_without_front_matter() {
+        let template = parse("Just @text").unwrap();
+        assert!(template.declared.is_empty());
+        assert_eq!(template.body, "Just @text");
+    }
+
+    #[test]
+    fn test_parse_unterminated_front_matter() {
+        assert!(parse("---\nparams: path\nReview @path").is_err());
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("refactor.md"), "Refactor @path").unwrap();
+        std::fs::write(
+            dir.path().join("bugfix.txt"),
+            "---\nparams: path\n---\nFix @path\n@clipboard",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("notes.json"), "{}").unwrap();
+        std::fs::create_dir(dir.path().join("drafts.md")).unwrap();
+
+        let templates = load_dir(dir.path()).unwrap();
+        assert_eq!(
+            templates,
+            vec![
+                NamedTemplate {
+                    name: "bugfix".to_string(),
+                    body: "Fix @path\n@clipboard".to_string(),
+                },
+                NamedTemplate {
+                    name: "refactor".to_string(),
+                    body: "Refactor @path".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_dir_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_dir(&dir.path().join("missing")).is_err());
+
+        std::fs::write(dir.path().join("broken.txt"), "---\nparams: path").unwrap();
+        let err = load_dir(dir.path()).unwrap_err();
+        assert!(format!("{:#}", err).contains("broken.txt"));
+    }
+
+    #[test]
+    fn test_scan_placeholders() {
+        let refs =
+            scan_placeholders("Fix @path\nmail user@example.com\n@clipboard:path (@git-root)");
+        let names: Vec<(&str, usize)> = refs.iter().map(|r| (r.name.as_str(), r.line)).collect();
+        assert_eq!(names, vec![("path", 1), ("clipboard", 3), ("git-root", 3)]);
+    }
+
+    #[test]
+    fn test_clean_template() {
+        let template =
+            parse("---\nparams: path, selection\n---\nReview @path:\n@selection\n@clipboard")
+                .unwrap();
+        assert!(undeclared(&template, BUILTINS).is_empty());
+    }
+
+    #[test]
+    fn test_undeclared_placeholder() {
+        let template = parse("---\nparams: path\n---\nReview @path\nwith @selection").unwrap();
+        let missing = undeclared(&template, BUILTINS);
+        assert_eq!(
+            missing,
+            vec![PlaceholderRef {
+                name: "selection".to_string(),
+                line: 5
+            }]
+        );
+    }
+}
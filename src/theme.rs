@@ -0,0 +1,92 @@
+//! Light/dark color schemes for the dialog (`--theme`)
+//!
+//! `system` follows the OS appearance via dark-light and falls back to dark
+//! when the platform doesn't report one.
+
+/// `--theme`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+    /// Follow the OS appearance
+    #[default]
+    System,
+}
+
+impl Theme {
+    /// The concrete scheme to draw: `system` becomes light or dark according
+    /// to `system_dark` (`None` when detection failed, which means dark)
+    pub fn resolve(self, system_dark: Option<bool>) -> Theme {
+        match (self, system_dark) {
+            (Theme::System, Some(false)) => Theme::Light,
+            (Theme::System, _) => Theme::Dark,
+            (theme, _) => theme,
+        }
+    }
+
+    /// Colors for this scheme; `system` must be resolved first and gets dark
+    pub fn colors(self) -> Colors {
+        match self {
+            Theme::Light => Colors {
+                background: slint::Color::from_argb_u8(0xee, 0xf8, 0xfa, 0xfc),
+                foreground: slint::Color::from_rgb_u8(0x1e, 0x29, 0x3b),
+                // Darker violet/blue than the dark scheme's, to stay readable on white
+                accent: slint::Color::from_rgb_u8(0x6d, 0x28, 0xd9),
+                argument: slint::Color::from_rgb_u8(0x03, 0x69, 0xa1),
+            },
+            Theme::Dark | Theme::System => Colors {
+                background: slint::Color::from_argb_u8(0xcc, 0x1e, 0x1e, 0x2e),
+                foreground: slint::Color::from_rgb_u8(0xe2, 0xe8, 0xf0),
+                accent: slint::Color::from_rgb_u8(0xa7, 0x8b, 0xfa),
+                argument: slint::Color::from_rgb_u8(0x38, 0xbd, 0xf8),
+            },
+        }
+    }
+}
+
+/// Dialog colors handed to the Slint `theme-*` properties
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colors {
+    /// Dialog body fill
+    pub background: slint::Color,
+    /// Typed text
+    pub foreground: slint::Color,
+    /// Placeholder highlight and autocomplete text
+    pub accent: slint::Color,
+    /// Placeholder arguments (the path of `@file:path`)
+    pub argument: slint::Color,
+}
+
+/// Whether the OS is in dark mode; `None` if it doesn't say
+pub fn system_is_dark() -> Option<bool> {
+    match dark_light::detect() {
+        dark_light::Mode::Dark => Some(true),
+        dark_light::Mode::Light => Some(false),
+        dark_light::Mode::Default => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(Theme::System.resolve(Some(false)), Theme::Light);
+        assert_eq!(Theme::System.resolve(Some(true)), Theme::Dark);
+        // Detection failed: dark
+        assert_eq!(Theme::System.resolve(None), Theme::Dark);
+        // Explicit choices ignore the OS
+        assert_eq!(Theme::Light.resolve(Some(true)), Theme::Light);
+        assert_eq!(Theme::Dark.resolve(Some(false)), Theme::Dark);
+    }
+
+    #[test]
+    fn test_colors_differ_per_scheme() {
+        let (light, dark) = (Theme::Light.colors(), Theme::Dark.colors());
+        assert_ne!(light.background, dark.background);
+        assert_ne!(light.accent, dark.accent);
+        assert_eq!(Theme::System.colors(), dark);
+    }
+}
@@ -0,0 +1,197 @@
+//! Undo/redo history for the prompt input
+//!
+//! Keeps a bounded list of input snapshots. Typing is grouped: edits that
+//! follow each other within `GROUP_PAUSE` amend the same step, so a burst of
+//! keystrokes undoes at once. Programmatic changes (autocomplete, history
+//! recall, Ctrl+E) are checkpoints, always a step of their own.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Undo steps kept unless `--undo-depth` says otherwise
+pub const DEFAULT_DEPTH: usize = 50;
+
+/// A pause in typing at least this long starts a new undo step
+pub const GROUP_PAUSE: Duration = Duration::from_millis(700);
+
+/// The input text and caret (byte offset) at one point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub text: String,
+    pub cursor: usize,
+}
+
+/// Snapshots of the input, oldest first, with the one on screen at `index`
+#[derive(Debug, Clone)]
+pub struct UndoStack {
+    entries: VecDeque<Snapshot>,
+    index: usize,
+    depth: usize,
+    /// When the current step was last amended by typing; `None` once the step
+    /// is closed (after a checkpoint, undo or redo)
+    last_edit: Option<Instant>,
+}
+
+impl UndoStack {
+    /// Start from an empty input, keeping at most `depth` undo steps
+    pub fn new(depth: usize) -> Self {
+        Self {
+            entries: VecDeque::from([Snapshot {
+                text: String::new(),
+                cursor: 0,
+            }]),
+            index: 0,
+            depth,
+            last_edit: None,
+        }
+    }
+
+    /// Record typing at `now`. Joins the current step if the previous edit
+    /// was less than `GROUP_PAUSE` ago; an unchanged text is ignored (e.g.
+    /// the text-changed that follows a restore).
+    pub fn record(&mut self, text: &str, cursor: usize, now: Instant) {
+        if self.entries[self.index].text == text {
+            return;
+        }
+        let grouped = self
+            .last_edit
+            .is_some_and(|last| now.saturating_duration_since(last) < GROUP_PAUSE);
+        let snapshot = Snapshot {
+            text: text.to_string(),
+            cursor,
+        };
+        if grouped {
+            self.entries.truncate(self.index + 1);
+            self.entries[self.index] = snapshot;
+        } else {
+            self.push(snapshot);
+        }
+        self.last_edit = Some(now);
+    }
+
+    /// Record a programmatic change as a step of its own; typing after it
+    /// starts a new step
+    pub fn checkpoint(&mut self, text: &str, cursor: usize) {
+        if self.entries[self.index].text != text {
+            self.push(Snapshot {
+                text: text.to_string(),
+                cursor,
+            });
+        }
+        self.last_edit = None;
+    }
+
+    /// Replace the current step, e.g. when another Tab swaps the completion
+    /// the previous one inserted
+    pub fn amend(&mut self, text: &str, cursor: usize) {
+        self.entries.truncate(self.index + 1);
+        self.entries[self.index] = Snapshot {
+            text: text.to_string(),
+            cursor,
+        };
+        self.last_edit = None;
+    }
+
+    /// Step back, returning the snapshot to restore
+    pub fn undo(&mut self) -> Option<&Snapshot> {
+        self.index = self.index.checked_sub(1)?;
+        self.last_edit = None;
+        Some(&self.entries[self.index])
+    }
+
+    /// Step forward again after an undo, returning the snapshot to restore
+    pub fn redo(&mut self) -> Option<&Snapshot> {
+        if self.index + 1 >= self.entries.len() {
+            return None;
+        }
+        self.index += 1;
+        self.last_edit = None;
+        Some(&self.entries[self.index])
+    }
+
+    /// Add a step after the current one, dropping any redo steps and the
+    /// oldest step beyond `depth`
+    fn push(&mut self, snapshot: Snapshot) {
+        self.entries.truncate(self.index + 1);
+        self.entries.push_back(snapshot);
+        if self.entries.len() > self.depth + 1 {
+            self.entries.pop_front();
+        }
+        self.index = self.entries.len() - 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(snapshot: Option<&Snapshot>) -> Option<&str> {
+        snapshot.map(|s| s.text.as_str())
+    }
+
+    #[test]
+    fn test_typing_groups_until_a_pause() {
+        let start = Instant::now();
+        let mut stack = UndoStack::new(DEFAULT_DEPTH);
+        stack.record("h", 1, start);
+        stack.record("he", 2, start + Duration::from_millis(100));
+        stack.record("hey", 3, start + Duration::from_millis(200));
+        stack.record("hey you", 7, start + Duration::from_secs(2));
+
+        assert_eq!(text(stack.undo()), Some("hey"));
+        assert_eq!(stack.undo().map(|s| s.cursor), Some(0));
+        assert_eq!(text(stack.undo()), None);
+        assert_eq!(text(stack.redo()), Some("hey"));
+        assert_eq!(text(stack.redo()), Some("hey you"));
+        assert_eq!(text(stack.redo()), None);
+    }
+
+    #[test]
+    fn test_checkpoint_is_its_own_step() {
+        let start = Instant::now();
+        let mut stack = UndoStack::new(DEFAULT_DEPTH);
+        stack.record("@cl", 3, start);
+        stack.checkpoint("@clipboard", 10);
+        // Typing right after the completion doesn't merge into it
+        stack.record("@clipboard ok", 13, start + Duration::from_millis(100));
+
+        assert_eq!(text(stack.undo()), Some("@clipboard"));
+        assert_eq!(text(stack.undo()), Some("@cl"));
+    }
+
+    #[test]
+    fn test_amend_replaces_the_step() {
+        let mut stack = UndoStack::new(DEFAULT_DEPTH);
+        stack.checkpoint("@clipboard", 10);
+        stack.amend("@clipboard-image", 16);
+        assert_eq!(text(stack.undo()), Some(""));
+        assert_eq!(text(stack.redo()), Some("@clipboard-image"));
+    }
+
+    #[test]
+    fn test_edit_after_undo_drops_redo() {
+        let start = Instant::now();
+        let mut stack = UndoStack::new(DEFAULT_DEPTH);
+        stack.record("one", 3, start);
+        stack.checkpoint("one two", 7);
+        stack.undo();
+        // The restored text coming back through text-changed is not an edit
+        stack.record("one", 3, start + Duration::from_secs(5));
+        assert_eq!(text(stack.redo()), Some("one two"));
+        stack.undo();
+        stack.record("one three", 9, start + Duration::from_secs(6));
+        assert_eq!(text(stack.redo()), None);
+        assert_eq!(text(stack.undo()), Some("one"));
+    }
+
+    #[test]
+    fn test_depth_is_bounded() {
+        let mut stack = UndoStack::new(2);
+        for step in ["a", "ab", "abc", "abcd"] {
+            stack.checkpoint(step, step.len());
+        }
+        assert_eq!(text(stack.undo()), Some("abc"));
+        assert_eq!(text(stack.undo()), Some("ab"));
+        assert_eq!(text(stack.undo()), None);
+    }
+}
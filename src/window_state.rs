@@ -0,0 +1,158 @@
+//! Last dialog position and size, restored on the next launch
+//!
+//! Stored as JSON under the OS config dir. A saved position is only reused
+//! while the window would still fit on one of the connected monitors.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// File name of the window state inside the app config dir
+pub const WINDOW_STATE_FILE: &str = "window.json";
+
+/// Default state location: `<config dir>/prompt-dialog/window.json`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("prompt-dialog").join(WINDOW_STATE_FILE))
+}
+
+/// Window frame in physical pixels, as last seen when the dialog closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowState {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowState {
+    /// Load the state from `path`; a missing file yields `None`
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read window state {}", path.display()))?;
+        let state = serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse window state {}", path.display()))?;
+        Ok(Some(state))
+    }
+
+    /// Write the state to `path`, creating parent directories as needed
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let data =
+            serde_json::to_string_pretty(self).context("Failed to serialize window state")?;
+        std::fs::write(path, data)
+            .with_context(|| format!("Failed to write window state {}", path.display()))
+    }
+}
+
+/// A monitor's area in physical pixels (desktop coordinates)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MonitorRect {
+    /// Whether the whole saved window frame lies on this monitor
+    fn contains(&self, state: &WindowState) -> bool {
+        let (left, top) = (i64::from(self.x), i64::from(self.y));
+        let right = left + i64::from(self.width);
+        let bottom = top + i64::from(self.height);
+        let (x, y) = (i64::from(state.x), i64::from(state.y));
+        x >= left
+            && y >= top
+            && x + i64::from(state.width) <= right
+            && y + i64::from(state.height) <= bottom
+    }
+}
+
+/// The saved position, if the window still fits on one of `monitors`
+///
+/// A frame left on a monitor that has since been disconnected (or straddling
+/// two monitors) yields `None`, so the caller falls back to centering.
+pub fn restore_position(state: &WindowState, monitors: &[MonitorRect]) -> Option<(i32, i32)> {
+    monitors
+        .iter()
+        .any(|monitor| monitor.contains(state))
+        .then_some((state.x, state.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: MonitorRect = MonitorRect {
+        x: 0,
+        y: 0,
+        width: 1920,
+        height: 1080,
+    };
+    const SECONDARY: MonitorRect = MonitorRect {
+        x: 1920,
+        y: -200,
+        width: 2560,
+        height: 1440,
+    };
+
+    fn frame(x: i32, y: i32) -> WindowState {
+        WindowState {
+            x,
+            y,
+            width: 680,
+            height: 240,
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(WINDOW_STATE_FILE);
+        assert_eq!(WindowState::load(&path).unwrap(), None);
+
+        frame(2100, -50).save(&path).unwrap();
+        assert_eq!(WindowState::load(&path).unwrap(), Some(frame(2100, -50)));
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(WINDOW_STATE_FILE);
+        std::fs::write(&path, "not json").unwrap();
+        assert!(WindowState::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_restore_on_connected_monitor() {
+        let monitors = [PRIMARY, SECONDARY];
+        assert_eq!(
+            restore_position(&frame(100, 100), &monitors),
+            Some((100, 100))
+        );
+        assert_eq!(
+            restore_position(&frame(2100, -50), &monitors),
+            Some((2100, -50))
+        );
+    }
+
+    #[test]
+    fn test_restore_off_screen() {
+        // Saved on the secondary monitor, which is now unplugged
+        assert_eq!(restore_position(&frame(2100, -50), &[PRIMARY]), None);
+        // Hanging off the bottom edge
+        assert_eq!(restore_position(&frame(100, 900), &[PRIMARY]), None);
+        // Straddling both monitors
+        assert_eq!(
+            restore_position(&frame(1500, 100), &[PRIMARY, SECONDARY]),
+            None
+        );
+        assert_eq!(restore_position(&frame(100, 100), &[]), None);
+    }
+}
@@ -0,0 +1,68 @@
+//! `prompt-dialog expand` run as a process: stdout and exit code
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Run the binary with `args` in `dir`, isolated from the user's config
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_prompt-dialog"))
+        .args(args)
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join(".config"))
+        .env_remove("RUST_LOG")
+        .output()
+        .expect("Failed to run prompt-dialog")
+}
+
+#[test]
+fn test_expand_prints_expanded_prompt() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(
+        dir.path(),
+        &[
+            "expand",
+            "Fix @path@cursor now",
+            "--param",
+            "path=src/main.rs",
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Fix src/main.rs now\n"
+    );
+}
+
+#[test]
+fn test_expand_resolves_builtins_against_cwd() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("notes.md"), "remember").unwrap();
+    let output = run(dir.path(), &["expand", "Notes: @file:notes.md"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Notes: remember\n");
+}
+
+#[test]
+fn test_expand_strict_fails_on_unknown_tokens() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(dir.path(), &["expand", "Fix @slection"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Fix @slection\n");
+
+    let output = run(dir.path(), &["expand", "Fix @slection", "--strict"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("@slection"));
+}
+
+#[test]
+fn test_print_expanded_flag_is_the_same_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(
+        dir.path(),
+        &["--strict", "--print-expanded", "Fix @slection"],
+    );
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}